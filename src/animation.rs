@@ -0,0 +1,209 @@
+use super::camera::Camera;
+use super::math::matrix::Trs;
+use super::scene::Scene;
+use super::shape;
+use super::Vec3;
+
+// Values that can be interpolated between keyframes.
+pub trait Lerp: Copy {
+    fn lerp(a: &Self, b: &Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(a: &f64, b: &f64, t: f64) -> f64 {
+        a * (1.0 - t) + b * t
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(a: &Vec3, b: &Vec3, t: f64) -> Vec3 {
+        *a * (1.0 - t) + *b * t
+    }
+}
+
+impl Lerp for Trs {
+    fn lerp(a: &Trs, b: &Trs, t: f64) -> Trs {
+        Trs::lerp(a, b, t)
+    }
+}
+
+/*
+    Keyframes sorted on time, time is in frames. Values are interpolated linearly and held
+    before the first and after the last keyframe.
+*/
+#[derive(Clone)]
+pub struct Track<T: Lerp> {
+    keys: Vec<(f64, T)>,
+}
+
+impl<T: Lerp> Track<T> {
+    pub fn new(mut keys: Vec<(f64, T)>) -> Track<T> {
+        assert!(!keys.is_empty(), "Track needs at least one keyframe");
+        keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Track { keys }
+    }
+
+    pub fn constant(value: T) -> Track<T> {
+        Track::new(vec![(0.0, value)])
+    }
+
+    pub fn sample(&self, time: f64) -> T {
+        let first = &self.keys[0];
+        if time <= first.0 {
+            return first.1;
+        }
+
+        for pair in self.keys.windows(2) {
+            let (t0, v0) = &pair[0];
+            let (t1, v1) = &pair[1];
+            if time <= *t1 {
+                return T::lerp(v0, v1, (time - t0) / (t1 - t0));
+            }
+        }
+
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+#[derive(Clone)]
+pub struct CameraAnimation {
+    pub position: Track<Vec3>,
+    pub look_at: Track<Vec3>,
+    pub fov: Track<f64>,
+    pub focus_distance: Track<f64>,
+    pub up: Vec3,
+    pub aperture: f64,
+    pub ratio: f64,
+}
+
+/*
+    Animation of a scene. Object tracks refer to the index of the object in Scene::objects and
+    are applied on top of the object and its motion (like a parent transform).
+*/
+#[derive(Clone)]
+pub struct Animation {
+    pub camera: Option<CameraAnimation>,
+    pub objects: Vec<(usize, Track<Trs>)>,
+}
+
+impl Animation {
+    /*
+        Sets the scene to the state of the frame. The camera shutter (between 0 and 1) is used
+        as a fraction of the frame, so objects get motion from this frame to the next one.
+    */
+    pub fn apply(&self, scene: &mut Scene, frame: f64) {
+        if let Some(camera) = &self.camera {
+            let (open, close) = (scene.camera.shutter_open, scene.camera.shutter_close);
+            let lens = scene.camera.clone();
+            scene.camera = Camera::set(
+                camera.position.sample(frame),
+                camera.look_at.sample(frame),
+                camera.up,
+                camera.fov.sample(frame),
+                camera.ratio,
+                camera.aperture,
+                camera.focus_distance.sample(frame),
+            )
+            .with_shutter(open, close)
+            .with_lens_of(&lens);
+        }
+
+        for (index, track) in self.objects.iter() {
+            let object = scene.objects[*index].clone();
+            scene.objects[*index] =
+                shape::with_parent_motion(object, track.sample(frame), track.sample(frame + 1.0));
+        }
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::material::{self, MaterialType};
+    use crate::ray::Ray;
+    use crate::shape::{Motion, ObjectType};
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_track_sample() {
+        let track = Track::new(vec![(10.0, 1.0), (0.0, 0.0), (20.0, 5.0)]);
+
+        assert_approx_eq!(track.sample(-5.0), 0.0, ASSERT_MARGIN);
+        assert_approx_eq!(track.sample(5.0), 0.5, ASSERT_MARGIN);
+        assert_approx_eq!(track.sample(10.0), 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(track.sample(15.0), 3.0, ASSERT_MARGIN);
+        assert_approx_eq!(track.sample(25.0), 5.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_object_track_keeps_motion() {
+        // A sphere at the origin that is placed and spun by its motion, like an instance.
+        let placed = Trs {
+            translation: Vec3(1.5, 1.0, 0.0),
+            ..Trs::identity()
+        };
+        let sphere = shape::with_motion(
+            shape::new(
+                Vec3::zero(),
+                ObjectType::Sphere { radius: 0.5 },
+                &material::new(Vec3::fill(0.5), MaterialType::Lambertian),
+            ),
+            Motion::Transform {
+                start: placed,
+                end: Trs {
+                    rotation: Vec3(0.0, 0.0, 60.0),
+                    ..placed
+                },
+            },
+        );
+        let mut scene = Scene {
+            objects: vec![sphere],
+            lights: vec![],
+            camera: Camera::set(
+                Vec3::zero(),
+                Vec3(0.0, 0.0, -1.0),
+                Vec3::up(),
+                30.0,
+                1.0,
+                0.0,
+                1.0,
+            ),
+            bvh: Bvh::default(),
+            animation: None,
+        };
+        let jump = Trs {
+            translation: Vec3(0.0, 2.0, 0.0),
+            ..Trs::identity()
+        };
+        let animation = Animation {
+            camera: None,
+            objects: vec![(0, Track::new(vec![(0.0, Trs::identity()), (2.0, jump)]))],
+        };
+
+        // Height of the center of the sphere, found with a ray straight down through it.
+        let height = |scene: &Scene, time: f64| {
+            let mut ray = Ray::new(Vec3(1.5, 10.0, 0.0), -Vec3::up());
+            ray.time = time;
+            shape::intersect(&scene.objects[0], &mut ray, 0.0001);
+            assert!(ray.is_intersected.is_some());
+            10.0 - ray.travel_distance - 0.5
+        };
+        let mut frame = scene.clone();
+        animation.apply(&mut frame, 0.0);
+        assert_approx_eq!(height(&frame, 0.0), 1.0, ASSERT_MARGIN);
+        // Halfway through the frame the track moved up a quarter of the jump.
+        assert_approx_eq!(height(&frame, 0.5), 1.5, ASSERT_MARGIN);
+
+        animation.apply(&mut scene, 2.0);
+        assert_approx_eq!(height(&scene, 0.0), 3.0, ASSERT_MARGIN);
+    }
+}
@@ -0,0 +1,142 @@
+use super::color::{self, ColorPipeline};
+use super::cryptomatte::Manifest;
+use super::math::vector::Vec3;
+use super::output::{self, Layer, LayerKind};
+use super::sampler;
+
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
+pub enum AovOutput {
+    // One multi-layer EXR with the image and all passes as 32 bit floats.
+    Exr,
+    // A png per pass next to the image, light passes go through the color pipeline.
+    Png,
+}
+
+/*
+    Render passes (AOVs) of one camera sample. The data passes come from the first hit. The
+    color of the sample is split into direct light, which reaches the camera after a single
+    bounce from a light or the sky (or is the sky seen directly), and indirect light. Lights has
+    the direct light of every scene light followed by the sky.
+*/
+#[derive(Debug, Clone)]
+pub struct AovSample {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    // Distance from the camera, None when nothing was hit.
+    pub depth: Option<f64>,
+    // Index of the scene object and id of the material.
+    pub object: Option<usize>,
+    pub material: Option<usize>,
+    // Names for the ID mattes.
+    pub object_name: Option<Arc<str>>,
+    pub material_name: Option<Arc<str>>,
+    pub direct: Vec3,
+    pub indirect: Vec3,
+    pub lights: Vec<Vec3>,
+}
+
+impl AovSample {
+    // Sample that did not hit anything, for a scene with the number of lights.
+    pub fn new(lights: usize) -> AovSample {
+        AovSample {
+            albedo: Vec3::zero(),
+            normal: Vec3::zero(),
+            depth: None,
+            object: None,
+            material: None,
+            object_name: None,
+            material_name: None,
+            direct: Vec3::zero(),
+            indirect: Vec3::zero(),
+            lights: vec![Vec3::zero(); lights + 1],
+        }
+    }
+}
+
+/*
+    Writes the layers of an image, path is the file name without extension. EXR writes all
+    layers to path.exr with the manifests of the ID mattes, png writes a path_<layer>.png for
+    every pass but the image itself and the mattes, which are only useful in a compositor.
+*/
+pub fn write_layers(
+    path: &str,
+    format: AovOutput,
+    width: usize,
+    height: usize,
+    layers: &[Layer],
+    manifests: &[Manifest],
+    pipeline: &ColorPipeline,
+) -> Result<(), std::io::Error> {
+    match format {
+        AovOutput::Exr => {
+            let path = format!("{}.exr", path);
+            let metadata: Vec<(String, String)> =
+                manifests.iter().flat_map(Manifest::metadata).collect();
+            output::write_exr(Path::new(&path), width, height, layers, &metadata)
+        }
+        AovOutput::Png => {
+            let passes = layers
+                .iter()
+                .filter(|layer| !layer.name.is_empty() && layer.kind != LayerKind::Matte);
+            for layer in passes {
+                let path = format!("{}_{}.png", path, layer.name);
+                let image = display(layer, pipeline);
+                output::write_png(Path::new(&path), width, height, pipeline.bit_depth, &image)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Viewable version of a layer, in the bit depth of the pipeline.
+fn display(layer: &Layer, pipeline: &ColorPipeline) -> Vec<u8> {
+    let channels = layer.kind.channels().len();
+    let pixels = layer.values.chunks(channels).map(|v| match channels {
+        1 => Vec3::fill(v[0] as f64),
+        _ => Vec3(v[0] as f64, v[1] as f64, v[2] as f64),
+    });
+
+    // Depth is shown relative to the median depth (gray), white is far away or nothing hit.
+    let median = match layer.kind {
+        LayerKind::Depth => median(&layer.values),
+        _ => 1.0,
+    };
+
+    let mut image = Vec::with_capacity(layer.values.len() / channels * 3);
+    for pixel in pixels {
+        let value = match layer.kind {
+            LayerKind::Radiance => pipeline.display(pixel),
+            LayerKind::Color => Vec3(
+                color::linear_to_srgb(pixel.0.clamp(0.0, 1.0)),
+                color::linear_to_srgb(pixel.1.clamp(0.0, 1.0)),
+                color::linear_to_srgb(pixel.2.clamp(0.0, 1.0)),
+            ),
+            LayerKind::Vector => pixel * 0.5 + Vec3::fill(0.5),
+            LayerKind::Depth if pixel.0.is_finite() => pixel / (pixel.0 + median),
+            LayerKind::Depth => Vec3::fill(1.0),
+            // Random color for every id, black where nothing was hit.
+            LayerKind::Id | LayerKind::Matte if pixel.0 == 0.0 => Vec3::zero(),
+            LayerKind::Id => id_color(pixel.0 as u64),
+            // Color of the first id, mattes store the ids as float bits.
+            LayerKind::Matte => id_color((pixel.0 as f32).to_bits() as u64),
+        };
+        color::quantize(value, pipeline.bit_depth, &mut image);
+    }
+    image
+}
+
+fn id_color(id: u64) -> Vec3 {
+    let bits = sampler::hash(id);
+    let channel = |shift: u64| ((bits >> shift) & 0xff) as f64 / 255.0;
+    Vec3(channel(0), channel(8), channel(16))
+}
+
+// Median of the finite values, 1 when there are none.
+fn median(values: &[f32]) -> f64 {
+    let mut finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    finite.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    finite.get(finite.len() / 2).map_or(1.0, |&v| v as f64)
+}
@@ -0,0 +1,305 @@
+use crate::math::matrix::Mat4;
+use crate::math::vector::Vec3;
+use crate::ray::Ray;
+
+use std::f64;
+
+// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(a: Vec3, b: Vec3) -> Aabb {
+        Aabb {
+            min: Vec3(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: Vec3(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::fill(f64::INFINITY),
+            max: Vec3::fill(f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3(
+                a.min.0.min(b.min.0),
+                a.min.1.min(b.min.1),
+                a.min.2.min(b.min.2),
+            ),
+            max: Vec3(
+                a.max.0.max(b.max.0),
+                a.max.1.max(b.max.1),
+                a.max.2.max(b.max.2),
+            ),
+        }
+    }
+
+    pub fn grow(&self, p: Vec3) -> Aabb {
+        Aabb::union(self, &Aabb { min: p, max: p })
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3(a.0, a.1, a.2),
+            Vec3(b.0, a.1, a.2),
+            Vec3(a.0, b.1, a.2),
+            Vec3(b.0, b.1, a.2),
+            Vec3(a.0, a.1, b.2),
+            Vec3(b.0, a.1, b.2),
+            Vec3(a.0, b.1, b.2),
+            Vec3(b.0, b.1, b.2),
+        ]
+    }
+
+    // Bounds of the transformed box.
+    pub fn transform(&self, matrix: &Mat4) -> Aabb {
+        self.corners()
+            .iter()
+            .fold(Aabb::empty(), |b, &p| b.grow(matrix.transform_point(p)))
+    }
+
+    // Slab test, returns true when the ray overlaps with the box between t_min and t_max.
+    pub fn hit(&self, origin: Vec3, inv_direction: Vec3, t_min: f64, t_max: f64) -> bool {
+        self.clip(origin, inv_direction, t_min, t_max).is_some()
+    }
+
+    // Part of the range between t_min and t_max where the ray is inside of the box.
+    pub fn clip(
+        &self,
+        origin: Vec3,
+        inv_direction: Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        let axes = [
+            (self.min.0, self.max.0, origin.0, inv_direction.0),
+            (self.min.1, self.max.1, origin.1, inv_direction.1),
+            (self.min.2, self.max.2, origin.2, inv_direction.2),
+        ];
+        for &(min, max, o, inv) in axes.iter() {
+            let mut t0 = (min - o) * inv;
+            let mut t1 = (max - o) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (0 * inf) is ignored by min/max.
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    bounds: Aabb,
+    // Leaf: range in indices. Interior: count is 0 and first is the index of the right child,
+    // the left child is always the next node.
+    first: usize,
+    count: usize,
+}
+
+const MAX_LEAF_SIZE: usize = 4;
+
+/*
+    Bounding volume hierarchy over a list of primitives. The BVH only stores indices, so the
+    same structure is used for objects, triangles or particles. Primitives without bounds
+    (planes) are kept apart and tested for every ray.
+*/
+#[derive(Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Option<Aabb>]) -> Bvh {
+        let mut bvh = Bvh::default();
+
+        let mut bounded = Vec::with_capacity(bounds.len());
+        for (i, b) in bounds.iter().enumerate() {
+            match b {
+                Some(b) => bounded.push((i, *b, b.centroid())),
+                None => bvh.unbounded.push(i),
+            }
+        }
+
+        if !bounded.is_empty() {
+            bvh.build_range(&mut bounded, 0);
+        }
+        bvh.indices = bounded.iter().map(|(i, _, _)| *i).collect();
+        bvh
+    }
+
+    // Builds the node for the items, offset is the position of the items in the final list.
+    fn build_range(&mut self, items: &mut [(usize, Aabb, Vec3)], offset: usize) -> usize {
+        let bounds = items
+            .iter()
+            .fold(Aabb::empty(), |b, (_, item, _)| Aabb::union(&b, item));
+        let node_index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            first: offset,
+            count: items.len(),
+        });
+
+        if items.len() <= MAX_LEAF_SIZE {
+            return node_index;
+        }
+
+        // Median split along the largest axis of the centroids.
+        let centroids = items
+            .iter()
+            .fold(Aabb::empty(), |b, (_, _, c)| b.grow(*c))
+            .extent();
+        let axis = if centroids.0 > centroids.1 && centroids.0 > centroids.2 {
+            0
+        } else if centroids.1 > centroids.2 {
+            1
+        } else {
+            2
+        };
+        let key = |c: &Vec3| match axis {
+            0 => c.0,
+            1 => c.1,
+            _ => c.2,
+        };
+
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| {
+            key(&a.2)
+                .partial_cmp(&key(&b.2))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build_range(left, offset);
+        let right_index = self.build_range(right, offset + mid);
+
+        self.nodes[node_index].first = right_index;
+        self.nodes[node_index].count = 0;
+        node_index
+    }
+
+    // Bounds of all primitives, None when there is an unbounded primitive.
+    pub fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        Some(self.nodes.first().map_or(Aabb::empty(), |n| n.bounds))
+    }
+
+    /*
+        Calls `intersect` for every primitive the ray might hit. The primitives are expected to
+        shorten `ray.travel_distance` when they are hit, which is used to skip nodes further away.
+    */
+    pub fn traverse<F>(&self, ray: &mut Ray, tolerance: f64, mut intersect: F)
+    where
+        F: FnMut(usize, &mut Ray),
+    {
+        for &i in self.unbounded.iter() {
+            intersect(i, ray);
+        }
+
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_direction = Vec3(
+            1.0 / ray.direction.0,
+            1.0 / ray.direction.1,
+            1.0 / ray.direction.2,
+        );
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node
+                .bounds
+                .hit(ray.origin, inv_direction, tolerance, ray.travel_distance)
+            {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(index + 1);
+            } else {
+                for &i in self.indices[node.first..node.first + node.count].iter() {
+                    intersect(i, ray);
+                }
+            }
+        }
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_hit() {
+        let b = Aabb::new(Vec3::fill(-1.0), Vec3::fill(1.0));
+        let inv = |d: Vec3| Vec3(1.0 / d.0, 1.0 / d.1, 1.0 / d.2);
+
+        let origin = Vec3(0.0, 0.0, -5.0);
+        assert!(b.hit(origin, inv(Vec3(0.0, 0.0, 1.0)), 0.0, f64::MAX));
+        assert!(!b.hit(origin, inv(Vec3(0.0, 0.0, -1.0)), 0.0, f64::MAX));
+        assert!(!b.hit(origin, inv(Vec3(0.0, 1.0, 0.0)), 0.0, f64::MAX));
+        // Box is further away than the current closest hit.
+        assert!(!b.hit(origin, inv(Vec3(0.0, 0.0, 1.0)), 0.0, 3.0));
+    }
+
+    #[test]
+    fn test_bvh_visits_all_hit_primitives() {
+        // Row of unit boxes along x, the ray goes through all of them.
+        let bounds: Vec<Option<Aabb>> = (0..50)
+            .map(|i| {
+                let c = Vec3(i as f64 * 3.0, 0.0, 0.0);
+                Some(Aabb::new(c - Vec3::fill(0.5), c + Vec3::fill(0.5)))
+            })
+            .chain(std::iter::once(None))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+
+        let mut ray = Ray::new(Vec3(-5.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let mut visited = vec![];
+        bvh.traverse(&mut ray, 0.001, |i, _| visited.push(i));
+        visited.sort();
+        assert_eq!(visited, (0..51).collect::<Vec<usize>>());
+
+        // Ray above the boxes only tests the unbounded primitive.
+        let mut ray = Ray::new(Vec3(-5.0, 2.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        let mut visited = vec![];
+        bvh.traverse(&mut ray, 0.001, |i, _| visited.push(i));
+        assert_eq!(visited, vec![50]);
+    }
+}
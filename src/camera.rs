@@ -1,6 +1,169 @@
+use super::math::vector::{Vec2, Vector};
 use super::ray::Ray;
+use super::sampler::Sampler;
+use super::texture::Texture;
 use super::Vec3;
 use std::f64;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/*
+    Shape of the aperture, which is the shape of out of focus highlights (bokeh). Samples are
+    positions on the lens within the unit circle, scaled by the lens radius.
+*/
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    // Regular polygon with the corners on the unit circle, rotation in degrees.
+    Polygon { blades: usize, rotation: f64 },
+    // Custom image, bright pixels let light through.
+    Mask(Arc<ApertureMask>),
+}
+
+// Image of the aperture stored as a cumulative distribution over the pixels.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    // Uses the brightness of the texture, the image is fit in the unit circle.
+    pub fn new(texture: &Texture) -> ApertureMask {
+        let (width, height) = texture.size();
+        let mut total = 0.0;
+        let cdf = (0..width * height)
+            .map(|i| {
+                let c = texture.pixel(i % width, i / width);
+                total += (c.0 + c.1 + c.2) / 3.0;
+                total
+            })
+            .collect();
+        assert!(total > 0.0, "Aperture mask is completely black");
+        ApertureMask { width, height, cdf }
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Vec2 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = u.0 * total;
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= target)
+            .min(self.cdf.len() - 1);
+
+        // Position of the target within the pixel, the longest side of the image spans -1 to 1.
+        let start = if index > 0 { self.cdf[index - 1] } else { 0.0 };
+        let within = ((target - start) / (self.cdf[index] - start)).clamp(0.0, 1.0);
+        let (x, y) = (index % self.width, index / self.width);
+        let size = self.width.max(self.height) as f64;
+        let px = (x as f64 + within - self.width as f64 / 2.0) / size * 2.0;
+        let py = (self.height as f64 / 2.0 - y as f64 - u.1) / size * 2.0;
+        // The corners of a square image are outside of the unit circle.
+        Vec2(px, py) * f64::consts::FRAC_1_SQRT_2
+    }
+}
+
+impl Aperture {
+    fn sample(&self, u: (f64, f64)) -> Vec2 {
+        match self {
+            Aperture::Circle => {
+                let (r, angle) = (u.0.sqrt(), u.1 * 2.0 * PI);
+                Vec2(r * angle.cos(), r * angle.sin())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // Uniform point in one of the equal triangles between the center and two corners,
+                // the first number picks the triangle and is reused within it.
+                let blades = (*blades).max(3);
+                let corner = |i: usize| {
+                    let angle = rotation.to_radians() + 2.0 * PI * i as f64 / blades as f64;
+                    Vec2(angle.cos(), angle.sin())
+                };
+                let scaled = u.0 * blades as f64;
+                let i = (scaled as usize).min(blades - 1);
+                let r = (scaled - i as f64).sqrt();
+                corner(i) * (r * (1.0 - u.1)) + corner(i + 1) * (r * u.1)
+            }
+            Aperture::Mask(mask) => mask.sample(u),
+        }
+    }
+}
+
+/*
+    Lens described with physical values. The sensor width and focal length set the field of
+    view, the height of the sensor follows from the ratio of the image. Lengths are in mm,
+    the scene is expected in meters.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Lens {
+    pub focal_length: f64,
+    pub sensor_width: f64,
+    pub f_stop: f64,
+}
+
+impl Lens {
+    // Full frame (36mm) sensor.
+    pub fn full_frame(focal_length: f64, f_stop: f64) -> Lens {
+        Lens {
+            focal_length,
+            sensor_width: 36.0,
+            f_stop,
+        }
+    }
+
+    // Vertical field of view in degrees for the ratio of the image.
+    pub fn fov(&self, ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    // Diameter of the aperture in meters.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_stop / 1000.0
+    }
+}
+
+/*
+    How positions on the image map to rays. Only the perspective projection uses the lens
+    (aperture, focus, tilt and shift), the others are pinhole cameras.
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    Perspective,
+    // Parallel rays over a view of this height, the width follows from the ratio of the image.
+    Orthographic { height: f64 },
+    // Full 360 by 180 degree panorama with the view direction in the center.
+    Equirectangular,
+    /*
+        Six 90 degree faces in a 3 by 2 grid: +x, -x, +y on top and -y, +z, -z below, oriented
+        like OpenGL cube maps. Axes are in camera space, so the view direction is -z.
+    */
+    CubeMap,
+    // Circle fitting the height of the image, the field of view in degrees can exceed 180.
+    Fisheye { fov: f64, mapping: FisheyeMapping },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FisheyeMapping {
+    // Distance from the center is proportional to the angle.
+    Equidistant,
+    // Keeps the area, like most real fisheye lenses.
+    Equisolid,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+// Rotation of v around a unit axis (Rodrigues).
+fn rotate(v: Vec3, axis: Vec3, degrees: f64) -> Vec3 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    v * cos + Vec3::cross(axis, v) * sin + axis * Vec3::dot(axis, v) * (1.0 - cos)
+}
 
 #[derive(Debug, Clone)]
 pub struct Camera {
@@ -14,7 +177,28 @@ pub struct Camera {
     pub u: Vec3,
     pub v: Vec3,
 
+    pub projection: Projection,
+
     pub lens_radius: f64,
+    pub aperture: Aperture,
+    // Amount the aperture is cut off towards the corners of the image (0 to 1), cat's eye bokeh.
+    pub cat_eye: f64,
+
+    // Tilt of the plane in focus (degrees around the horizontal and vertical axis) and shift of
+    // the image (fraction of the image size), as with a tilt-shift lens.
+    pub tilt: Vec2,
+    pub shift: Vec2,
+    focus_point: Vec3,
+    focus_normal: Vec3,
+
+    // Offset of the eye along u for stereo (negative for the left eye) and the distance at which
+    // both eyes converge, infinite for parallel eyes.
+    pub eye_offset: f64,
+    pub convergence: f64,
+
+    // Rays get a time between shutter open and close, equal values disable motion blur.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
 }
 
 impl Camera {
@@ -49,22 +233,383 @@ impl Camera {
             u,
             v,
             lower_top_corner: lt_corner,
+            projection: Projection::Perspective,
             lens_radius: aperture / 2.0,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            tilt: Vec2(0.0, 0.0),
+            shift: Vec2(0.0, 0.0),
+            focus_point: position - to * focus_dist,
+            focus_normal: to,
+            eye_offset: 0.0,
+            convergence: f64::INFINITY,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
-    pub fn generate_ray(&self, x: f64, y: f64) -> Ray {
-        let rd = Vec3::rand_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.0 + self.v * rd.1;
+    // Camera from physical lens values, focused at the focus distance (in meters).
+    pub fn physical(
+        position: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        lens: &Lens,
+        ratio: f64,
+        focus_dist: f64,
+    ) -> Camera {
+        Camera::set(
+            position,
+            look_at,
+            up,
+            lens.fov(ratio),
+            ratio,
+            lens.aperture(),
+            focus_dist,
+        )
+    }
 
+    // Object motion is defined between time 0 and 1, so the shutter is expected in that range.
+    pub fn with_shutter(self, open: f64, close: f64) -> Camera {
+        Camera {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
+        }
+    }
+
+    // The field of view given to set is only used by the perspective projection.
+    pub fn with_projection(self, projection: Projection) -> Camera {
+        Camera { projection, ..self }
+    }
+
+    pub fn with_aperture(self, aperture: Aperture, cat_eye: f64) -> Camera {
+        Camera {
+            aperture,
+            cat_eye,
+            ..self
+        }
+    }
+
+    /*
+        Tilts the plane in focus (degrees, x around the horizontal axis and y around the
+        vertical axis) and shifts the image (fraction of the width and height, y up).
+    */
+    pub fn with_tilt_shift(self, tilt: Vec2, shift: Vec2) -> Camera {
+        let normal = rotate(self.direction, self.u, tilt.0);
+        let normal = rotate(normal, self.v, tilt.1);
+        Camera {
+            tilt,
+            shift,
+            focus_normal: normal,
+            ..self
+        }
+    }
+
+    /*
+        One eye of a stereo pair, half the interocular distance (in meters) to the side. The
+        perspective image is shifted so objects at the convergence distance line up in both eyes,
+        panoramic projections become omnidirectional stereo (ODS). Orthographic cameras have no
+        parallax and ignore the eye.
+    */
+    pub fn with_eye(self, eye: Eye, interocular: f64, convergence: f64) -> Camera {
+        let side = match eye {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        };
+        Camera {
+            eye_offset: side * interocular / 2.0,
+            convergence,
+            ..self
+        }
+    }
+
+    // Takes the projection, eye and lens settings of the other camera, for rebuilt cameras.
+    pub fn with_lens_of(self, other: &Camera) -> Camera {
+        Camera {
+            projection: other.projection,
+            eye_offset: other.eye_offset,
+            convergence: other.convergence,
+            aperture: other.aperture.clone(),
+            cat_eye: other.cat_eye,
+            ..self
+        }
+        .with_tilt_shift(other.tilt, other.shift)
+    }
+
+    /*
+        Ray through the pixel, None when the sample is not part of the image: blocked by the
+        lens (cat's eye vignetting) or outside of the fisheye circle. These samples are black.
+    */
+    pub fn generate_ray(&self, x: f64, y: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
         let fx = x / super::SCREEN_WIDTH as f64;
         let fy = y / super::SCREEN_HEIGHT as f64;
-        Ray::new(
-            self.position + offset,
-            self.lower_top_corner + (self.horizonal * fx)
-                - (self.vertical * fy)
-                - self.position
-                - offset,
-        )
+        let mut ray = match self.projection {
+            Projection::Perspective => self.thin_lens_ray(fx, fy, sampler)?,
+            Projection::Orthographic { height } => {
+                let width = height * self.ratio();
+                let offset = self.u * (fx + self.shift.0 - 0.5) * width
+                    + self.v * (0.5 - fy + self.shift.1) * height;
+                Ray::new(self.position + offset, -self.direction)
+            }
+            Projection::Equirectangular => {
+                let longitude = (fx - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - fy) * PI;
+                let d = Vec3(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                self.panorama_ray(d)
+            }
+            Projection::CubeMap => {
+                let column = (fx * 3.0).floor().clamp(0.0, 2.0);
+                let row = (fy * 2.0).floor().clamp(0.0, 1.0);
+                // Position on the face from -1 to 1, t goes down.
+                let s = (fx * 3.0 - column) * 2.0 - 1.0;
+                let t = (fy * 2.0 - row) * 2.0 - 1.0;
+                let d = match (row * 3.0 + column) as usize {
+                    0 => Vec3(1.0, -t, -s),
+                    1 => Vec3(-1.0, -t, s),
+                    2 => Vec3(s, 1.0, t),
+                    3 => Vec3(s, -1.0, -t),
+                    4 => Vec3(s, -t, 1.0),
+                    _ => Vec3(-s, -t, -1.0),
+                };
+                self.panorama_ray(d)
+            }
+            Projection::Fisheye { fov, mapping } => {
+                let (px, py) = ((2.0 * fx - 1.0) * self.ratio(), 1.0 - 2.0 * fy);
+                let r = (px * px + py * py).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half = (fov / 2.0).to_radians();
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half / 2.0).sin()).asin(),
+                };
+                let (sin, cos) = theta.sin_cos();
+                let d = if r > 0.0 {
+                    Vec3(sin * px / r, sin * py / r, -cos)
+                } else {
+                    Vec3(0.0, 0.0, -1.0)
+                };
+                self.panorama_ray(d)
+            }
+        };
+
+        if self.shutter_close > self.shutter_open {
+            let u = sampler.get_1d();
+            ray.time = self.shutter_open * (1.0 - u) + self.shutter_close * u;
+        }
+
+        Some(ray)
+    }
+
+    fn ratio(&self) -> f64 {
+        self.horizonal.length() / self.vertical.length()
+    }
+
+    // Camera space (x right, y up, looking down -z) to world space.
+    fn to_world(&self, d: Vec3) -> Vec3 {
+        self.u * d.0 + self.v * d.1 + self.direction * d.2
+    }
+
+    /*
+        Ray from the camera in a camera space direction. For stereo the origin moves sideways,
+        perpendicular to the horizontal part of the direction. The offset shrinks towards the
+        poles, so the eyes do not swap when looking straight up or down.
+    */
+    fn panorama_ray(&self, d: Vec3) -> Ray {
+        let direction = self.to_world(Vec3::normalize(d));
+        if self.eye_offset == 0.0 {
+            return Ray::new(self.position, direction);
+        }
+
+        let side = Vec3::cross(direction, self.v) * self.eye_offset;
+        let origin = self.position + side;
+        if self.convergence.is_finite() {
+            // Aim at the point at the convergence distance seen from the center.
+            let target = self.position + direction * self.convergence;
+            Ray::new(origin, Vec3::normalize(target - origin))
+        } else {
+            Ray::new(origin, direction)
+        }
+    }
+
+    fn thin_lens_ray(&self, fx: f64, fy: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let fx = fx + self.shift.0;
+        let fy = fy - self.shift.1;
+        let target = self.lower_top_corner + (self.horizonal * fx) - (self.vertical * fy);
+
+        // For stereo the image moves less than the eye (off-axis), so the center of the image
+        // meets the center line of the camera at the convergence distance.
+        let eye = self.position + self.u * self.eye_offset;
+        let focus_dist = Vec3::dot(self.position - self.focus_point, self.direction);
+        let target = target + self.u * self.eye_offset * (1.0 - focus_dist / self.convergence);
+
+        // Point on the plane in focus seen through the center of the lens.
+        let through = target - eye;
+        let denom = Vec3::dot(through, self.focus_normal);
+        let focus = if denom.abs() > 1e-12 {
+            let t = Vec3::dot(self.focus_point - eye, self.focus_normal) / denom;
+            if t > 0.0 {
+                eye + through * t
+            } else {
+                target
+            }
+        } else {
+            target
+        };
+
+        let sample = self.aperture.sample(sampler.get_2d());
+        if self.cat_eye > 0.0 {
+            // The aperture is seen through the lens barrel, a circle that moves away from the
+            // center of the aperture towards the corners of the image.
+            let ratio = self.ratio();
+            let image =
+                Vec2((2.0 * fx - 1.0) * ratio, 1.0 - 2.0 * fy) / (ratio * ratio + 1.0).sqrt();
+            let barrel = image * -self.cat_eye;
+            if (sample - barrel).length() > 1.0 {
+                return None;
+            }
+        }
+
+        let offset = (self.u * sample.0 + self.v * sample.1) * self.lens_radius;
+        Some(Ray::new(eye + offset, focus - eye - offset))
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::sampler::{self, SamplerType};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_lens() {
+        // 50mm on full frame has a field of view of about 39.6 degrees over the width.
+        let lens = Lens::full_frame(50.0, 2.0);
+        assert_approx_eq!(lens.fov(1.0), 39.597752709, ASSERT_MARGIN);
+        assert_approx_eq!(lens.aperture(), 0.025, ASSERT_MARGIN);
+        // A wider image has a smaller vertical field of view.
+        assert!(lens.fov(1.5) < lens.fov(1.0));
+    }
+
+    #[test]
+    fn test_aperture_polygon() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let aperture = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        // A hexagon with a corner on the x axis has its flat sides at distance cos(30).
+        let inner = (PI / 6.0).cos();
+        for _ in 0..1000 {
+            let p = aperture.sample(rng.gen());
+            assert!(p.length() <= 1.0 + ASSERT_MARGIN);
+            assert!(p.1.abs() <= inner + ASSERT_MARGIN);
+        }
+    }
+
+    #[test]
+    fn test_projections() {
+        let (w, h) = (crate::SCREEN_WIDTH as f64, crate::SCREEN_HEIGHT as f64);
+        let camera = Camera::set(
+            Vec3::zero(),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3::up(),
+            40.0,
+            w / h,
+            0.0,
+            1.0,
+        );
+        let direction = |projection: Projection, x: f64, y: f64| {
+            let ray = camera.clone().with_projection(projection).generate_ray(
+                x,
+                y,
+                &mut *sampler::new(SamplerType::Independent, 1, 0),
+            )?;
+            Some(Vec3::normalize(ray.direction))
+        };
+        let forward = Vec3(0.0, 0.0, -1.0);
+
+        // Center of the panorama and of the -z cube face look forward.
+        let center = direction(Projection::Equirectangular, w / 2.0, h / 2.0).unwrap();
+        assert_approx_eq!(Vec3::dot(center, forward), 1.0, ASSERT_MARGIN);
+        let front = direction(Projection::CubeMap, w * 5.0 / 6.0, h * 3.0 / 4.0).unwrap();
+        assert_approx_eq!(Vec3::dot(front, forward), 1.0, ASSERT_MARGIN);
+        let right = direction(Projection::CubeMap, w / 6.0, h / 4.0).unwrap();
+        assert_approx_eq!(right.0, 1.0, ASSERT_MARGIN);
+
+        // The edge of a 180 degree fisheye looks sideways, the corners are outside the circle.
+        let fisheye = Projection::Fisheye {
+            fov: 180.0,
+            mapping: FisheyeMapping::Equidistant,
+        };
+        let top = direction(fisheye, w / 2.0, 0.0).unwrap();
+        assert_approx_eq!(top.1, 1.0, ASSERT_MARGIN);
+        assert!(direction(fisheye, 0.0, 0.0).is_none());
+
+        let orthographic = Projection::Orthographic { height: 2.0 };
+        let ray = camera
+            .clone()
+            .with_projection(orthographic)
+            .generate_ray(
+                w / 2.0,
+                0.0,
+                &mut *sampler::new(SamplerType::Independent, 1, 0),
+            )
+            .unwrap();
+        assert_approx_eq!(ray.origin.1, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(Vec3::dot(ray.direction, forward), 1.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_stereo() {
+        let (w, h) = (crate::SCREEN_WIDTH as f64, crate::SCREEN_HEIGHT as f64);
+        let camera = Camera::set(
+            Vec3::zero(),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3::up(),
+            40.0,
+            w / h,
+            0.0,
+            2.0,
+        );
+        let converge = Vec3(0.0, 0.0, -5.0);
+
+        // Both eyes see the point at the convergence distance in the center of the image.
+        for &(eye, offset) in [(Eye::Left, -0.032), (Eye::Right, 0.032)].iter() {
+            for &projection in [Projection::Perspective, Projection::Equirectangular].iter() {
+                let ray = camera
+                    .clone()
+                    .with_projection(projection)
+                    .with_eye(eye, 0.064, 5.0)
+                    .generate_ray(
+                        w / 2.0,
+                        h / 2.0,
+                        &mut *sampler::new(SamplerType::Independent, 1, 0),
+                    )
+                    .unwrap();
+                assert_approx_eq!(ray.origin.0, offset, ASSERT_MARGIN);
+                let expected = Vec3::normalize(converge - ray.origin);
+                assert_approx_eq!(
+                    Vec3::dot(Vec3::normalize(ray.direction), expected),
+                    1.0,
+                    ASSERT_MARGIN
+                );
+            }
+        }
     }
 }
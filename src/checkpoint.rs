@@ -0,0 +1,118 @@
+use super::film::Film;
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/*
+    Checkpoints of a render in progress, so an interrupted render can be resumed. Pixel jobs
+    are merged into the film in scan order, a checkpoint has the sums of the film and the
+    number of jobs merged so far. The fingerprint of the scene and the render settings is stored
+    with it, a checkpoint of another scene or other settings is not resumed.
+*/
+
+// Seconds between checkpoints.
+pub const INTERVAL: f32 = 60.0;
+
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+// Writes the checkpoint next to the path first, an interrupted write keeps the last checkpoint.
+pub fn save(path: &Path, fingerprint: u64, jobs_done: usize, film: &Film) -> Result<(), Error> {
+    let (width, height) = film.size();
+    let mut data = MAGIC.to_vec();
+    for &v in [fingerprint, width as u64, height as u64, jobs_done as u64].iter() {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    film.write_sums(&mut data);
+
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, data)?;
+    std::fs::rename(&temporary, path)
+}
+
+/*
+    Reads the checkpoint into the film and returns the number of jobs it has, None when there
+    is no checkpoint. The film has to be empty and of the size of the checkpoint.
+*/
+pub fn load(path: &Path, fingerprint: u64, film: &mut Film) -> Result<Option<usize>, Error> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let invalid = |message: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    };
+
+    let header = MAGIC.len() + 4 * 8;
+    if data.len() < header || &data[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a checkpoint"));
+    }
+    let value = |i: usize| {
+        let start = MAGIC.len() + i * 8;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&data[start..start + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    let (width, height) = film.size();
+    if value(0) != fingerprint || value(1) != width as u64 || value(2) != height as u64 {
+        return Err(invalid(
+            "checkpoint of a render with other settings, remove it to start over",
+        ));
+    }
+    if !film.read_sums(&data[header..]) {
+        return Err(invalid("checkpoint is incomplete"));
+    }
+    Ok(Some(value(3) as usize))
+}
+
+// Path for one of the renders sharing a checkpoint path, like render_left.ckpt for render.ckpt.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}{}", stem, suffix);
+    if let Some(extension) = path.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::film::{Features, Filter};
+    use crate::math::vector::Vec3;
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let filter = Filter::Tent { radius: 1.0 };
+        let mut film = Film::new(4, 3);
+        film.add_sample(1.3, 2.7, Vec3(0.1, 0.2, 0.3), &filter);
+        film.add_features(1, 2, &Features::miss());
+
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.ckpt", std::process::id()));
+        save(&path, 42, 5, &film).unwrap();
+
+        let mut resumed = Film::new(4, 3);
+        assert_eq!(load(&path, 42, &mut resumed).unwrap(), Some(5));
+        for (x, y) in (0..3).flat_map(|y| (0..4).map(move |x| (x, y))) {
+            assert_eq!(resumed.pixel(x, y), film.pixel(x, y));
+            assert_eq!(resumed.features(x, y), film.features(x, y));
+        }
+
+        assert!(load(&path, 43, &mut Film::new(4, 3)).is_err());
+        assert!(load(&path, 42, &mut Film::new(3, 4)).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(load(&path, 42, &mut Film::new(4, 3)).unwrap(), None);
+
+        assert_eq!(
+            with_suffix(Path::new("dir/render.ckpt"), "_left"),
+            Path::new("dir/render_left.ckpt")
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use super::math::vector::Vec3;
+use super::output::BitDepth;
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+/*
+    Film to image pipeline: white balance, exposure, tone mapping, the sRGB transfer function,
+    an optional 3D LUT and quantization to the bit depth of the image.
+*/
+#[derive(Clone)]
+pub struct ColorPipeline {
+    // In stops, every stop doubles the light.
+    pub exposure: f64,
+    // Color temperature (Kelvin) of the light that should look white, 6504 is neutral.
+    pub white_balance: Option<f64>,
+    pub tone_map: ToneMap,
+    // Applied to the sRGB encoded values, like a look or grade.
+    pub lut: Option<Arc<Lut3d>>,
+    pub bit_depth: BitDepth,
+}
+
+// Maps scene light (0 to infinity) to display light (0 to 1).
+#[derive(Debug, Clone, Copy)]
+pub enum ToneMap {
+    // Everything above 1 clips.
+    Clamp,
+    // Extended Reinhard on the luminance, white is the luminance that maps to 1.
+    Reinhard { white: f64 },
+    // Fit of the ACES reference rendering and sRGB output transforms (Stephen Hill).
+    Aces,
+    // AgX base look (Troy Sobotka), bright colors fade to white instead of skewing the hue.
+    Agx,
+}
+
+type Mat3 = [[f64; 3]; 3];
+
+fn multiply(m: &Mat3, v: Vec3) -> Vec3 {
+    Vec3(
+        m[0][0] * v.0 + m[0][1] * v.1 + m[0][2] * v.2,
+        m[1][0] * v.0 + m[1][1] * v.1 + m[1][2] * v.2,
+        m[2][0] * v.0 + m[2][1] * v.1 + m[2][2] * v.2,
+    )
+}
+
+fn luminance(color: Vec3) -> f64 {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+const SRGB_TO_XYZ: Mat3 = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175_0],
+    [0.019_333_9, 0.119_192_0, 0.950_304_1],
+];
+
+const XYZ_TO_SRGB: Mat3 = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266_0, 1.876_010_8, 0.041_556_0],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+// Cone response used for chromatic adaptation.
+const BRADFORD: Mat3 = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+const BRADFORD_INVERSE: Mat3 = [
+    [0.986_992_9, -0.147_054_3, 0.159_962_7],
+    [0.432_305_3, 0.518_360_3, 0.049_291_2],
+    [-0.008_528_7, 0.040_042_8, 0.968_486_7],
+];
+
+/*
+    Chromaticity of a light of the temperature: the CIE daylight locus from 4000K, which passes
+    through D65 at 6504K, and the Planckian locus (Kim et al.) below.
+*/
+fn temperature_xy(kelvin: f64) -> (f64, f64) {
+    let t = kelvin.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    if t >= 4000.0 {
+        let x = if t <= 7000.0 {
+            -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+        } else {
+            -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+        };
+        (x, -3.0 * x * x + 2.870 * x - 0.275)
+    } else {
+        let x = -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179910;
+        let (x2, x3) = (x * x, x * x * x);
+        let y = if t <= 2222.0 {
+            -1.106_381_4 * x3 - 1.348_110_20 * x2 + 2.185_558_32 * x - 0.202_196_83
+        } else {
+            -0.954_947_6 * x3 - 1.374_185_93 * x2 + 2.091_370_15 * x - 0.167_488_67
+        };
+        (x, y)
+    }
+}
+
+// Von Kries adaptation in the Bradford cone space from the light of the temperature to D65.
+fn white_balance(color: Vec3, kelvin: f64) -> Vec3 {
+    let (x, y) = temperature_xy(kelvin);
+    let source = multiply(&BRADFORD, Vec3(x / y, 1.0, (1.0 - x - y) / y));
+    let target = multiply(&BRADFORD, Vec3(0.950_47, 1.0, 1.088_83));
+
+    let cone = multiply(&BRADFORD, multiply(&SRGB_TO_XYZ, color));
+    let adapted = Vec3(
+        cone.0 * target.0 / source.0,
+        cone.1 * target.1 / source.1,
+        cone.2 * target.2 / source.2,
+    );
+    multiply(&XYZ_TO_SRGB, multiply(&BRADFORD_INVERSE, adapted))
+}
+
+fn aces(color: Vec3) -> Vec3 {
+    const INPUT: Mat3 = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: Mat3 = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let fit = |v: f64| {
+        (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+    };
+
+    let v = multiply(&INPUT, color);
+    multiply(&OUTPUT, Vec3(fit(v.0), fit(v.1), fit(v.2)))
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    // Insets the primaries, so saturated colors also go to white.
+    const INSET: Mat3 = [
+        [
+            0.842_479_062_253_094,
+            0.078_433_599_999_999_2,
+            0.079_223_745_147_764_3,
+        ],
+        [
+            0.042_328_242_261_012_3,
+            0.878_468_636_469_772,
+            0.079_166_127_460_543_4,
+        ],
+        [0.042_375_654_905_705_1, 0.078_433_6, 0.879_142_973_793_104],
+    ];
+    const OUTSET: Mat3 = [
+        [
+            1.196_879_005_120_17,
+            -0.098_020_881_140_136_8,
+            -0.099_029_744_079_720_5,
+        ],
+        [
+            -0.052_896_851_757_456_2,
+            1.151_903_129_904_17,
+            -0.098_961_176_844_843_3,
+        ],
+        [
+            -0.052_971_635_514_443_8,
+            -0.098_043_450_117_124_1,
+            1.151_073_672_641_16,
+        ],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    // Log encoding followed by a polynomial fit of the sigmoid, which gives gamma 2.2 values.
+    let curve = |v: f64| {
+        let v = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let (v2, v4) = (v * v, v * v * v * v);
+        15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v
+            - 0.00232
+    };
+
+    let v = multiply(&INSET, color);
+    let v = multiply(&OUTSET, Vec3(curve(v.0), curve(v.1), curve(v.2)));
+    Vec3(
+        v.0.max(0.0).powf(2.2),
+        v.1.max(0.0).powf(2.2),
+        v.2.max(0.0).powf(2.2),
+    )
+}
+
+impl ToneMap {
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard { white } => {
+                let l = luminance(color);
+                if l <= 0.0 {
+                    return color;
+                }
+                let mapped = l * (1.0 + l / (white * white)) / (1.0 + l);
+                color * (mapped / l)
+            }
+            ToneMap::Aces => aces(color),
+            ToneMap::Agx => agx(color),
+        }
+    }
+}
+
+impl ColorPipeline {
+    // sRGB output of the tone map, without exposure, white balance or LUT.
+    pub fn new(tone_map: ToneMap, bit_depth: BitDepth) -> ColorPipeline {
+        ColorPipeline {
+            exposure: 0.0,
+            white_balance: None,
+            tone_map,
+            lut: None,
+            bit_depth,
+        }
+    }
+
+    pub fn with_exposure(self, exposure: f64) -> ColorPipeline {
+        ColorPipeline { exposure, ..self }
+    }
+
+    pub fn with_white_balance(self, kelvin: f64) -> ColorPipeline {
+        ColorPipeline {
+            white_balance: Some(kelvin),
+            ..self
+        }
+    }
+
+    pub fn with_lut(self, lut: Lut3d) -> ColorPipeline {
+        ColorPipeline {
+            lut: Some(Arc::new(lut)),
+            ..self
+        }
+    }
+
+    // Linear film color to sRGB encoded values between 0 and 1.
+    pub fn display(&self, color: Vec3) -> Vec3 {
+        let color = match self.white_balance {
+            Some(kelvin) => white_balance(color, kelvin),
+            None => color,
+        };
+        let color = self.tone_map.apply(color * 2f64.powf(self.exposure));
+
+        let encode = |v: f64| linear_to_srgb(v.clamp(0.0, 1.0));
+        let color = Vec3(encode(color.0), encode(color.1), encode(color.2));
+        match &self.lut {
+            Some(lut) => lut.apply(color),
+            None => color,
+        }
+    }
+
+    // Appends the pixel to the image data, 16 bit values are big endian as in png.
+    pub fn encode(&self, color: Vec3, image: &mut Vec<u8>) {
+        quantize(self.display(color), self.bit_depth, image);
+    }
+}
+
+// Appends encoded values between 0 and 1 to the image data, for images that skip the pipeline.
+pub fn quantize(color: Vec3, bit_depth: BitDepth, image: &mut Vec<u8>) {
+    for &v in [color.0, color.1, color.2].iter() {
+        let v = v.clamp(0.0, 1.0);
+        match bit_depth {
+            BitDepth::Eight => image.push((v * 255.0).round() as u8),
+            BitDepth::Sixteen => {
+                image.extend_from_slice(&((v * 65535.0).round() as u16).to_be_bytes())
+            }
+        }
+    }
+}
+
+/*
+    3D lookup table from an Adobe/Resolve .cube file, sampled with trilinear interpolation.
+    Red changes fastest in the table.
+*/
+pub struct Lut3d {
+    size: usize,
+    domain_min: Vec3,
+    domain_max: Vec3,
+    table: Vec<Vec3>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Lut3d {
+    pub fn load_cube(path: &Path) -> Result<Lut3d, Error> {
+        Lut3d::parse_cube(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse_cube(text: &str) -> Result<Lut3d, Error> {
+        let mut size = 0;
+        let mut domain_min = Vec3::zero();
+        let mut domain_max = Vec3::fill(1.0);
+        let mut table = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            let values = |words: std::str::SplitWhitespace| -> Result<Vec3, Error> {
+                let v: Vec<f64> = words
+                    .map(|w| w.parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid(format!("Invalid number on line {}", number + 1)))?;
+                match v[..] {
+                    [r, g, b] => Ok(Vec3(r, g, b)),
+                    _ => Err(invalid(format!("Expected 3 values on line {}", number + 1))),
+                }
+            };
+
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    size = words
+                        .next()
+                        .and_then(|w| w.parse().ok())
+                        .filter(|&s| s >= 2)
+                        .ok_or_else(|| invalid("Invalid LUT_3D_SIZE".to_string()))?;
+                }
+                "DOMAIN_MIN" => domain_min = values(words)?,
+                "DOMAIN_MAX" => domain_max = values(words)?,
+                "LUT_1D_SIZE" => return Err(invalid("1D LUTs are not supported".to_string())),
+                // Other keywords, like LUT_3D_INPUT_RANGE, don't change how the table is read.
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => continue,
+                _ => table.push(values(line.split_whitespace())?),
+            }
+        }
+
+        if size == 0 || table.len() != size * size * size {
+            return Err(invalid(format!(
+                "Expected {} entries for a LUT of size {}, found {}",
+                size * size * size,
+                size,
+                table.len()
+            )));
+        }
+        Ok(Lut3d {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let n = self.size - 1;
+        // Position in the table and the interpolation weight on each axis.
+        let axis = |v: f64, min: f64, max: f64| {
+            let p = ((v - min) / (max - min)).clamp(0.0, 1.0) * n as f64;
+            let i = (p.floor() as usize).min(n - 1);
+            (i, p - i as f64)
+        };
+        let (r, tr) = axis(color.0, self.domain_min.0, self.domain_max.0);
+        let (g, tg) = axis(color.1, self.domain_min.1, self.domain_max.1);
+        let (b, tb) = axis(color.2, self.domain_min.2, self.domain_max.2);
+
+        let at = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+        let lerp = |a: Vec3, b: Vec3, t: f64| a * (1.0 - t) + b * t;
+        let plane = |b: usize| {
+            lerp(
+                lerp(at(r, g, b), at(r + 1, g, b), tr),
+                lerp(at(r, g + 1, b), at(r + 1, g + 1, b), tr),
+                tg,
+            )
+        };
+        lerp(plane(b), plane(b + 1), tb)
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_tone_maps() {
+        for &tone_map in [
+            ToneMap::Reinhard { white: 4.0 },
+            ToneMap::Aces,
+            ToneMap::Agx,
+        ]
+        .iter()
+        {
+            // Increasing, black stays black and bright light does not clip hard.
+            let gray = |v: f64| luminance(tone_map.apply(Vec3::fill(v)));
+            assert!(gray(0.0).abs() < 0.01);
+            assert!(gray(0.18) < gray(1.0) && gray(1.0) < gray(4.0));
+            assert!(gray(4.0) < 1.01);
+        }
+        let reinhard = ToneMap::Reinhard { white: 4.0 };
+        assert_approx_eq!(reinhard.apply(Vec3::fill(4.0)).0, 1.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_white_balance() {
+        // D65 is neutral, a warm light becomes white.
+        let white = white_balance(Vec3::fill(1.0), 6504.0);
+        assert_approx_eq!(white.0, 1.0, 0.001);
+        assert_approx_eq!(white.2, 1.0, 0.001);
+        let warm = white_balance(Vec3(1.0, 0.8, 0.6), 3200.0);
+        assert!(warm.2 > 0.6 * 1.2 && warm.0 < 1.0);
+    }
+
+    #[test]
+    fn test_lut_cube() {
+        // Identity of size 2, except that blue is inverted.
+        let lut = Lut3d::parse_cube(
+            "TITLE \"test\"\n# comment\nLUT_3D_SIZE 2\n\
+             0 0 1\n1 0 1\n0 1 1\n1 1 1\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n",
+        )
+        .unwrap();
+        let color = lut.apply(Vec3(0.25, 0.5, 0.75));
+        assert_approx_eq!(color.0, 0.25, ASSERT_MARGIN);
+        assert_approx_eq!(color.1, 0.5, ASSERT_MARGIN);
+        assert_approx_eq!(color.2, 0.25, ASSERT_MARGIN);
+
+        assert!(Lut3d::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn test_lut_cube_keywords() {
+        // Unknown keywords are skipped instead of read as a row of the table.
+        let lut = Lut3d::parse_cube(
+            "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 1\n\
+             0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )
+        .unwrap();
+        let color = lut.apply(Vec3(0.25, 0.5, 0.75));
+        assert_approx_eq!(color.2, 0.75, ASSERT_MARGIN);
+
+        match Lut3d::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n") {
+            Err(error) => assert_eq!(error.to_string(), "1D LUTs are not supported"),
+            Ok(_) => panic!("1D LUT was parsed"),
+        }
+    }
+}
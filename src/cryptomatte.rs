@@ -0,0 +1,249 @@
+use super::output::{Layer, LayerKind};
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/*
+    ID mattes in the Cryptomatte layout (Friedman and Jones 2015). Every pixel keeps the names
+    that were hit within the filter footprint with their coverage, so compositors can pull an
+    anti-aliased matte for any object or material, also through motion blur and depth of field.
+    Names are stored as their MurmurHash3 reinterpreted as a float, the manifest in the metadata
+    maps the names back to the hashes.
+*/
+
+// Number of ids per pixel in the file, two per RGBA layer. Ids with less coverage are left out.
+pub const RANKS: usize = 6;
+
+// Summed filter weights of the names in every pixel.
+#[derive(Clone)]
+pub struct Mattes {
+    pixels: Vec<Vec<(u32, f64)>>,
+    names: HashMap<u32, Arc<str>>,
+}
+
+// Names and their hashes of one kind of matte, like CryptoObject.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub name: &'static str,
+    pub ids: BTreeMap<String, u32>,
+}
+
+impl Mattes {
+    pub fn new(size: usize) -> Mattes {
+        Mattes {
+            pixels: vec![vec![]; size],
+            names: HashMap::new(),
+        }
+    }
+
+    // Adds a sample with the name to the pixels of its footprint.
+    pub fn add(&mut self, name: &Arc<str>, footprint: &[(usize, f64)]) {
+        let id = hash(name);
+        self.names.entry(id).or_insert_with(|| Arc::clone(name));
+        for &(index, weight) in footprint {
+            add_weight(&mut self.pixels[index], id, weight);
+        }
+    }
+
+    pub fn merge(&mut self, to: usize, other: &Mattes, from: usize) {
+        for &(id, weight) in other.pixels[from].iter() {
+            add_weight(&mut self.pixels[to], id, weight);
+        }
+    }
+
+    // Only has to be done once per merged film, not for every pixel.
+    pub fn merge_names(&mut self, other: &Mattes) {
+        for (id, name) in other.names.iter() {
+            self.names.entry(*id).or_insert_with(|| Arc::clone(name));
+        }
+    }
+
+    /*
+        Layers name00, name01, .. with (id, coverage, id, coverage) in RGBA, sorted from the
+        most to the least coverage. The coverage is the weight of the id divided by the total
+        weight of the pixel, which are the weights of the image.
+    */
+    pub fn layers(&self, name: &str, weights: &[f64]) -> Vec<Layer> {
+        let mut layers: Vec<Layer> = (0..RANKS / 2)
+            .map(|i| Layer {
+                name: format!("{}{:02}", name, i),
+                kind: LayerKind::Matte,
+                values: Vec::with_capacity(self.pixels.len() * 4),
+            })
+            .collect();
+
+        for (ids, &total) in self.pixels.iter().zip(weights.iter()) {
+            let mut ranked: Vec<(u32, f64)> = ids
+                .iter()
+                .map(|&(id, weight)| (id, if total > 0.0 { weight / total } else { 0.0 }))
+                .collect();
+            // Ties are sorted by id, so the file is the same for every run.
+            ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+            for rank in 0..RANKS {
+                let (id, coverage) = match ranked.get(rank) {
+                    Some(&(id, coverage)) => (to_float(id), coverage as f32),
+                    None => (0.0, 0.0),
+                };
+                let values = &mut layers[rank / 2].values;
+                values.push(id);
+                values.push(coverage);
+            }
+        }
+        layers
+    }
+
+    pub fn manifest(&self, name: &'static str) -> Manifest {
+        Manifest {
+            name,
+            ids: self
+                .names
+                .iter()
+                .map(|(&id, name)| (name.to_string(), id))
+                .collect(),
+        }
+    }
+}
+
+impl Manifest {
+    // Adds the names of the same kind of matte, for example of the other eye.
+    pub fn merge(&mut self, other: &Manifest) {
+        for (name, &id) in other.ids.iter() {
+            self.ids.insert(name.clone(), id);
+        }
+    }
+
+    /*
+        EXR header attributes cryptomatte/<key>/.., the key is the start of the hash of the
+        layer name. The manifest is a JSON object with the hex bits of the float id per name.
+    */
+    pub fn metadata(&self) -> Vec<(String, String)> {
+        let key = &format!("{:08x}", hash(self.name))[..7];
+        let entries: Vec<String> = self
+            .ids
+            .iter()
+            .map(|(name, &id)| format!("\"{}\":\"{:08x}\"", escape(name), to_float(id).to_bits()))
+            .collect();
+        let attribute =
+            |field: &str, value: String| (format!("cryptomatte/{}/{}", key, field), value);
+        vec![
+            attribute("name", self.name.to_string()),
+            attribute("hash", "MurmurHash3_32".to_string()),
+            attribute("conversion", "uint32_to_float32".to_string()),
+            attribute("manifest", format!("{{{}}}", entries.join(","))),
+        ]
+    }
+}
+
+fn add_weight(ids: &mut Vec<(u32, f64)>, id: u32, weight: f64) {
+    match ids.iter_mut().find(|(other, _)| *other == id) {
+        Some((_, sum)) => *sum += weight,
+        None => ids.push((id, weight)),
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// MurmurHash3_x86_32 of the UTF-8 bytes of the name, with seed 0.
+pub fn hash(name: &str) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let bytes = name.as_bytes();
+    let mut h: u32 = 0;
+    let mut blocks = bytes.chunks_exact(4);
+    for block in &mut blocks {
+        let k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .rev()
+            .fold(0u32, |k, &byte| (k << 8) | byte as u32);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h ^= bytes.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+// The hash as float bits, with the exponent changed when it would be a denormal, inf or nan.
+pub fn to_float(id: u32) -> f32 {
+    let exponent = (id >> 23) & 0xff;
+    if exponent == 0 || exponent == 0xff {
+        f32::from_bits(id ^ (1 << 23))
+    } else {
+        f32::from_bits(id)
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(""), 0);
+        assert_eq!(hash("hello"), 0x248b_fa47);
+        assert_eq!(
+            hash("The quick brown fox jumps over the lazy dog"),
+            0x2e4f_f723
+        );
+        assert!(to_float(hash("hello")).is_normal());
+        assert!(to_float(0x7f80_0001).is_normal());
+    }
+
+    #[test]
+    fn test_mattes_coverage() {
+        // A pixel with three quarters of its weight on the sphere and a quarter on the ground.
+        let (sphere, ground): (Arc<str>, Arc<str>) = ("sphere".into(), "ground".into());
+        let mut mattes = Mattes::new(1);
+        mattes.add(&sphere, &[(0, 0.5)]);
+        mattes.add(&ground, &[(0, 0.5)]);
+        let mut other = Mattes::new(1);
+        other.add(&sphere, &[(0, 1.0)]);
+        mattes.merge(0, &other, 0);
+        mattes.merge_names(&other);
+
+        let layers = mattes.layers("CryptoObject", &[2.0]);
+        assert_eq!(layers.len(), RANKS / 2);
+        assert_eq!(layers[0].name, "CryptoObject00");
+        let values = &layers[0].values;
+        assert_eq!(values[0], to_float(hash("sphere")));
+        assert_approx_eq!(values[1] as f64, 0.75, ASSERT_MARGIN);
+        assert_eq!(values[2], to_float(hash("ground")));
+        assert_approx_eq!(values[3] as f64, 0.25, ASSERT_MARGIN);
+        assert_eq!(layers[1].values, vec![0.0; 4]);
+
+        let metadata = mattes.manifest("CryptoObject").metadata();
+        assert!(metadata
+            .iter()
+            .any(|(k, v)| k.ends_with("/name") && v == "CryptoObject"));
+        let manifest = &metadata
+            .iter()
+            .find(|(k, _)| k.ends_with("/manifest"))
+            .unwrap()
+            .1;
+        assert!(manifest.contains(&format!(
+            "\"sphere\":\"{:08x}\"",
+            to_float(hash("sphere")).to_bits()
+        )));
+    }
+}
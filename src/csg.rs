@@ -0,0 +1,319 @@
+use super::material::Material;
+use super::math::vector::{Vec2, Vector};
+use super::primitive::Hit;
+use super::Vec3;
+
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    // First object with the second one carved out.
+    Difference,
+}
+
+// Point where the ray crosses the surface of a closed object, normal points outwards.
+#[derive(Clone)]
+pub struct SurfaceHit {
+    pub t: f64,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    // None for the ends of infinite intervals (half spaces).
+    pub material: Option<Arc<Material>>,
+}
+
+impl SurfaceHit {
+    pub fn infinite(t: f64) -> SurfaceHit {
+        SurfaceHit {
+            t,
+            normal: Vec3::zero(),
+            uv: Vec2(0.0, 0.0),
+            material: None,
+        }
+    }
+}
+
+// Part of the ray that is inside of an object.
+#[derive(Clone)]
+pub struct Interval {
+    pub enter: SurfaceHit,
+    pub exit: SurfaceHit,
+}
+
+// Crossings closer than this (relative to t) are at the same point of the surface.
+const CROSSING_EPSILON: f64 = 1e-7;
+
+// Crossings at a smaller angle (cosine) with the surface only touch it.
+const GRAZING_COSINE: f64 = 1e-6;
+
+/*
+    Side of a crossing of a surface with exact normals (true where the line of d enters), None
+    where the line only touches the surface.
+*/
+pub fn crossing(hit: Hit, d: Vec3) -> Option<(Hit, bool)> {
+    let cosine = Vec3::dot(hit.normal, d) / d.length();
+    if cosine.abs() < GRAZING_COSINE {
+        None
+    } else {
+        Some((hit, cosine < 0.0))
+    }
+}
+
+/*
+    Pairs the crossings of a closed surface, sorted on t and true where the line enters, into
+    (enter, exit) pairs. Crossings at the same point that enter or exit twice are one crossing,
+    like an edge shared by two triangles or a crossing found again. An exit and enter at the
+    same point cancel out, like a tangent touch or a silhouette edge. Left over crossings that
+    do not change the side are skipped, so missing or extra crossings do not flip the inside.
+*/
+pub fn pair_crossings(crossings: Vec<(Hit, bool)>) -> Vec<(Hit, Hit)> {
+    let mut boundaries: Vec<(Hit, bool)> = Vec::with_capacity(crossings.len());
+    for (hit, entering) in crossings {
+        match boundaries.last() {
+            Some(&(last, last_entering))
+                if hit.t - last.t <= CROSSING_EPSILON * last.t.abs().max(1.0) =>
+            {
+                if entering != last_entering {
+                    boundaries.pop();
+                }
+            }
+            _ => boundaries.push((hit, entering)),
+        }
+    }
+
+    let mut pairs = vec![];
+    let mut enter: Option<Hit> = None;
+    for (hit, entering) in boundaries {
+        match (entering, enter) {
+            (true, None) => enter = Some(hit),
+            (false, Some(first)) => {
+                pairs.push((first, hit));
+                enter = None;
+            }
+            // Already inside or outside.
+            _ => {}
+        }
+    }
+    pairs
+}
+
+/*
+    Combines two sorted lists of intervals. Walks over all boundaries in order and tracks if
+    the ray is inside of a and b, a new interval starts where the operation becomes true.
+*/
+pub fn combine(operation: Operation, a: Vec<Interval>, b: Vec<Interval>) -> Vec<Interval> {
+    // (hit, from a, entering)
+    let mut events: Vec<(SurfaceHit, bool, bool)> = Vec::with_capacity((a.len() + b.len()) * 2);
+    for (intervals, is_a) in [(a, true), (b, false)].iter_mut() {
+        for interval in intervals.drain(..) {
+            events.push((interval.enter, *is_a, true));
+            events.push((interval.exit, *is_a, false));
+        }
+    }
+    events.sort_by(|x, y| {
+        x.0.t
+            .partial_cmp(&y.0.t)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let inside = |in_a: bool, in_b: bool| match operation {
+        Operation::Union => in_a || in_b,
+        Operation::Intersection => in_a && in_b,
+        Operation::Difference => in_a && !in_b,
+    };
+
+    let mut result = vec![];
+    let mut in_a = false;
+    let mut in_b = false;
+    let mut enter: Option<SurfaceHit> = None;
+    for (mut hit, is_a, entering) in events {
+        let was_inside = inside(in_a, in_b);
+        if is_a {
+            in_a = entering;
+        } else {
+            in_b = entering;
+        }
+        let is_inside = inside(in_a, in_b);
+
+        // Surfaces of the carved out object face the other way.
+        if operation == Operation::Difference && !is_a {
+            hit.normal = -hit.normal;
+        }
+
+        if !was_inside && is_inside {
+            enter = Some(hit);
+        } else if was_inside && !is_inside {
+            if let Some(enter) = enter.take() {
+                result.push(Interval { enter, exit: hit });
+            }
+        }
+    }
+
+    result
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::material::{self, MaterialType};
+    use crate::mesh::Mesh;
+    use crate::ray::Ray;
+    use crate::shape::{self, Object, ObjectType};
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    fn intervals(ranges: &[(f64, f64)]) -> Vec<Interval> {
+        ranges
+            .iter()
+            .map(|&(enter, exit)| Interval {
+                enter: SurfaceHit::infinite(enter),
+                exit: SurfaceHit::infinite(exit),
+            })
+            .collect()
+    }
+
+    fn ranges(intervals: &[Interval]) -> Vec<(f64, f64)> {
+        intervals.iter().map(|i| (i.enter.t, i.exit.t)).collect()
+    }
+
+    #[test]
+    fn test_combine() {
+        let a = || intervals(&[(1.0, 4.0), (6.0, 8.0)]);
+        let b = || intervals(&[(3.0, 7.0)]);
+
+        let union = combine(Operation::Union, a(), b());
+        assert_eq!(ranges(&union), vec![(1.0, 8.0)]);
+
+        let intersection = combine(Operation::Intersection, a(), b());
+        assert_eq!(ranges(&intersection), vec![(3.0, 4.0), (6.0, 7.0)]);
+
+        let difference = combine(Operation::Difference, a(), b());
+        assert_eq!(ranges(&difference), vec![(1.0, 3.0), (7.0, 8.0)]);
+    }
+
+    fn crossings(sides: &[(f64, bool)]) -> Vec<(Hit, bool)> {
+        sides
+            .iter()
+            .map(|&(t, entering)| {
+                let hit = Hit {
+                    t,
+                    normal: Vec3::zero(),
+                    uv: Vec2(0.0, 0.0),
+                };
+                (hit, entering)
+            })
+            .collect()
+    }
+
+    fn pairs(sides: &[(f64, bool)]) -> Vec<(f64, f64)> {
+        pair_crossings(crossings(sides))
+            .iter()
+            .map(|(enter, exit)| (enter.t, exit.t))
+            .collect()
+    }
+
+    #[test]
+    fn test_pair_crossings() {
+        // An edge shared by two triangles is crossed twice.
+        let edge = [(1.0, true), (1.0, true), (3.0, false), (3.0, false)];
+        assert_eq!(pairs(&edge), vec![(1.0, 3.0)]);
+        // Touching the surface in between, or two parts touching each other.
+        let touch = [(1.0, true), (2.0, false), (4.0, true), (4.0, false)];
+        assert_eq!(pairs(&touch), vec![(1.0, 2.0)]);
+        let joined = [(1.0, true), (2.0, false), (2.0, true), (3.0, false)];
+        assert_eq!(pairs(&joined), vec![(1.0, 3.0)]);
+        // A crossing found again a step later, and a missing exit at the end.
+        let again = [
+            (1.0, true),
+            (2.0, false),
+            (2.001, false),
+            (3.0, true),
+            (4.0, false),
+        ];
+        assert_eq!(pairs(&again), vec![(1.0, 2.0), (3.0, 4.0)]);
+        let open = [(1.0, true), (2.0, false), (3.0, true)];
+        assert_eq!(pairs(&open), vec![(1.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_edge_and_grazing_intervals() {
+        let material = material::new(Vec3::fill(0.5), MaterialType::Lambertian);
+        let line = |o: Vec3, d: Vec3, object: &Object| {
+            let intervals = shape::intervals(object, &Ray::new(o, d));
+            ranges(&intervals)
+        };
+
+        // Cube from -1 to 1, the diagonal of every face is an edge between two triangles.
+        let positions: Vec<Vec3> = (0..8)
+            .map(|i| {
+                Vec3::fill(-1.0) + Vec3((i & 1) as f64, (i >> 1 & 1) as f64, (i >> 2) as f64) * 2.0
+            })
+            .collect();
+        let mut triangles = vec![];
+        for &[a, b, c, d] in [
+            [0, 1, 3, 2],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [0, 2, 6, 4],
+            [1, 3, 7, 5],
+        ]
+        .iter()
+        {
+            for &triangle in [[a, b, c], [a, c, d]].iter() {
+                // Faces outwards.
+                let [p, q, r] = [
+                    positions[triangle[0]],
+                    positions[triangle[1]],
+                    positions[triangle[2]],
+                ];
+                let facing = Vec3::dot(Vec3::cross(q - p, r - p), p + q + r) > 0.0;
+                triangles.push(if facing {
+                    triangle
+                } else {
+                    [triangle[0], triangle[2], triangle[1]]
+                });
+            }
+        }
+        let mesh = Arc::new(Mesh::new(positions, vec![], vec![], triangles));
+        let cube = shape::new(
+            Vec3::zero(),
+            ObjectType::Mesh {
+                mesh: Arc::clone(&mesh),
+            },
+            &material,
+        );
+
+        let (o, d) = (Vec3(0.3, 0.3, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert_eq!(mesh.crossings(o, d).len(), 4);
+        let cube_line = line(o, d, &cube);
+        assert_eq!(cube_line.len(), 1);
+        assert_approx_eq!(cube_line[0].0, 4.0, ASSERT_MARGIN);
+        assert_approx_eq!(cube_line[0].1, 6.0, ASSERT_MARGIN);
+
+        let torus = shape::new(
+            Vec3::zero(),
+            ObjectType::Torus {
+                major_radius: 2.0,
+                minor_radius: 0.5,
+            },
+            &material,
+        );
+        // Along the top of the torus, only touching it on both sides of the hole.
+        let top = line(Vec3(-5.0, 0.5, 0.0), Vec3(1.0, 0.0, 0.0), &torus);
+        assert!(top.is_empty());
+        // Through the tube, touching the inside of the ring halfway.
+        let inner = line(Vec3(-5.0, 0.0, 1.5), Vec3(1.0, 0.0, 0.0), &torus);
+        assert_eq!(inner.len(), 1);
+        assert_approx_eq!(inner[0].0, 3.0, ASSERT_MARGIN);
+        assert_approx_eq!(inner[0].1, 7.0, ASSERT_MARGIN);
+    }
+}
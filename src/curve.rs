@@ -0,0 +1,274 @@
+use super::bvh::{Aabb, Bvh};
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::primitive::Hit;
+use super::ray::Ray;
+
+// Straight pieces every cubic segment is split into for the intersection.
+const PIECES_PER_SEGMENT: usize = 6;
+// Rays starting this close to the axis of a piece were spawned on it and skip it.
+const SELF_HIT_MARGIN: f64 = 1.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveShape {
+    // Flat strip that always faces the ray, for grass and distant fur.
+    Ribbon,
+    // Flat strip shaded with the normals of a round tube, for hair.
+    Cylinder,
+}
+
+/*
+    One strand, a uniform cubic B-spline over the control points. The end points are repeated
+    so the strand starts and ends exactly at them. The width changes linearly from root to tip.
+*/
+#[derive(Clone)]
+pub struct Strand {
+    pub points: Vec<Vec3>,
+    pub root_width: f64,
+    pub tip_width: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Piece {
+    start: Vec3,
+    end: Vec3,
+    radius: (f64, f64),
+    // Position along the strand (0 at the root, 1 at the tip).
+    u: (f64, f64),
+}
+
+fn b_spline(p: [Vec3; 4], t: f64) -> Vec3 {
+    let s = 1.0 - t;
+    (p[0] * (s * s * s)
+        + p[1] * (3.0 * t * t * t - 6.0 * t * t + 4.0)
+        + p[2] * (-3.0 * t * t * t + 3.0 * t * t + 3.0 * t + 1.0)
+        + p[3] * (t * t * t))
+        / 6.0
+}
+
+/*
+    Collection of strands with a BVH over the straight pieces of the curves, so a whole fur
+    coat is a single object.
+*/
+pub struct Curves {
+    pieces: Vec<Piece>,
+    shape: CurveShape,
+    bvh: Bvh,
+}
+
+impl Curves {
+    pub fn new(strands: &[Strand], shape: CurveShape) -> Curves {
+        let mut pieces = vec![];
+        for strand in strands.iter().filter(|s| !s.points.is_empty()) {
+            let first = strand.points[0];
+            let last = strand.points[strand.points.len() - 1];
+            let points: Vec<Vec3> = [first, first]
+                .iter()
+                .chain(strand.points.iter())
+                .chain([last, last].iter())
+                .copied()
+                .collect();
+
+            let segments = points.len() - 3;
+            let steps = segments * PIECES_PER_SEGMENT;
+            let width = |u: f64| strand.root_width * (1.0 - u) + strand.tip_width * u;
+            let at = |step: usize| {
+                let segment = (step / PIECES_PER_SEGMENT).min(segments - 1);
+                let t = (step - segment * PIECES_PER_SEGMENT) as f64 / PIECES_PER_SEGMENT as f64;
+                let control = [
+                    points[segment],
+                    points[segment + 1],
+                    points[segment + 2],
+                    points[segment + 3],
+                ];
+                b_spline(control, t)
+            };
+
+            for step in 0..steps {
+                let (u0, u1) = (step as f64 / steps as f64, (step + 1) as f64 / steps as f64);
+                pieces.push(Piece {
+                    start: at(step),
+                    end: at(step + 1),
+                    radius: (width(u0) / 2.0, width(u1) / 2.0),
+                    u: (u0, u1),
+                });
+            }
+        }
+
+        let bounds: Vec<Option<Aabb>> = pieces
+            .iter()
+            .map(|piece| {
+                let r = Vec3::fill(piece.radius.0.max(piece.radius.1));
+                Some(Aabb::union(
+                    &Aabb::new(piece.start - r, piece.start + r),
+                    &Aabb::new(piece.end - r, piece.end + r),
+                ))
+            })
+            .collect();
+
+        Curves {
+            bvh: Bvh::build(&bounds),
+            pieces,
+            shape,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds().unwrap_or_else(Aabb::empty)
+    }
+
+    /*
+        Hit on a piece, treated as a strip through the axis facing the ray with the width of
+        the curve. Returns the hit and the tangent, uv.1 is the offset across the strip.
+    */
+    fn intersect_piece(
+        &self,
+        piece: &Piece,
+        o: Vec3,
+        d: Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(Hit, Vec3)> {
+        let axis = piece.end - piece.start;
+        let length2 = axis.squared();
+        let radius_at = |s: f64| piece.radius.0 * (1.0 - s) + piece.radius.1 * s;
+
+        // Rays leaving the curve itself.
+        let s_origin = if length2 > 0.0 {
+            (Vec3::dot(o - piece.start, axis) / length2).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let to_origin = o - (piece.start + axis * s_origin);
+        let limit = radius_at(s_origin) * SELF_HIT_MARGIN;
+        if to_origin.squared() < limit * limit {
+            return None;
+        }
+
+        // Closest points of the line of the ray and the axis.
+        let a = d.squared();
+        let b = Vec3::dot(d, axis);
+        let w = o - piece.start;
+        let denom = a * length2 - b * b;
+        let s = if denom.abs() > 1e-12 {
+            ((a * Vec3::dot(axis, w) - b * Vec3::dot(d, w)) / denom).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let on_axis = piece.start + axis * s;
+        let t = Vec3::dot(on_axis - o, d) / a;
+
+        let offset = o + d * t - on_axis;
+        let radius = radius_at(s);
+        if offset.squared() > radius * radius {
+            return None;
+        }
+
+        let tangent = if length2 > 0.0 {
+            axis / length2.sqrt()
+        } else {
+            Vec3::up()
+        };
+        let across = -d - tangent * Vec3::dot(-d, tangent);
+        let facing = if across.squared() > 0.0 {
+            across.normalize()
+        } else {
+            -d.normalize()
+        };
+        let side = Vec3::cross(facing, tangent);
+        let h = (Vec3::dot(offset, side) / radius).clamp(-1.0, 1.0);
+
+        let (t, normal) = match self.shape {
+            CurveShape::Ribbon => (t, facing),
+            CurveShape::Cylinder => {
+                let depth = (1.0 - h * h).sqrt();
+                (t - depth * radius / a.sqrt(), side * h + facing * depth)
+            }
+        };
+        if t < t_min || t >= t_max {
+            return None;
+        }
+
+        let u = piece.u.0 * (1.0 - s) + piece.u.1 * s;
+        Some((
+            Hit {
+                t,
+                normal,
+                uv: Vec2(u, (h + 1.0) / 2.0),
+            },
+            tangent,
+        ))
+    }
+
+    // Closest hit between t_min and t_max with the tangent, o and d are in the space of the curves.
+    pub fn intersect(&self, o: Vec3, d: Vec3, t_min: f64, t_max: f64) -> Option<(Hit, Vec3)> {
+        let mut ray = Ray::new(o, d);
+        ray.travel_distance = t_max;
+
+        let mut closest = None;
+        self.bvh.traverse(&mut ray, t_min, |i, ray| {
+            if let Some(hit) =
+                self.intersect_piece(&self.pieces[i], o, d, t_min, ray.travel_distance)
+            {
+                ray.travel_distance = hit.0.t;
+                closest = Some(hit);
+            }
+        });
+        closest
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_curve_intersect() {
+        // Straight vertical strand, 0.2 wide at the root and 0.1 at the tip.
+        let strand = Strand {
+            points: vec![Vec3::zero(), Vec3(0.0, 1.0, 0.0), Vec3(0.0, 2.0, 0.0)],
+            root_width: 0.2,
+            tip_width: 0.1,
+        };
+        let curves = Curves::new(&[strand], CurveShape::Ribbon);
+        let d = Vec3(0.0, 0.0, -1.0);
+
+        let (hit, tangent) = curves
+            .intersect(Vec3(0.05, 1.0, 5.0), d, 0.001, f64::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 5.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.normal.2, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(tangent.1, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.uv.0, 0.5, ASSERT_MARGIN);
+
+        // Wider than the strand near the tip.
+        assert!(curves
+            .intersect(Vec3(0.07, 1.9, 5.0), d, 0.001, f64::MAX)
+            .is_none());
+        // Rays starting on the strand leave it.
+        assert!(curves
+            .intersect(Vec3(0.0, 1.0, 0.0), d, 0.0, f64::MAX)
+            .is_none());
+
+        let cylinder = Curves::new(
+            &[Strand {
+                points: vec![Vec3::zero(), Vec3(0.0, 2.0, 0.0)],
+                root_width: 0.2,
+                tip_width: 0.2,
+            }],
+            CurveShape::Cylinder,
+        );
+        let (hit, _) = cylinder
+            .intersect(Vec3(0.0, 1.0, 5.0), d, 0.001, f64::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 4.9, ASSERT_MARGIN);
+    }
+}
@@ -0,0 +1,186 @@
+use super::film::{Features, Film};
+use super::math::vector::Vec3;
+
+/*
+    Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010). Every iteration blurs with a
+    5x5 B3 spline kernel whose taps are twice as far apart as in the previous one, the weight of
+    a tap drops when its color, albedo, normal or depth differs from the center pixel. The
+    lighting is filtered without the albedo, so textures stay sharp.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Denoiser {
+    // Number of passes, the filter reaches 2^(iterations + 1) pixels.
+    pub iterations: u32,
+    // Allowed differences before the weight of a tap falls off, the color sigma is halved with
+    // every pass so the wide passes only smooth noise that is left.
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    // Relative to the depth of the center pixel.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedo below this is not divided out, black surfaces have no lighting to recover.
+const MIN_ALBEDO: f64 = 0.01;
+
+impl Denoiser {
+    // Denoised colors of the film in scan order.
+    pub fn apply(&self, film: &Film) -> Vec<Vec3> {
+        let (width, height) = film.size();
+        let features: Vec<Features> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| film.features(x, y))
+            .collect();
+
+        let demodulate = |c: f64, a: f64| if a > MIN_ALBEDO { c / a } else { c };
+        let mut lighting: Vec<Vec3> = features
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let color = film.pixel(i % width, i / width);
+                Vec3(
+                    demodulate(color.0, f.albedo.0),
+                    demodulate(color.1, f.albedo.1),
+                    demodulate(color.2, f.albedo.2),
+                )
+            })
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let sigma_color = self.sigma_color / (1 << iteration) as f64;
+            lighting = (0..width * height)
+                .map(|i| {
+                    let (x, y) = ((i % width) as i64, (i / width) as i64);
+                    let mut sum = Vec3::zero();
+                    let mut total = 0.0;
+                    for (ky, wy) in KERNEL.iter().enumerate() {
+                        let sy = y + (ky as i64 - 2) * step;
+                        if sy < 0 || sy >= height as i64 {
+                            continue;
+                        }
+                        for (kx, wx) in KERNEL.iter().enumerate() {
+                            let sx = x + (kx as i64 - 2) * step;
+                            if sx < 0 || sx >= width as i64 {
+                                continue;
+                            }
+                            let j = sy as usize * width + sx as usize;
+                            let weight = wx
+                                * wy
+                                * self.edge_weight(
+                                    (lighting[i], &features[i]),
+                                    (lighting[j], &features[j]),
+                                    sigma_color,
+                                );
+                            sum += lighting[j] * weight;
+                            total += weight;
+                        }
+                    }
+                    // The center tap always has a weight, total is never zero.
+                    sum / total
+                })
+                .collect();
+        }
+
+        let remodulate = |c: f64, a: f64| if a > MIN_ALBEDO { c * a } else { c };
+        lighting
+            .iter()
+            .zip(features.iter())
+            .map(|(l, f)| {
+                Vec3(
+                    remodulate(l.0, f.albedo.0),
+                    remodulate(l.1, f.albedo.1),
+                    remodulate(l.2, f.albedo.2),
+                )
+            })
+            .collect()
+    }
+
+    fn edge_weight(&self, center: (Vec3, &Features), tap: (Vec3, &Features), sigma: f64) -> f64 {
+        let squared = |v: Vec3| Vec3::dot(v, v);
+        // Compressed colors, so a single bright sample does not stop all smoothing around it.
+        let compress = |c: Vec3| Vec3(c.0 / (1.0 + c.0), c.1 / (1.0 + c.1), c.2 / (1.0 + c.2));
+
+        let (color, features) = center;
+        let color = squared(compress(color) - compress(tap.0)) / (sigma * sigma);
+        let albedo =
+            squared(features.albedo - tap.1.albedo) / (self.sigma_albedo * self.sigma_albedo);
+        let normal =
+            squared(features.normal - tap.1.normal) / (self.sigma_normal * self.sigma_normal);
+        let depth = (features.depth - tap.1.depth)
+            / (self.sigma_depth * features.depth.max(tap.1.depth).max(1e-6));
+        (-(color + albedo + normal + depth * depth)).exp()
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::film::Filter;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_denoise_edges() {
+        // Left half is a red wall facing the camera, right half a white floor facing up.
+        let (width, height) = (16, 8);
+        let filter = Filter::Box { radius: 0.5 };
+        let mut film = Film::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                let features = Features {
+                    albedo: if left {
+                        Vec3(0.8, 0.1, 0.1)
+                    } else {
+                        Vec3::fill(0.8)
+                    },
+                    normal: if left {
+                        Vec3(0.0, 0.0, 1.0)
+                    } else {
+                        Vec3::up()
+                    },
+                    depth: 5.0,
+                };
+                // Noisy lighting, bright and dark in a checkerboard.
+                let lighting = if (x + y) % 2 == 0 { 0.25 } else { 0.75 };
+                let x_center = x as f64 + 0.5;
+                let y_center = y as f64 + 0.5;
+                film.add_sample(x_center, y_center, features.albedo * lighting, &filter);
+                film.add_features(x, y, &features);
+            }
+        }
+
+        let denoised = Denoiser::default().apply(&film);
+        for y in 1..height - 1 {
+            // The noise is smoothed out, the colors do not bleed over the edge.
+            let wall = denoised[y * width + 2];
+            let floor = denoised[y * width + width - 3];
+            assert!((wall.0 - 0.4).abs() < 0.05);
+            assert!((floor.1 - 0.4).abs() < 0.05);
+            assert_approx_eq!(wall.1 / wall.0, 0.125, ASSERT_MARGIN);
+            assert_approx_eq!(floor.0, floor.1, ASSERT_MARGIN);
+        }
+    }
+}
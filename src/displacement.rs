@@ -0,0 +1,328 @@
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::mesh::{self, Mesh};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+extern crate png;
+extern crate rand;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/*
+    Gradient (Perlin) noise with a permutation table from the seed, so the same seed always
+    gives the same surface.
+*/
+#[derive(Clone)]
+pub struct Noise {
+    permutation: Vec<usize>,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        let mut table: Vec<usize> = (0..256).collect();
+        table.shuffle(&mut StdRng::seed_from_u64(seed));
+        let permutation = table.iter().chain(table.iter()).copied().collect();
+        Noise { permutation }
+    }
+
+    fn gradient(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+        // One of the 12 directions to the edges of a cube.
+        let h = hash & 15;
+        let u = if h < 8 { x } else { y };
+        let v = if h < 4 {
+            y
+        } else if h == 12 || h == 14 {
+            x
+        } else {
+            z
+        };
+        (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+    }
+
+    // Noise between about -1 and 1, 0 on all integer coordinates.
+    pub fn eval(&self, p: Vec3) -> f64 {
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+
+        let (fx, fy, fz) = (p.0.floor(), p.1.floor(), p.2.floor());
+        let (x, y, z) = (p.0 - fx, p.1 - fy, p.2 - fz);
+        let (xi, yi, zi) = (
+            (fx as i64 & 255) as usize,
+            (fy as i64 & 255) as usize,
+            (fz as i64 & 255) as usize,
+        );
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let perm = &self.permutation;
+        let a = perm[xi] + yi;
+        let (aa, ab) = (perm[a] + zi, perm[a + 1] + zi);
+        let b = perm[xi + 1] + yi;
+        let (ba, bb) = (perm[b] + zi, perm[b + 1] + zi);
+
+        let g = |hash: usize, dx: f64, dy: f64, dz: f64| {
+            Noise::gradient(perm[hash], x - dx, y - dy, z - dz)
+        };
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, g(aa, 0.0, 0.0, 0.0), g(ba, 1.0, 0.0, 0.0)),
+                lerp(u, g(ab, 0.0, 1.0, 0.0), g(bb, 1.0, 1.0, 0.0)),
+            ),
+            lerp(
+                v,
+                lerp(u, g(aa + 1, 0.0, 0.0, 1.0), g(ba + 1, 1.0, 0.0, 1.0)),
+                lerp(u, g(ab + 1, 0.0, 1.0, 1.0), g(bb + 1, 1.0, 1.0, 1.0)),
+            ),
+        )
+    }
+
+    // Sum of octaves with doubling frequency and halving amplitude (fractal Brownian motion).
+    pub fn fbm(&self, p: Vec3, octaves: usize) -> f64 {
+        (0..octaves)
+            .fold((0.0, 1.0), |(sum, scale), _| {
+                (sum + self.eval(p * scale) / scale, scale * 2.0)
+            })
+            .0
+    }
+}
+
+// Grayscale image with values between 0 and 1, sampled with the uvs of the mesh.
+#[derive(Clone)]
+pub struct HeightMap {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl HeightMap {
+    pub fn new(width: usize, height: usize, values: Vec<f64>) -> Result<HeightMap, Error> {
+        if width == 0 || height == 0 || values.len() != width * height {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Height map of {}x{} has {} values",
+                    width,
+                    height,
+                    values.len()
+                ),
+            ));
+        }
+        Ok(HeightMap {
+            width,
+            height,
+            values,
+        })
+    }
+
+    // Loads the first channel of a png file (any bit depth or color type).
+    pub fn load_png(path: &Path) -> Result<HeightMap, Error> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buffer = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buffer)?;
+
+        let (width, height) = (info.width as usize, info.height as usize);
+        let channels = info.line_size / width;
+        let values = buffer
+            .chunks(info.line_size)
+            .flat_map(|line| line.chunks(channels).take(width))
+            .map(|pixel| pixel[0] as f64 / 255.0)
+            .collect();
+        HeightMap::new(width, height, values)
+    }
+
+    // Bilinear sample, uvs wrap around and v goes up in the image.
+    pub fn sample(&self, uv: Vec2) -> f64 {
+        let x = uv.0.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - uv.1.rem_euclid(1.0)) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let value = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.values[y * self.width + x]
+        };
+        let top = value(x0, y0) * (1.0 - tx) + value(x0 + 1.0, y0) * tx;
+        let bottom = value(x0, y0 + 1.0) * (1.0 - tx) + value(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+// Scalar height that moves the surface along the normal.
+#[derive(Clone)]
+pub enum Displacement {
+    // Noise of the position of the vertex (in the space of the mesh).
+    Noise {
+        noise: Noise,
+        frequency: f64,
+        octaves: usize,
+        amplitude: f64,
+    },
+    // Height map over the uvs of the mesh, 0.5 is the original surface.
+    Texture {
+        map: HeightMap,
+        amplitude: f64,
+    },
+}
+
+impl Displacement {
+    pub fn height(&self, position: Vec3, uv: Vec2) -> f64 {
+        match self {
+            Displacement::Noise {
+                noise,
+                frequency,
+                octaves,
+                amplitude,
+            } => noise.fbm(position * *frequency, *octaves) * amplitude,
+            Displacement::Texture { map, amplitude } => (map.sample(uv) - 0.5) * amplitude,
+        }
+    }
+}
+
+/*
+    Splits every triangle into four, the number of times given. Edge midpoints are shared
+    between the triangles so the surface stays closed when it is displaced.
+*/
+fn tessellate(mesh: &Mesh, levels: usize) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec2>, Vec<[usize; 3]>) {
+    let mut positions = mesh.positions.clone();
+    let mut normals = if mesh.normals.is_empty() {
+        mesh::smooth_normals(&mesh.positions, &mesh.triangles)
+    } else {
+        mesh.normals.clone()
+    };
+    let mut uvs = mesh.uvs.clone();
+    let mut triangles = mesh.triangles.clone();
+
+    for _ in 0..levels {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]) / 2.0);
+                normals.push((normals[a] + normals[b]).normalize());
+                if !uvs.is_empty() {
+                    uvs.push(Vec2(
+                        (uvs[a].0 + uvs[b].0) / 2.0,
+                        (uvs[a].1 + uvs[b].1) / 2.0,
+                    ));
+                }
+                positions.len() - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    (positions, normals, uvs, triangles)
+}
+
+/*
+    Tessellates the mesh and moves every vertex along its normal by the displacement. The
+    normals are recomputed from the displaced triangles, the BVH is built on the result.
+*/
+pub fn displace(mesh: &Mesh, displacement: &Displacement, levels: usize) -> Mesh {
+    let (mut positions, normals, uvs, triangles) = tessellate(mesh, levels);
+    for (i, position) in positions.iter_mut().enumerate() {
+        let uv = uvs.get(i).copied().unwrap_or(Vec2(0.0, 0.0));
+        *position += normals[i] * displacement.height(*position, uv);
+    }
+    Mesh::smooth(positions, uvs, triangles)
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_noise() {
+        let noise = Noise::new(7);
+        assert_approx_eq!(noise.eval(Vec3(1.0, 2.0, 3.0)), 0.0, ASSERT_MARGIN);
+        let value = noise.eval(Vec3(0.3, 0.6, 0.9));
+        assert!(value.abs() <= 1.5);
+        assert_approx_eq!(
+            Noise::new(7).eval(Vec3(0.3, 0.6, 0.9)),
+            value,
+            ASSERT_MARGIN
+        );
+    }
+
+    #[test]
+    fn test_displace_flat_square() {
+        let positions = vec![
+            Vec3::zero(),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 1.0),
+            Vec3(0.0, 0.0, 1.0),
+        ];
+        let uvs = vec![
+            Vec2(0.0, 0.0),
+            Vec2(1.0, 0.0),
+            Vec2(1.0, 1.0),
+            Vec2(0.0, 1.0),
+        ];
+        let mesh = Mesh::smooth(positions, uvs, vec![[0, 2, 1], [0, 3, 2]]);
+
+        // Height map with a white left half, the left side moves up.
+        let map = HeightMap::new(2, 1, vec![1.0, 0.0]).unwrap();
+        let displaced = displace(
+            &mesh,
+            &Displacement::Texture {
+                map,
+                amplitude: 1.0,
+            },
+            2,
+        );
+        assert_eq!(displaced.triangles.len(), 2 * 16);
+        // 5 x 5 grid of vertices, shared between the triangles.
+        assert_eq!(displaced.positions.len(), 25);
+        // Corner at uv (0.25, 0.5) samples only the white pixel.
+        let left = displaced
+            .positions
+            .iter()
+            .zip(displaced.uvs.iter())
+            .find(|(_, uv)| (uv.0 - 0.25).abs() < 1e-9 && (uv.1 - 0.5).abs() < 1e-9)
+            .unwrap();
+        assert_approx_eq!((left.0).1, 0.5, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_height_map() {
+        assert!(HeightMap::new(2, 2, vec![0.0; 3]).is_err());
+        assert!(HeightMap::new(0, 0, vec![]).is_err());
+
+        // Bricks with a black mortar line along the top and left of every brick.
+        let map = HeightMap::load_png(Path::new("other/textures/bricks.png")).unwrap();
+        assert_eq!((map.width, map.height), (64, 64));
+        assert_approx_eq!(map.values[0], 0.0, ASSERT_MARGIN);
+        assert_approx_eq!(map.values[8 * 64 + 16], 1.0, ASSERT_MARGIN);
+        // Center of the first brick, v goes up in the image.
+        assert_approx_eq!(
+            map.sample(Vec2(17.0 / 64.0, 1.0 - 9.0 / 64.0)),
+            1.0,
+            ASSERT_MARGIN
+        );
+
+        assert!(HeightMap::load_png(Path::new("other/textures/missing.png")).is_err());
+    }
+}
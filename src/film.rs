@@ -0,0 +1,685 @@
+use super::aov::AovSample;
+use super::cryptomatte::{Manifest, Mattes};
+use super::math::vector::Vec3;
+use super::output::{Layer, LayerKind};
+
+use std::f64::consts::PI;
+
+/*
+    Reconstruction filters, the weight of a sample for a pixel depends on the distance from the
+    sample to the center of the pixel. The radius is in pixels, filters are separable.
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    // Radius 0.5 averages the samples within the pixel.
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    // Cubic with negative lobes, B = C = 1/3 is the recommended balance of blur and ringing.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // Sinc windowed by a wider sinc, tau is the number of lobes.
+    Lanczos { radius: f64, tau: f64 },
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            // Shifted down so the filter goes to zero at the radius.
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                // The cubic is defined from 0 to 2.
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+// Layer names of the ID mattes, as used by the Cryptomatte plugins.
+const OBJECT_MATTE: &str = "CryptoObject";
+const MATERIAL_MATTE: &str = "CryptoMaterial";
+
+/*
+    Surface at the first hit of a camera ray that is not specular, guides the denoiser. Rays
+    that miss the scene have a white albedo, no normal and zero depth.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Features {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    // Distance from the camera, or from the last mirror or glass surface on the way.
+    pub depth: f64,
+}
+
+impl Features {
+    pub fn miss() -> Features {
+        Features {
+            albedo: Vec3::fill(1.0),
+            normal: Vec3::zero(),
+            depth: 0.0,
+        }
+    }
+
+    fn zero() -> Features {
+        Features {
+            albedo: Vec3::zero(),
+            normal: Vec3::zero(),
+            depth: 0.0,
+        }
+    }
+
+    fn add(&mut self, other: &Features) {
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.depth += other.depth;
+    }
+}
+
+/*
+    Float image that samples are splatted into with a reconstruction filter. A film can cover a
+    part of the image (a tile), tiles are merged into the film of the whole image. Pixels keep
+    the weighted sum of the samples and the sum of the weights. Features are not filtered, they
+    are averaged over the samples within the pixel.
+*/
+#[derive(Clone)]
+pub struct Film {
+    x0: usize,
+    y0: usize,
+    width: usize,
+    height: usize,
+    colors: Vec<Vec3>,
+    weights: Vec<f64>,
+    features: Vec<Features>,
+    feature_samples: Vec<u32>,
+    // Only there when passes are added.
+    aovs: Option<Box<Aovs>>,
+}
+
+// Sums of the render passes per pixel, see AovSample.
+#[derive(Clone)]
+struct Aovs {
+    albedo: Vec<Vec3>,
+    normal: Vec<Vec3>,
+    samples: Vec<u32>,
+    depth: Vec<f64>,
+    hits: Vec<u32>,
+    // Squared distance to the center of the pixel with the object and material ids.
+    ids: Vec<(f64, Option<usize>, Option<usize>)>,
+    // Weighted like the colors of the film.
+    direct: Vec<Vec3>,
+    indirect: Vec<Vec3>,
+    lights: Vec<Vec<Vec3>>,
+    // Filtered like the colors of the film, the coverage is relative to its weights.
+    objects: Mattes,
+    materials: Mattes,
+}
+
+impl Aovs {
+    fn new(size: usize, lights: usize) -> Aovs {
+        Aovs {
+            albedo: vec![Vec3::zero(); size],
+            normal: vec![Vec3::zero(); size],
+            samples: vec![0; size],
+            depth: vec![0.0; size],
+            hits: vec![0; size],
+            ids: vec![(f64::INFINITY, None, None); size],
+            direct: vec![Vec3::zero(); size],
+            indirect: vec![Vec3::zero(); size],
+            lights: vec![vec![Vec3::zero(); size]; lights],
+            objects: Mattes::new(size),
+            materials: Mattes::new(size),
+        }
+    }
+
+    fn merge(&mut self, to: usize, other: &Aovs, from: usize) {
+        self.albedo[to] += other.albedo[from];
+        self.normal[to] += other.normal[from];
+        self.samples[to] += other.samples[from];
+        self.depth[to] += other.depth[from];
+        self.hits[to] += other.hits[from];
+        if other.ids[from].0 < self.ids[to].0 {
+            self.ids[to] = other.ids[from];
+        }
+        self.direct[to] += other.direct[from];
+        self.indirect[to] += other.indirect[from];
+        for (light, other_light) in self.lights.iter_mut().zip(other.lights.iter()) {
+            light[to] += other_light[from];
+        }
+        self.objects.merge(to, &other.objects, from);
+        self.materials.merge(to, &other.materials, from);
+    }
+
+    fn merge_names(&mut self, other: &Aovs) {
+        self.objects.merge_names(&other.objects);
+        self.materials.merge_names(&other.materials);
+    }
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film::tile(0, 0, width, height)
+    }
+
+    pub fn tile(x0: usize, y0: usize, width: usize, height: usize) -> Film {
+        Film {
+            x0,
+            y0,
+            width,
+            height,
+            colors: vec![Vec3::zero(); width * height],
+            weights: vec![0.0; width * height],
+            features: vec![Features::zero(); width * height],
+            feature_samples: vec![0; width * height],
+            aovs: None,
+        }
+    }
+
+    // Tile of the pixels that samples within pixel (x, y) can reach, clipped to the image.
+    pub fn around(x: usize, y: usize, filter: &Filter, image_size: (usize, usize)) -> Film {
+        let reach = (filter.radius() + 0.5).ceil() as usize;
+        let (x0, y0) = (x.saturating_sub(reach), y.saturating_sub(reach));
+        let x1 = (x + reach).min(image_size.0 - 1);
+        let y1 = (y + reach).min(image_size.1 - 1);
+        Film::tile(x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+    }
+
+    // Adds the sample at the image position (in pixels) to the pixels within the filter radius.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Vec3, filter: &Filter) {
+        for (index, weight) in self.footprint(x, y, filter) {
+            self.colors[index] += color * weight;
+            self.weights[index] += weight;
+        }
+    }
+
+    /*
+        Adds the passes of a sample, the light passes are filtered like the image and divided by
+        the same weights. Samples that are black in the image can be left out. The data passes
+        only go to the pixel the sample is in.
+    */
+    pub fn add_aovs(&mut self, x: f64, y: f64, sample: &AovSample, filter: &Filter) {
+        let footprint: Vec<(usize, f64)> = self.footprint(x, y, filter).collect();
+        let pixel = self.index(x as usize, y as usize);
+        let size = self.width * self.height;
+        let aovs = self
+            .aovs
+            .get_or_insert_with(|| Box::new(Aovs::new(size, sample.lights.len())));
+
+        if let Some(name) = &sample.object_name {
+            aovs.objects.add(name, &footprint);
+        }
+        if let Some(name) = &sample.material_name {
+            aovs.materials.add(name, &footprint);
+        }
+        for (index, weight) in footprint {
+            aovs.direct[index] += sample.direct * weight;
+            aovs.indirect[index] += sample.indirect * weight;
+            for (light, color) in aovs.lights.iter_mut().zip(sample.lights.iter()) {
+                light[index] += *color * weight;
+            }
+        }
+
+        aovs.albedo[pixel] += sample.albedo;
+        aovs.normal[pixel] += sample.normal;
+        aovs.samples[pixel] += 1;
+        if let Some(depth) = sample.depth {
+            aovs.depth[pixel] += depth;
+            aovs.hits[pixel] += 1;
+        }
+        // The ids come from the sample closest to the center of the pixel.
+        let (dx, dy) = (x.fract() - 0.5, y.fract() - 0.5);
+        let distance = dx * dx + dy * dy;
+        if distance < aovs.ids[pixel].0 {
+            aovs.ids[pixel] = (distance, sample.object, sample.material);
+        }
+    }
+
+    // Adds the features of a sample to the pixel (in image coordinates) it is in.
+    pub fn add_features(&mut self, x: usize, y: usize, features: &Features) {
+        let index = self.index(x, y);
+        self.features[index].add(features);
+        self.feature_samples[index] += 1;
+    }
+
+    pub fn merge(&mut self, tile: &Film) {
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let from = y * tile.width + x;
+                let to = self.index(tile.x0 + x, tile.y0 + y);
+                self.merge_pixel(to, tile, from);
+            }
+        }
+        self.merge_names(tile);
+    }
+
+    // Pixels of the rectangle in image coordinates as a film of its own, the passes included.
+    pub fn crop(&self, x0: usize, y0: usize, width: usize, height: usize) -> Film {
+        let mut crop = Film::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                crop.merge_pixel(y * width + x, self, self.index(x0 + x, y0 + y));
+            }
+        }
+        crop.merge_names(self);
+        crop
+    }
+
+    /*
+        Appends the sums and sample counts of every pixel, for a checkpoint of the render.
+        Values are stored at full precision, so a resumed render gives the same image. The
+        passes are not stored.
+    */
+    pub fn write_sums(&self, data: &mut Vec<u8>) {
+        let mut push = |v: f64| data.extend_from_slice(&v.to_le_bytes());
+        for i in 0..self.width * self.height {
+            let (color, features) = (self.colors[i], &self.features[i]);
+            for &v in [color.0, color.1, color.2, self.weights[i]].iter() {
+                push(v);
+            }
+            for &v in [features.albedo, features.normal].iter() {
+                push(v.0);
+                push(v.1);
+                push(v.2);
+            }
+            push(features.depth);
+            push(self.feature_samples[i] as f64);
+        }
+    }
+
+    // Replaces the sums with those written by write_sums, false if the size does not match.
+    pub fn read_sums(&mut self, data: &[u8]) -> bool {
+        const VALUES: usize = 12;
+        if data.len() != self.width * self.height * VALUES * 8 {
+            return false;
+        }
+        let values: Vec<f64> = data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+            .collect();
+        for (i, v) in values.chunks_exact(VALUES).enumerate() {
+            self.colors[i] = Vec3(v[0], v[1], v[2]);
+            self.weights[i] = v[3];
+            self.features[i] = Features {
+                albedo: Vec3(v[4], v[5], v[6]),
+                normal: Vec3(v[7], v[8], v[9]),
+                depth: v[10],
+            };
+            self.feature_samples[i] = v[11] as u32;
+        }
+        true
+    }
+
+    fn merge_pixel(&mut self, to: usize, other: &Film, from: usize) {
+        self.colors[to] += other.colors[from];
+        self.weights[to] += other.weights[from];
+        self.feature_samples[to] += other.feature_samples[from];
+        self.features[to].add(&other.features[from]);
+        if let Some(other_aovs) = &other.aovs {
+            let size = self.width * self.height;
+            let lights = other_aovs.lights.len();
+            self.aovs
+                .get_or_insert_with(|| Box::new(Aovs::new(size, lights)))
+                .merge(to, other_aovs, from);
+        }
+    }
+
+    // Only has to be done once per merged film, not for every pixel.
+    fn merge_names(&mut self, other: &Film) {
+        if let (Some(aovs), Some(other_aovs)) = (&mut self.aovs, &other.aovs) {
+            aovs.merge_names(other_aovs);
+        }
+    }
+
+    /*
+        Filtered color of the pixel in image coordinates. Filters with negative lobes can give
+        negative values, these are clamped to black.
+    */
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        let index = self.index(x, y);
+        let weight = self.weights[index];
+        if weight <= 0.0 {
+            return Vec3::zero();
+        }
+        let color = self.colors[index] / weight;
+        Vec3(color.0.max(0.0), color.1.max(0.0), color.2.max(0.0))
+    }
+
+    // Average features of the samples in the pixel, the normal is not normalized.
+    pub fn features(&self, x: usize, y: usize) -> Features {
+        let index = self.index(x, y);
+        let samples = self.feature_samples[index];
+        if samples == 0 {
+            return Features::miss();
+        }
+        let sum = &self.features[index];
+        let n = samples as f64;
+        Features {
+            albedo: sum.albedo / n,
+            normal: sum.normal / n,
+            depth: sum.depth / n,
+        }
+    }
+
+    /*
+        The image and its passes as float layers, light passes are named light_<index> in the
+        order of the scene lights, followed by sky. Ids start at 1, 0 is nothing hit. The ID
+        mattes come last, see manifests for their names.
+    */
+    pub fn layers(&self) -> Vec<Layer> {
+        let pixels = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)));
+        let mut layers = vec![Layer {
+            name: String::new(),
+            kind: LayerKind::Radiance,
+            values: pixels
+                .clone()
+                .flat_map(|(x, y)| rgb(self.pixel(x, y)))
+                .collect(),
+        }];
+        let aovs = match &self.aovs {
+            Some(aovs) => aovs,
+            None => return layers,
+        };
+
+        let layer = |name: &str, kind: LayerKind, values: Vec<f32>| Layer {
+            name: name.to_string(),
+            kind,
+            values,
+        };
+        let average = |sums: &[Vec3]| -> Vec<f32> {
+            sums.iter()
+                .zip(aovs.samples.iter())
+                .flat_map(|(&sum, &n)| rgb(sum / (n.max(1) as f64)))
+                .collect()
+        };
+        let filtered = |sums: &[Vec3]| -> Vec<f32> {
+            sums.iter()
+                .zip(self.weights.iter())
+                .flat_map(|(&sum, &w)| rgb(if w > 0.0 { sum / w } else { Vec3::zero() }))
+                .collect()
+        };
+        let id = |id: Option<usize>| id.map_or(0.0, |id| (id + 1) as f32);
+
+        layers.push(layer("albedo", LayerKind::Color, average(&aovs.albedo)));
+        layers.push(layer("normal", LayerKind::Vector, average(&aovs.normal)));
+        let depth = aovs.depth.iter().zip(aovs.hits.iter());
+        layers.push(layer(
+            "depth",
+            LayerKind::Depth,
+            depth
+                .map(|(&d, &n)| {
+                    if n > 0 {
+                        (d / n as f64) as f32
+                    } else {
+                        f32::INFINITY
+                    }
+                })
+                .collect(),
+        ));
+        layers.push(layer(
+            "object_id",
+            LayerKind::Id,
+            aovs.ids.iter().map(|ids| id(ids.1)).collect(),
+        ));
+        // Material ids already start at 1.
+        layers.push(layer(
+            "material_id",
+            LayerKind::Id,
+            aovs.ids
+                .iter()
+                .map(|ids| ids.2.unwrap_or(0) as f32)
+                .collect(),
+        ));
+        layers.push(layer("direct", LayerKind::Radiance, filtered(&aovs.direct)));
+        layers.push(layer(
+            "indirect",
+            LayerKind::Radiance,
+            filtered(&aovs.indirect),
+        ));
+        let count = aovs.lights.len();
+        for (i, light) in aovs.lights.iter().enumerate() {
+            let name = if i + 1 == count {
+                "sky".to_string()
+            } else {
+                format!("light_{}", i)
+            };
+            layers.push(layer(&name, LayerKind::Radiance, filtered(light)));
+        }
+        layers.extend(aovs.objects.layers(OBJECT_MATTE, &self.weights));
+        layers.extend(aovs.materials.layers(MATERIAL_MATTE, &self.weights));
+        layers
+    }
+
+    // Names in the ID mattes, empty when no passes were added.
+    pub fn manifests(&self) -> Vec<Manifest> {
+        match &self.aovs {
+            Some(aovs) => vec![
+                aovs.objects.manifest(OBJECT_MATTE),
+                aovs.materials.manifest(MATERIAL_MATTE),
+            ],
+            None => vec![],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // Pixels in the tile within the filter radius of the image position, with their weights.
+    fn footprint(&self, x: f64, y: f64, filter: &Filter) -> impl Iterator<Item = (usize, f64)> {
+        let radius = filter.radius();
+        // Range of pixels in the tile, the center of pixel i is at i + 0.5.
+        let range = |position: f64, start: usize, size: usize| {
+            let first = (position - 0.5 - radius).ceil().max(start as f64) as i64;
+            let last = (position - 0.5 + radius).floor() as i64;
+            let last = last.min((start + size) as i64 - 1);
+            first..=last
+        };
+        let (x_range, y_range) = (
+            range(x, self.x0, self.width),
+            range(y, self.y0, self.height),
+        );
+        let (x0, y0, width, filter) = (self.x0 as i64, self.y0 as i64, self.width as i64, *filter);
+
+        y_range
+            .flat_map(move |py| x_range.clone().map(move |px| (px, py)))
+            .filter_map(move |(px, py)| {
+                let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                let index = ((py - y0) * width + (px - x0)) as usize;
+                if weight == 0.0 {
+                    None
+                } else {
+                    Some((index, weight))
+                }
+            })
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.y0) * self.width + (x - self.x0)
+    }
+}
+
+fn rgb(color: Vec3) -> [f32; 3] {
+    [color.0 as f32, color.1 as f32, color.2 as f32]
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_filters() {
+        let mitchell = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        assert_approx_eq!(
+            mitchell.evaluate(0.0, 0.0),
+            (8.0f64 / 9.0).powi(2),
+            ASSERT_MARGIN
+        );
+        assert_approx_eq!(mitchell.evaluate(2.0, 0.0), 0.0, ASSERT_MARGIN);
+        // Negative lobe between 1 and 2 pixels.
+        assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+
+        let lanczos = Filter::Lanczos {
+            radius: 3.0,
+            tau: 3.0,
+        };
+        assert_approx_eq!(lanczos.evaluate(0.0, 0.0), 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(lanczos.evaluate(1.0, 0.0), 0.0, ASSERT_MARGIN);
+
+        let gaussian = Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        };
+        assert_approx_eq!(gaussian.evaluate(1.5, 0.0), 0.0, ASSERT_MARGIN);
+        assert_approx_eq!(
+            gaussian.evaluate(0.3, -0.2),
+            gaussian.evaluate(-0.3, 0.2),
+            ASSERT_MARGIN
+        );
+        assert_approx_eq!(
+            Filter::Tent { radius: 1.0 }.evaluate(0.5, 0.0),
+            0.5,
+            ASSERT_MARGIN
+        );
+    }
+
+    #[test]
+    fn test_film_splat() {
+        let filter = Filter::Tent { radius: 1.0 };
+        let mut film = Film::new(4, 4);
+
+        // A sample in the corner of pixel (1, 1) is shared with the pixels around that corner.
+        let mut tile = Film::around(1, 1, &filter, (4, 4));
+        tile.add_sample(2.0, 2.0, Vec3(1.0, 0.5, 0.25), &filter);
+        film.merge(&tile);
+
+        for &(x, y) in [(1, 1), (2, 1), (1, 2), (2, 2)].iter() {
+            let color = film.pixel(x, y);
+            assert_approx_eq!(color.0, 1.0, ASSERT_MARGIN);
+            assert_approx_eq!(color.2, 0.25, ASSERT_MARGIN);
+        }
+        assert_eq!(film.pixel(0, 0), Vec3::zero());
+        assert_eq!(film.pixel(3, 3), Vec3::zero());
+    }
+
+    #[test]
+    fn test_film_aovs() {
+        let filter = Filter::Box { radius: 0.5 };
+        let mut film = Film::new(2, 1);
+        let mut hit = AovSample::new(1);
+        hit.albedo = Vec3::fill(0.5);
+        hit.depth = Some(4.0);
+        hit.object = Some(3);
+        hit.material = Some(7);
+        hit.object_name = Some("sphere".into());
+        hit.direct = Vec3::fill(0.25);
+        hit.lights[0] = Vec3::fill(0.25);
+        let sky = AovSample {
+            direct: Vec3::fill(1.0),
+            ..AovSample::new(1)
+        };
+
+        // The hit is closer to the center of the pixel than the sky.
+        film.add_sample(0.4, 0.6, hit.direct, &filter);
+        film.add_aovs(0.4, 0.6, &hit, &filter);
+        film.add_sample(0.9, 0.1, sky.direct, &filter);
+        film.add_aovs(0.9, 0.1, &sky, &filter);
+
+        let layers = film.layers();
+        let names: Vec<&str> = layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "",
+                "albedo",
+                "normal",
+                "depth",
+                "object_id",
+                "material_id",
+                "direct",
+                "indirect",
+                "light_0",
+                "sky",
+                "CryptoObject00",
+                "CryptoObject01",
+                "CryptoObject02",
+                "CryptoMaterial00",
+                "CryptoMaterial01",
+                "CryptoMaterial02"
+            ]
+        );
+        let value = |name: &str, index: usize| {
+            let layer = layers.iter().find(|l| l.name == name).unwrap();
+            layer.values[index] as f64
+        };
+        assert_approx_eq!(value("", 0), 0.625, ASSERT_MARGIN);
+        assert_approx_eq!(value("direct", 0), 0.625, ASSERT_MARGIN);
+        assert_approx_eq!(value("light_0", 0), 0.125, ASSERT_MARGIN);
+        // Half of the weight of the pixel is on the sphere, the sky has no name.
+        assert_approx_eq!(value("CryptoObject00", 1), 0.5, ASSERT_MARGIN);
+        assert_approx_eq!(value("CryptoObject00", 3), 0.0, ASSERT_MARGIN);
+        assert_approx_eq!(value("albedo", 0), 0.25, ASSERT_MARGIN);
+        // Depth is averaged over the hits only.
+        assert_approx_eq!(value("depth", 0), 4.0, ASSERT_MARGIN);
+        assert_approx_eq!(value("object_id", 0), 4.0, ASSERT_MARGIN);
+        assert_approx_eq!(value("material_id", 0), 7.0, ASSERT_MARGIN);
+        // Nothing was rendered in the second pixel.
+        assert!(value("depth", 1).is_infinite());
+        assert_approx_eq!(value("object_id", 1), 0.0, ASSERT_MARGIN);
+    }
+}
@@ -0,0 +1,866 @@
+use super::bvh::{Aabb, Bvh};
+use super::camera::{Camera, Projection};
+use super::json::Json;
+use super::light::{Light, LightType};
+use super::material::{self, Ior, Material, MaterialType};
+use super::math::matrix::{Mat4, Transform};
+use super::math::vector::{Vec2, Vec3, Vec4, Vector};
+use super::mesh::Mesh;
+use super::scene::Scene;
+use super::shape::{self, Group, Object, ObjectType};
+use super::texture::Texture;
+
+use std::f64::consts::FRAC_PI_4;
+use std::fs;
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A;
+const GLB_BIN_CHUNK: u32 = 0x004E_4942;
+
+// Triangle modes of a primitive, the other modes are points and lines.
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+const MODE_TRIANGLE_FAN: usize = 6;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/*
+    Loads a glTF 2.0 file (.gltf with external or embedded buffers, or binary .glb) as a scene.
+    Nodes become instances of their mesh with the transform of the hierarchy, materials are
+    mapped onto the material types, the first camera is used and KHR_lights_punctual lights
+    are added to the lights of the scene. Also returns warnings for the parts that were skipped,
+    like textures that could not be decoded.
+*/
+pub fn load_scene(path: &Path) -> Result<(Scene, Vec<String>), Error> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&fs::read(path)?, directory)
+}
+
+// Scene from the contents of a .gltf or .glb file, relative uris are read from the directory.
+pub fn parse_scene(data: &[u8], directory: &Path) -> Result<(Scene, Vec<String>), Error> {
+    let (json, binary) = if data.starts_with(GLB_MAGIC) {
+        split_glb(data)?
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("Invalid glTF text".into()))?;
+        (Json::parse(text)?, None)
+    };
+
+    let version = json
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Json::as_str)
+        .unwrap_or("");
+    if !version.starts_with('2') {
+        return Err(invalid(format!("Unsupported glTF version '{}'", version)));
+    }
+
+    let buffers = list(&json, "buffers")
+        .iter()
+        .enumerate()
+        .map(
+            |(i, buffer)| match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => read_uri(uri, directory),
+                // Only the first buffer can refer to the binary chunk of a glb file.
+                None if i == 0 => binary
+                    .clone()
+                    .ok_or_else(|| invalid("glTF buffer without data".into())),
+                None => Err(invalid("glTF buffer without data".into())),
+            },
+        )
+        .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+    let mut document = Document {
+        json: &json,
+        buffers,
+        textures: vec![],
+    };
+    let mut warnings = vec![];
+    document.textures = list(&json, "textures")
+        .iter()
+        .map(|texture| document.texture(texture, directory, &mut warnings))
+        .collect();
+
+    let materials: Vec<(Arc<Material>, Vec3)> = list(&json, "materials")
+        .iter()
+        .map(|m| document.material(m))
+        .collect();
+    let meshes = list(&json, "meshes")
+        .iter()
+        .map(|m| document.mesh(m, &materials).map(Arc::new))
+        .collect::<Result<Vec<Arc<Group>>, Error>>()?;
+
+    // The default scene, or the nodes without a parent when the file has no scenes.
+    let nodes = list(&json, "nodes");
+    let roots: Vec<usize> =
+        match list(&json, "scenes").get(json.get("scene").and_then(Json::as_usize).unwrap_or(0)) {
+            Some(root) => indices(root.get("nodes")),
+            None => {
+                let children: Vec<usize> = nodes
+                    .iter()
+                    .flat_map(|n| indices(n.get("children")))
+                    .collect();
+                (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+            }
+        };
+
+    let mut objects = vec![];
+    let mut lights = vec![];
+    let mut camera = None;
+    let mut stack: Vec<(usize, Mat4, usize)> =
+        roots.iter().map(|&i| (i, Mat4::identity(), 0)).collect();
+    while let Some((index, parent, depth)) = stack.pop() {
+        let node = nodes
+            .get(index)
+            .ok_or_else(|| invalid(format!("glTF node {} does not exist", index)))?;
+        // A valid hierarchy is a tree, so no path is longer than the number of nodes.
+        if depth > nodes.len() {
+            return Err(invalid("glTF node hierarchy has a cycle".into()));
+        }
+        let world = local_matrix(node) * parent;
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            let geometry = meshes
+                .get(mesh)
+                .ok_or_else(|| invalid(format!("glTF mesh {} does not exist", mesh)))?;
+            // Nodes scaled to zero can not be inverted and are not visible anyway.
+            if world.inverse().is_some() {
+                let instance = shape::instance(geometry, Transform::new(world));
+                objects.push(match node.get("name").and_then(Json::as_str) {
+                    Some(name) => shape::with_name(instance, name),
+                    None => instance,
+                });
+            }
+        }
+        if camera.is_none() {
+            camera = node
+                .get("camera")
+                .and_then(Json::as_usize)
+                .and_then(|i| list(&json, "cameras").get(i))
+                .and_then(|c| document.camera(c, &world));
+        }
+        if let Some(light) = node
+            .get("extensions")
+            .and_then(|e| e.get("KHR_lights_punctual"))
+            .and_then(|e| e.get("light"))
+            .and_then(Json::as_usize)
+            .and_then(|i| {
+                let lights = json
+                    .get("extensions")
+                    .and_then(|e| e.get("KHR_lights_punctual"));
+                list_of(lights, "lights").get(i)
+            })
+        {
+            lights.push(light_at(light, &world));
+        }
+
+        for child in indices(node.get("children")) {
+            stack.push((child, world, depth + 1));
+        }
+    }
+
+    let camera = camera.unwrap_or_else(|| framing_camera(&objects));
+    let scene = Scene {
+        objects,
+        lights,
+        camera,
+        bvh: Bvh::default(),
+        animation: None,
+    };
+    Ok((scene, warnings))
+}
+
+fn ratio() -> f64 {
+    super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64
+}
+
+fn list<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    list_of(Some(json), key)
+}
+
+fn list_of<'a>(json: Option<&'a Json>, key: &str) -> &'a [Json] {
+    json.and_then(|j| j.get(key))
+        .and_then(Json::as_array)
+        .unwrap_or(&[])
+}
+
+fn indices(json: Option<&Json>) -> Vec<usize> {
+    json.and_then(Json::as_array)
+        .map(|items| items.iter().filter_map(Json::as_usize).collect())
+        .unwrap_or_default()
+}
+
+fn number(json: &Json, key: &str, default: f64) -> f64 {
+    json.get(key).and_then(Json::as_f64).unwrap_or(default)
+}
+
+fn numbers(json: &Json, key: &str, default: &[f64]) -> Vec<f64> {
+    json.get(key)
+        .and_then(Json::as_numbers)
+        .filter(|n| n.len() == default.len())
+        .unwrap_or_else(|| default.to_vec())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let b = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// The json and binary chunk of a glb file.
+fn split_glb(data: &[u8]) -> Result<(Json, Option<Vec<u8>>), Error> {
+    if u32_at(data, 4) != Some(2) {
+        return Err(invalid("Unsupported glb version".into()));
+    }
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while let (Some(length), Some(kind)) = (u32_at(data, offset), u32_at(data, offset + 4)) {
+        let start = offset + 8;
+        let chunk = data
+            .get(start..start + length as usize)
+            .ok_or_else(|| invalid("glb chunk is cut off".into()))?;
+        match kind {
+            GLB_JSON_CHUNK if json.is_none() => {
+                let text =
+                    std::str::from_utf8(chunk).map_err(|_| invalid("Invalid glb json".into()))?;
+                json = Some(Json::parse(text)?);
+            }
+            GLB_BIN_CHUNK if binary.is_none() => binary = Some(chunk.to_vec()),
+            _ => {}
+        }
+        offset = start + length as usize;
+    }
+
+    let json = json.ok_or_else(|| invalid("glb file has no json chunk".into()))?;
+    Ok((json, binary))
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|&c| c != b'=' && !c.is_ascii_whitespace())
+    {
+        let v = value(c).ok_or_else(|| invalid("Invalid base64 data".into()))?;
+        bits = (bits << 6) | v as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+// Uris are percent encoded, like %20 for spaces in file names.
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Contents of a data uri or of a file relative to the glTF file.
+fn read_uri(uri: &str, directory: &Path) -> Result<Vec<u8>, Error> {
+    if uri.starts_with("data:") {
+        let (_, data) = uri
+            .split_once(";base64,")
+            .ok_or_else(|| invalid("Only base64 data uris are supported".into()))?;
+        return decode_base64(data);
+    }
+    fs::read(directory.join(decode_percent(uri)))
+}
+
+// Transform of the node (matrix or translation, rotation and scale) relative to its parent.
+fn local_matrix(node: &Json) -> Mat4 {
+    if let Some(m) = node.get("matrix").and_then(Json::as_numbers) {
+        if m.len() == 16 {
+            // glTF stores column major matrices for column vectors, which are the rows of
+            // the matrix for row vectors.
+            let row = |r: usize| Vec4(m[r * 4], m[r * 4 + 1], m[r * 4 + 2], m[r * 4 + 3]);
+            return Mat4(row(0), row(1), row(2), row(3));
+        }
+    }
+
+    let t = numbers(node, "translation", &[0.0, 0.0, 0.0]);
+    let r = numbers(node, "rotation", &[0.0, 0.0, 0.0, 1.0]);
+    let s = numbers(node, "scale", &[1.0, 1.0, 1.0]);
+    Mat4::scale(Vec3(s[0], s[1], s[2]))
+        * Mat4::rotation_quaternion(Vec4(r[0], r[1], r[2], r[3]))
+        * Mat4::translation(Vec3(t[0], t[1], t[2]))
+}
+
+fn light_at(light: &Json, world: &Mat4) -> Light {
+    let color = numbers(light, "color", &[1.0, 1.0, 1.0]);
+    // Lights shine along -z of their node.
+    let direction = Vec3::normalize(world.transform_vector(Vec3(0.0, 0.0, -1.0)));
+    let spot = light.get("spot");
+    let light_type = match light.get("type").and_then(Json::as_str) {
+        Some("directional") => LightType::Directional { direction },
+        Some("spot") => LightType::Spot {
+            direction,
+            inner_angle: spot.map_or(0.0, |s| number(s, "innerConeAngle", 0.0)),
+            outer_angle: spot.map_or(FRAC_PI_4, |s| number(s, "outerConeAngle", FRAC_PI_4)),
+        },
+        _ => LightType::Point,
+    };
+
+    Light {
+        position: world.transform_point(Vec3::zero()),
+        color: Vec3(color[0], color[1], color[2]),
+        intensity: number(light, "intensity", 1.0),
+        light_type,
+    }
+}
+
+// Camera looking at the center of the objects from the front, for files without a camera.
+fn framing_camera(objects: &[Object]) -> Camera {
+    let bounds = objects
+        .iter()
+        .filter_map(shape::bounds)
+        .fold(Aabb::empty(), |total, b| Aabb::union(&total, &b));
+    let (center, radius) = if bounds.min.0 <= bounds.max.0 {
+        (
+            (bounds.min + bounds.max) / 2.0,
+            (bounds.max - bounds.min).length() / 2.0,
+        )
+    } else {
+        (Vec3::zero(), 1.0)
+    };
+
+    let fov: f64 = 40.0;
+    let distance = radius / (fov.to_radians() / 2.0).sin();
+    let position = center + Vec3(0.0, 0.0, distance);
+    Camera::set(position, center, Vec3::up(), fov, ratio(), 0.0, distance)
+}
+
+// Parsed json with the data of the buffers and the decoded textures.
+struct Document<'a> {
+    json: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    textures: Vec<Option<Arc<Texture>>>,
+}
+
+impl<'a> Document<'a> {
+    // Raw bytes of an image, from a uri or a buffer view.
+    fn image_data(&self, image: &Json, directory: &Path) -> Result<Vec<u8>, Error> {
+        if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+            return read_uri(uri, directory);
+        }
+        let view = image
+            .get("bufferView")
+            .and_then(Json::as_usize)
+            .ok_or_else(|| invalid("glTF image without data".into()))?;
+        let (data, _) = self.buffer_view(view)?;
+        Ok(data.to_vec())
+    }
+
+    fn texture(
+        &self,
+        texture: &Json,
+        directory: &Path,
+        warnings: &mut Vec<String>,
+    ) -> Option<Arc<Texture>> {
+        let image = texture
+            .get("source")
+            .and_then(Json::as_usize)
+            .and_then(|i| list(self.json, "images").get(i))?;
+        let name = image
+            .get("uri")
+            .and_then(Json::as_str)
+            .map_or("embedded image", |uri| {
+                if uri.starts_with("data:") {
+                    "data uri"
+                } else {
+                    uri
+                }
+            });
+
+        let decoded = self
+            .image_data(image, directory)
+            .and_then(|data| Texture::decode_png(Cursor::new(data)));
+        match decoded {
+            Ok(texture) => Some(Arc::new(texture)),
+            // Only png images can be decoded, the material keeps its factor.
+            Err(error) => {
+                warnings.push(format!("Skipped glTF texture {}: {}", name, error));
+                None
+            }
+        }
+    }
+
+    /*
+        Maps the metallic-roughness material on the material types: transmissive materials
+        become dielectrics, metallic materials metal with the roughness as fuzz and the rest
+        lambertian. Also returns the base color, which scales the vertex colors.
+    */
+    fn material(&self, json: &Json) -> (Arc<Material>, Vec3) {
+        let pbr = json.get("pbrMetallicRoughness");
+        let factor = pbr.map_or(vec![1.0; 4], |p| numbers(p, "baseColorFactor", &[1.0; 4]));
+        let base = Vec3(factor[0], factor[1], factor[2]);
+        let metallic = pbr.map_or(1.0, |p| number(p, "metallicFactor", 1.0));
+        let roughness = pbr.map_or(1.0, |p| number(p, "roughnessFactor", 1.0));
+
+        let extensions = json.get("extensions");
+        let transmission = extensions
+            .and_then(|e| e.get("KHR_materials_transmission"))
+            .map_or(0.0, |t| number(t, "transmissionFactor", 0.0));
+        let ior = extensions
+            .and_then(|e| e.get("KHR_materials_ior"))
+            .map_or(1.5, |i| number(i, "ior", 1.5));
+
+        let material_type = if transmission >= 0.5 {
+            MaterialType::Dielectric {
+                refract: Ior::Constant(ior),
+            }
+        } else if metallic >= 0.5 {
+            MaterialType::Metal { fuzz: roughness }
+        } else {
+            MaterialType::Lambertian
+        };
+
+        let texture = pbr
+            .and_then(|p| p.get("baseColorTexture"))
+            .and_then(|t| t.get("index"))
+            .and_then(Json::as_usize)
+            .and_then(|i| self.textures.get(i).cloned().flatten());
+        let material = match texture {
+            Some(texture) => material::textured(base, &texture, material_type),
+            None => material::new(base, material_type),
+        };
+        let material = match json.get("name").and_then(Json::as_str) {
+            Some(name) => material::with_name(material, name),
+            None => material,
+        };
+        (material, base)
+    }
+
+    // Bytes of a buffer view and its stride (0 when the elements are packed).
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], usize), Error> {
+        let view = list(self.json, "bufferViews")
+            .get(index)
+            .ok_or_else(|| invalid(format!("glTF buffer view {} does not exist", index)))?;
+        let buffer = view
+            .get("buffer")
+            .and_then(Json::as_usize)
+            .and_then(|b| self.buffers.get(b))
+            .ok_or_else(|| invalid(format!("glTF buffer view {} has no buffer", index)))?;
+        let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let length = view.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid(format!("glTF buffer view {} is out of range", index)))?;
+        let stride = view.get("byteStride").and_then(Json::as_usize).unwrap_or(0);
+        Ok((data, stride))
+    }
+
+    // Values of an accessor as floats, with the number of components per element.
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), Error> {
+        let accessor = list(self.json, "accessors")
+            .get(index)
+            .ok_or_else(|| invalid(format!("glTF accessor {} does not exist", index)))?;
+        let count = accessor.get("count").and_then(Json::as_usize).unwrap_or(0);
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            kind => {
+                return Err(invalid(format!(
+                    "Unsupported glTF accessor type {:?}",
+                    kind
+                )))
+            }
+        };
+        let component_type = accessor.get("componentType").and_then(Json::as_usize);
+        let size = match component_type {
+            Some(5120) | Some(5121) => 1,
+            Some(5122) | Some(5123) => 2,
+            Some(5125) | Some(5126) => 4,
+            kind => {
+                return Err(invalid(format!(
+                    "Unsupported glTF component type {:?}",
+                    kind
+                )))
+            }
+        };
+        let normalized = accessor.get("normalized") == Some(&Json::Bool(true));
+
+        let out_of_range = || invalid(format!("glTF accessor {} is out of range", index));
+        let element_size = size * components;
+
+        // Accessors without a buffer view are all zero, they can not be larger than the buffers.
+        let view = match accessor.get("bufferView").and_then(Json::as_usize) {
+            Some(view) => view,
+            None => {
+                let bytes: usize = self.buffers.iter().map(Vec::len).sum();
+                if count > bytes / element_size {
+                    return Err(out_of_range());
+                }
+                return Ok((vec![0.0; count * components], components));
+            }
+        };
+        let (data, stride) = self.buffer_view(view)?;
+        let offset = accessor
+            .get("byteOffset")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let stride = if stride == 0 { element_size } else { stride };
+
+        // The last element has to be in the buffer view, which also limits the allocation.
+        if count > 0 {
+            let end = (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(offset))
+                .and_then(|last| last.checked_add(element_size));
+            if end.filter(|&end| end <= data.len()).is_none() {
+                return Err(out_of_range());
+            }
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = offset + element * stride + component * size;
+                let b = &data[at..at + size];
+                let value = match component_type {
+                    Some(5120) if normalized => (b[0] as i8 as f64 / 127.0).max(-1.0),
+                    Some(5120) => b[0] as i8 as f64,
+                    Some(5121) if normalized => b[0] as f64 / 255.0,
+                    Some(5121) => b[0] as f64,
+                    Some(5122) if normalized => {
+                        (i16::from_le_bytes([b[0], b[1]]) as f64 / 32767.0).max(-1.0)
+                    }
+                    Some(5122) => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Some(5123) if normalized => u16::from_le_bytes([b[0], b[1]]) as f64 / 65535.0,
+                    Some(5123) => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Some(5125) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                };
+                values.push(value);
+            }
+        }
+        Ok((values, components))
+    }
+
+    // Accessor with at least three components per element as vectors (alpha is dropped).
+    fn vectors(&self, index: usize) -> Result<Vec<Vec3>, Error> {
+        let (values, components) = self.accessor(index)?;
+        if components < 3 {
+            return Err(invalid(format!("glTF accessor {} is not a vector", index)));
+        }
+        Ok(values
+            .chunks(components)
+            .map(|v| Vec3(v[0], v[1], v[2]))
+            .collect())
+    }
+
+    // Every triangle primitive of the mesh as a mesh object with its material.
+    fn mesh(&self, json: &Json, materials: &[(Arc<Material>, Vec3)]) -> Result<Group, Error> {
+        let mut objects = vec![];
+        for primitive in list_of(Some(json), "primitives") {
+            let mode = primitive
+                .get("mode")
+                .and_then(Json::as_usize)
+                .unwrap_or(MODE_TRIANGLES);
+            if ![MODE_TRIANGLES, MODE_TRIANGLE_STRIP, MODE_TRIANGLE_FAN].contains(&mode) {
+                continue;
+            }
+            let attribute = |name: &str| {
+                primitive
+                    .get("attributes")
+                    .and_then(|a| a.get(name))
+                    .and_then(Json::as_usize)
+            };
+
+            let positions = self.vectors(
+                attribute("POSITION")
+                    .ok_or_else(|| invalid("glTF primitive has no positions".into()))?,
+            )?;
+            let order: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+                Some(accessor) => self
+                    .accessor(accessor)?
+                    .0
+                    .iter()
+                    .map(|&i| i as usize)
+                    .collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if order.iter().any(|&i| i >= positions.len()) {
+                return Err(invalid("glTF primitive refers to a missing vertex".into()));
+            }
+            let triangles: Vec<[usize; 3]> = match mode {
+                MODE_TRIANGLE_STRIP => (2..order.len())
+                    .map(|i| {
+                        // Every other triangle is flipped to keep the winding.
+                        if i % 2 == 0 {
+                            [order[i - 2], order[i - 1], order[i]]
+                        } else {
+                            [order[i - 1], order[i - 2], order[i]]
+                        }
+                    })
+                    .collect(),
+                MODE_TRIANGLE_FAN => (2..order.len())
+                    .map(|i| [order[0], order[i - 1], order[i]])
+                    .collect(),
+                _ => order.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            };
+
+            // glTF uvs start at the top of the image, here v goes up.
+            let uvs = match attribute("TEXCOORD_0") {
+                Some(accessor) => self
+                    .accessor(accessor)?
+                    .0
+                    .chunks(2)
+                    .map(|uv| Vec2(uv[0], 1.0 - uv[1]))
+                    .collect(),
+                None => vec![],
+            };
+            // Attributes are indexed by vertex, so they need a value for every position.
+            let vertices = positions.len();
+            let matching = |name: &str, count: usize| {
+                if count == vertices {
+                    Ok(())
+                } else {
+                    Err(invalid(format!(
+                        "glTF primitive has {} {} values for {} positions",
+                        count, name, vertices
+                    )))
+                }
+            };
+            if !uvs.is_empty() {
+                matching("TEXCOORD_0", uvs.len())?;
+            }
+            let mut mesh = match attribute("NORMAL") {
+                Some(accessor) => {
+                    let normals = self.vectors(accessor)?;
+                    matching("NORMAL", normals.len())?;
+                    Mesh::new(positions, normals, uvs, triangles)
+                }
+                None => Mesh::smooth(positions, uvs, triangles),
+            };
+
+            let (material, base) = match primitive.get("material").and_then(Json::as_usize) {
+                Some(i) => materials
+                    .get(i)
+                    .cloned()
+                    .ok_or_else(|| invalid(format!("glTF material {} does not exist", i)))?,
+                // Primitives without a material are plain gray.
+                None => (
+                    material::new(Vec3::fill(0.8), MaterialType::Lambertian),
+                    Vec3::fill(0.8),
+                ),
+            };
+            if let Some(accessor) = attribute("COLOR_0") {
+                let colors = self.vectors(accessor)?;
+                matching("COLOR_0", colors.len())?;
+                mesh.colors = colors.into_iter().map(|color| color * base).collect();
+            }
+
+            objects.push(shape::new(
+                Vec3::zero(),
+                ObjectType::Mesh {
+                    mesh: Arc::new(mesh),
+                },
+                &material,
+            ));
+        }
+        Ok(Group::new(objects))
+    }
+
+    fn camera(&self, camera: &Json, world: &Mat4) -> Option<Camera> {
+        // Cameras look along -z with y up.
+        let position = world.transform_point(Vec3::zero());
+        let forward = Vec3::normalize(world.transform_vector(Vec3(0.0, 0.0, -1.0)));
+        let up = world.transform_vector(Vec3::up());
+        let set = |fov: f64| Camera::set(position, position + forward, up, fov, ratio(), 0.0, 1.0);
+
+        if let Some(perspective) = camera.get("perspective") {
+            let yfov = number(perspective, "yfov", 0.8);
+            return Some(set(yfov.to_degrees()));
+        }
+        // The magnification is half the size of the view, the width follows from the image.
+        let orthographic = camera.get("orthographic")?;
+        let ymag = number(orthographic, "ymag", 1.0);
+        Some(set(45.0).with_projection(Projection::Orthographic { height: 2.0 * ymag }))
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert!(decode_base64("T!==").is_err());
+    }
+
+    // Binary glTF file with the json and the binary chunk.
+    fn glb(json: &str, binary: &[u8]) -> Vec<u8> {
+        // Chunks are padded to 4 bytes.
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+
+        let mut glb = GLB_MAGIC.to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+        for (kind, chunk) in [(GLB_JSON_CHUNK, &json[..]), (GLB_BIN_CHUNK, binary)].iter() {
+            glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&kind.to_le_bytes());
+            glb.extend_from_slice(chunk);
+        }
+        glb
+    }
+
+    // Floats as the little endian bytes of a buffer.
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_glb() {
+        // One triangle in the binary chunk, used by a child node moved up by its parent.
+        let binary = floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [
+                {"translation": [0, 2, 0], "children": [1, 2]},
+                {"mesh": 0, "scale": [2, 2, 2]},
+                {"extensions": {"KHR_lights_punctual": {"light": 0}}}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+            "materials": [{"pbrMetallicRoughness": {"metallicFactor": 0}}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "buffers": [{"byteLength": 36}],
+            "extensions": {"KHR_lights_punctual": {"lights": [{"type": "point", "intensity": 5}]}}
+        }"#;
+
+        let (mut scene, warnings) = parse_scene(&glb(json, &binary), Path::new("")).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.lights.len(), 1);
+        assert_approx_eq!(scene.lights[0].position.1, 2.0, ASSERT_MARGIN);
+        assert_approx_eq!(scene.lights[0].intensity, 5.0, ASSERT_MARGIN);
+
+        // The triangle is scaled by the child and moved by the parent.
+        scene.build_bvh();
+        let mut ray = Ray::new(Vec3(0.5, 2.5, 1.0), Vec3(0.0, 0.0, -1.0));
+        scene.intersect(&mut ray, 0.001);
+        assert_approx_eq!(ray.travel_distance, 1.0, ASSERT_MARGIN);
+        let mut miss = Ray::new(Vec3(0.5, 4.5, 1.0), Vec3(0.0, 0.0, -1.0));
+        scene.intersect(&mut miss, 0.001);
+        assert!(miss.is_intersected.is_none());
+    }
+
+    #[test]
+    fn test_accessor_out_of_range() {
+        let binary = floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        // A triangle with the count of its positions replaced.
+        let load = |accessor: &str| {
+            let json = format!(
+                r#"{{
+                    "asset": {{"version": "2.0"}},
+                    "nodes": [{{"mesh": 0}}],
+                    "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}],
+                    "accessors": [{}],
+                    "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+                    "buffers": [{{"byteLength": 36}}]
+                }}"#,
+                accessor
+            );
+            parse_scene(&glb(&json, &binary), Path::new(""))
+        };
+
+        let vec3 = r#""componentType": 5126, "type": "VEC3""#;
+        assert!(load(&format!(r#"{{"bufferView": 0, "count": 3, {}}}"#, vec3)).is_ok());
+        for count in ["4", "1e15", "1e19"].iter() {
+            let accessor = format!(r#"{{"bufferView": 0, "count": {}, {}}}"#, count, vec3);
+            assert!(load(&accessor).is_err());
+            // Zeros without a buffer view can not be larger than the buffers either.
+            assert!(load(&format!(r#"{{"count": {}, {}}}"#, count, vec3)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_attribute_counts() {
+        // Three positions followed by the normals of only two of them.
+        let mut binary = floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        binary.extend(floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+        let json = |normals: usize| {
+            format!(
+                r#"{{
+                    "asset": {{"version": "2.0"}},
+                    "nodes": [{{"mesh": 0}}],
+                    "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+                    "accessors": [
+                        {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                        {{"bufferView": 1, "componentType": 5126, "count": {}, "type": "VEC3"}}
+                    ],
+                    "bufferViews": [
+                        {{"buffer": 0, "byteLength": 36}},
+                        {{"buffer": 0, "byteOffset": 36, "byteLength": 24}}
+                    ],
+                    "buffers": [{{"byteLength": 60}}]
+                }}"#,
+                normals
+            )
+        };
+
+        assert!(parse_scene(&glb(&json(2), &binary), Path::new("")).is_err());
+        // With one position less the counts match.
+        let shorter = json(2).replace(r#""count": 3"#, r#""count": 2"#);
+        assert!(parse_scene(&glb(&shorter, &binary), Path::new("")).is_ok());
+    }
+}
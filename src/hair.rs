@@ -0,0 +1,370 @@
+use super::math::vector::Vec3;
+
+use std::f64::consts::{LN_2, PI};
+
+/*
+    Hair scattering model of Chiang et al. 2016 ("A Practical and Controllable Hair and Fur
+    Model for Production Path Tracing"), following the implementation in pbrt-v3.
+
+    Directions are in the frame of the fiber: x along the fiber, z facing the viewer across the
+    fiber and y = cross(z, x). h is the offset of the hit across the fiber, between -1 and 1.
+    The lobes are R (p = 0), TT (p = 1), TRT (p = 2) and the sum of all longer paths.
+*/
+
+const P_MAX: usize = 3;
+const SQRT_PI_OVER_8: f64 = 0.626_657_069;
+
+pub struct HairBsdf {
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Vec3,
+    // Longitudinal variance per lobe.
+    v: [f64; P_MAX + 1],
+    // Azimuthal logistic scale.
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+fn sqr(x: f64) -> f64 {
+    x * x
+}
+
+fn exp(v: Vec3) -> Vec3 {
+    Vec3(v.0.exp(), v.1.exp(), v.2.exp())
+}
+
+// Single number for choosing between the lobes, channels can be colors or wavelengths.
+fn average(v: Vec3) -> f64 {
+    (v.0 + v.1 + v.2) / 3.0
+}
+
+// Modified Bessel function of the first kind.
+fn i0(x: f64) -> f64 {
+    let mut val = 0.0;
+    let mut x2i = 1.0;
+    let mut ifact = 1.0;
+    let mut i4 = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= i as f64;
+        }
+        val += x2i / (i4 * sqr(ifact));
+        x2i *= x * x;
+        i4 *= 4.0;
+    }
+    val
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+// Longitudinal scattering.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_i0(a) - b - 1.0 / v + LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Fresnel reflectance of a dielectric, light coming from outside.
+fn fresnel(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let sin_theta_t = safe_sqrt(1.0 - cos_theta_i * cos_theta_i) / eta;
+    if sin_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+// Attenuation of every lobe.
+fn ap(cos_theta_o: f64, eta: f64, h: f64, t: Vec3) -> [Vec3; P_MAX + 1] {
+    let cos_gamma_o = safe_sqrt(1.0 - h * h);
+    let f = fresnel(cos_theta_o * cos_gamma_o, eta);
+
+    let r = Vec3::fill(f);
+    let tt = t * ((1.0 - f) * (1.0 - f));
+    let trt = tt * t * f;
+    let rest = trt * t * f / (Vec3::fill(1.0) - t * f);
+    [r, tt, trt, rest]
+}
+
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    2.0 * p as f64 * gamma_t - 2.0 * gamma_o + p as f64 * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * sqr(1.0 + (-x / s).exp()))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// Azimuthal scattering.
+fn np(phi_delta: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_delta - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+// Angles of a direction in the frame of the fiber.
+fn angles(w: Vec3) -> (f64, f64, f64) {
+    let sin_theta = w.0;
+    (
+        sin_theta,
+        safe_sqrt(1.0 - sin_theta * sin_theta),
+        w.2.atan2(w.1),
+    )
+}
+
+// Absorption that gives the color for multiple scattering, with the azimuthal roughness.
+pub fn sigma_a_from_color(color: Vec3, beta_n: f64) -> Vec3 {
+    let factor = 5.969 - 0.215 * beta_n + 2.532 * sqr(beta_n) - 10.73 * beta_n.powi(3)
+        + 5.574 * beta_n.powi(4)
+        + 0.245 * beta_n.powi(5);
+    let sigma = |c: f64| sqr(c.max(1e-4).ln() / factor);
+    Vec3(sigma(color.0), sigma(color.1), sigma(color.2))
+}
+
+impl HairBsdf {
+    /*
+        beta_m and beta_n are the longitudinal and azimuthal roughness (0 to 1), alpha is the
+        tilt of the cuticle scales in degrees.
+    */
+    pub fn new(h: f64, eta: f64, sigma_a: Vec3, beta_m: f64, beta_n: f64, alpha: f64) -> HairBsdf {
+        let v0 = sqr(0.726 * beta_m + 0.812 * sqr(beta_m) + 3.7 * beta_m.powi(20));
+        let s = SQRT_PI_OVER_8 * (0.265 * beta_n + 1.194 * sqr(beta_n) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = alpha.to_radians().sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0 - sqr(sin_2k_alpha[0]));
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = sqr(cos_2k_alpha[i - 1]) - sqr(sin_2k_alpha[i - 1]);
+        }
+
+        HairBsdf {
+            h,
+            gamma_o: safe_asin(h),
+            eta,
+            sigma_a,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // Outgoing angle tilted by the scales for the lobe.
+    fn tilt(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = (&self.sin_2k_alpha, &self.cos_2k_alpha);
+        let (sin_op, cos_op) = match p {
+            0 => (
+                sin_theta_o * cos[1] - cos_theta_o * sin[1],
+                cos_theta_o * cos[1] + sin_theta_o * sin[1],
+            ),
+            1 => (
+                sin_theta_o * cos[0] + cos_theta_o * sin[0],
+                cos_theta_o * cos[0] - sin_theta_o * sin[0],
+            ),
+            2 => (
+                sin_theta_o * cos[2] + cos_theta_o * sin[2],
+                cos_theta_o * cos[2] - sin_theta_o * sin[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_op, cos_op.abs())
+    }
+
+    // Transmittance through the fiber and the refracted azimuth, for the outgoing direction.
+    fn inside(&self, sin_theta_o: f64, cos_theta_o: f64) -> (Vec3, f64) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sqr(sin_theta_t));
+        let etap = (self.eta * self.eta - sqr(sin_theta_o)).sqrt() / cos_theta_o;
+        let sin_gamma_t = self.h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - sqr(sin_gamma_t));
+        let t = exp(-self.sigma_a * (2.0 * cos_gamma_t / cos_theta_t));
+        (t, safe_asin(sin_gamma_t))
+    }
+
+    fn ap_pdf(&self, cos_theta_o: f64, t: Vec3) -> [f64; P_MAX + 1] {
+        let ap = ap(cos_theta_o, self.eta, self.h, t);
+        let sum: f64 = ap.iter().map(|a| average(*a)).sum();
+        let mut pdf = [0.0; P_MAX + 1];
+        for (pdf, a) in pdf.iter_mut().zip(ap.iter()) {
+            *pdf = average(*a) / sum;
+        }
+        pdf
+    }
+
+    /*
+        BSDF times the cosine to the surface (the z axis). pbrt divides this by the cosine,
+        here it is left in because the renderer does not apply it.
+    */
+    pub fn f_cos(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (t, gamma_t) = self.inside(sin_theta_o, cos_theta_o);
+        let phi_delta = phi_i - phi_o;
+        let ap = ap(cos_theta_o, self.eta, self.h, t);
+
+        let mut sum = Vec3::zero();
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            sum += *ap
+                * (mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                    * np(phi_delta, p, self.s, self.gamma_o, gamma_t));
+        }
+        sum + ap[P_MAX]
+            * (mp(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                self.v[P_MAX],
+            ) / (2.0 * PI))
+    }
+
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (t, gamma_t) = self.inside(sin_theta_o, cos_theta_o);
+        let phi_delta = phi_i - phi_o;
+        let ap_pdf = self.ap_pdf(cos_theta_o, t);
+
+        let mut pdf = 0.0;
+        for (p, ap_pdf) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                * ap_pdf
+                * np(phi_delta, p, self.s, self.gamma_o, gamma_t);
+        }
+        pdf + mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * ap_pdf[P_MAX]
+            / (2.0 * PI)
+    }
+
+    // Samples an incoming direction from four uniform numbers, returns it with f_cos / pdf.
+    pub fn sample(&self, wo: Vec3, u: [f64; 4]) -> Option<(Vec3, Vec3)> {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (t, gamma_t) = self.inside(sin_theta_o, cos_theta_o);
+        let ap_pdf = self.ap_pdf(cos_theta_o, t);
+
+        // Lobe.
+        let mut p = 0;
+        let mut cdf = ap_pdf[0];
+        while p < P_MAX && u[0] >= cdf {
+            p += 1;
+            cdf += ap_pdf[p];
+        }
+
+        // Longitudinal angle.
+        let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let v = self.v[p];
+        let u1 = u[1].max(1e-5);
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - sqr(cos_theta));
+        let cos_phi = (2.0 * PI * u[2]).cos();
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+
+        // Azimuthal angle.
+        let phi_delta = if p < P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(u[3], self.s, -PI, PI)
+        } else {
+            2.0 * PI * u[3]
+        };
+        let phi_i = phi_o + phi_delta;
+        let wi = Vec3(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+        Some((wi, self.f_cos(wo, wi) / pdf))
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate rand;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Without absorption no energy is lost, so the sampling weights average to about 1.
+    #[test]
+    fn test_hair_white_furnace() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = 20000;
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let h = rng.gen_range(-1.0, 1.0);
+            let bsdf = HairBsdf::new(h, 1.55, Vec3::zero(), 0.3, 0.3, 2.0);
+            let theta_o: f64 = rng.gen_range(-1.2, 1.2);
+            let phi_o: f64 = rng.gen_range(0.0, PI);
+            let wo = Vec3(
+                theta_o.sin(),
+                theta_o.cos() * phi_o.cos(),
+                theta_o.cos() * phi_o.sin(),
+            );
+            if let Some((_, weight)) = bsdf.sample(wo, rng.gen()) {
+                sum += weight.0;
+            }
+        }
+        let average = sum / samples as f64;
+        assert!((average - 1.0).abs() < 0.05, "average {}", average);
+    }
+}
@@ -0,0 +1,214 @@
+use super::math::vector::{Vec2, Vec3};
+use super::mesh::Mesh;
+use super::ply::Ply;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// Loads a triangle mesh, the format is taken from the extension of the file.
+pub fn load_mesh(path: &Path) -> Result<Mesh, Error> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("ply") => parse_ply(&fs::read(path)?),
+        Some("stl") => parse_stl(&fs::read(path)?),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported mesh file {}", path.display()),
+        )),
+    }
+}
+
+/*
+    Mesh from the vertex and face elements of a PLY file. Normals (nx, ny, nz), uvs (u and v or
+    s and t) and colors (red, green, blue) are used when the vertices have them, faces with
+    more than three corners are split into a fan of triangles.
+*/
+pub fn parse_ply(data: &[u8]) -> Result<Mesh, Error> {
+    let ply = Ply::parse(data)?;
+    let vertex = ply
+        .element("vertex")
+        .ok_or_else(|| invalid("PLY file has no vertices"))?;
+    let positions = vertex
+        .vectors(["x", "y", "z"])
+        .ok_or_else(|| invalid("PLY vertex has no x, y and z"))?;
+    let normals = vertex.vectors(["nx", "ny", "nz"]);
+    let uvs = [("u", "v"), ("s", "t"), ("texture_u", "texture_v")]
+        .iter()
+        .find_map(|(u, v)| Some((vertex.scalar(u)?, vertex.scalar(v)?)))
+        .map(|(u, v)| (0..vertex.count).map(|i| Vec2(u[i], v[i])).collect())
+        .unwrap_or_default();
+
+    let faces = ply
+        .element("face")
+        .and_then(|face| {
+            face.list("vertex_indices")
+                .or_else(|| face.list("vertex_index"))
+        })
+        .ok_or_else(|| invalid("PLY file has no faces"))?;
+    let mut triangles = Vec::with_capacity(faces.len());
+    for face in faces.iter() {
+        let indices: Vec<usize> = face.iter().map(|&i| i as usize).collect();
+        if indices.iter().any(|&i| i >= positions.len()) {
+            return Err(invalid("PLY face refers to a missing vertex"));
+        }
+        for i in 2..indices.len() {
+            triangles.push([indices[0], indices[i - 1], indices[i]]);
+        }
+    }
+
+    let mut mesh = match normals {
+        Some(normals) => Mesh::new(positions, normals, uvs, triangles),
+        None => Mesh::smooth(positions, uvs, triangles),
+    };
+    mesh.colors = vertex.colors().unwrap_or_default();
+    Ok(mesh)
+}
+
+/*
+    Mesh from an ascii or binary STL file. STL stores every triangle on its own, the corners
+    are merged on their exact position and the mesh is shaded flat like the facets in CAD.
+*/
+pub fn parse_stl(data: &[u8]) -> Result<Mesh, Error> {
+    // Binary files can start with "solid" as well, the size tells them apart.
+    let binary_size = data
+        .get(80..84)
+        .map(|b| 84 + 50 * u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let corners = if binary_size == Some(data.len()) {
+        stl_binary_corners(data)
+    } else if data.starts_with(b"solid") {
+        stl_ascii_corners(data)?
+    } else {
+        return Err(invalid("Invalid STL file"));
+    };
+
+    let mut indices: HashMap<[u64; 3], usize> = HashMap::new();
+    let mut positions = vec![];
+    let mut index = |p: Vec3| {
+        *indices
+            .entry([p.0.to_bits(), p.1.to_bits(), p.2.to_bits()])
+            .or_insert_with(|| {
+                positions.push(p);
+                positions.len() - 1
+            })
+    };
+    let triangles: Vec<[usize; 3]> = corners
+        .chunks(3)
+        .map(|c| [index(c[0]), index(c[1]), index(c[2])])
+        .collect();
+
+    Ok(Mesh::new(positions, vec![], vec![], triangles))
+}
+
+// Corners of the binary triangles, each after a normal and followed by two attribute bytes.
+fn stl_binary_corners(data: &[u8]) -> Vec<Vec3> {
+    let mut corners = Vec::with_capacity((data.len() - 84) / 50 * 3);
+    for triangle in data[84..].chunks(50) {
+        let float = |offset: usize| {
+            f32::from_le_bytes([
+                triangle[offset],
+                triangle[offset + 1],
+                triangle[offset + 2],
+                triangle[offset + 3],
+            ]) as f64
+        };
+        for offset in [12, 24, 36].iter() {
+            corners.push(Vec3(float(*offset), float(offset + 4), float(offset + 8)));
+        }
+    }
+    corners
+}
+
+// Corners of the ascii triangles, every vertex line is a corner.
+fn stl_ascii_corners(data: &[u8]) -> Result<Vec<Vec3>, Error> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid("Invalid STL text"))?;
+    let mut corners = vec![];
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let ["vertex", x, y, z] = words.as_slice() {
+            let value = |word: &str| {
+                word.parse::<f64>()
+                    .map_err(|_| invalid("Invalid STL vertex"))
+            };
+            corners.push(Vec3(value(x)?, value(y)?, value(z)?));
+        }
+    }
+    if corners.len() % 3 != 0 {
+        return Err(invalid("STL facet without three vertices"));
+    }
+    Ok(corners)
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_parse_ply_mesh() {
+        // Quad with colored corners, split into two triangles.
+        let data = b"ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+            property float y\nproperty float z\nproperty uchar red\nproperty uchar green\n\
+            property uchar blue\nelement face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n0 0 0 255 0 0\n0 0 1 255 0 0\n1 0 1 0 0 255\n1 0 0 0 0 255\n\
+            4 0 1 2 3\n";
+        let mesh = parse_ply(data).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+
+        let (hit, color) = mesh
+            .intersect(Vec3(0.5, 1.0, 0.5), Vec3(0.0, -1.0, 0.0), 0.001, f64::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.normal.1, 1.0, ASSERT_MARGIN);
+        let color = color.unwrap();
+        assert_approx_eq!(color.0, 0.5, ASSERT_MARGIN);
+        assert_approx_eq!(color.2, 0.5, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_parse_stl() {
+        let ascii = b"solid quad\nfacet normal 0 1 0\nouter loop\nvertex 0 0 0\n\
+            vertex 0 0 1\nvertex 1 0 1\nendloop\nendfacet\nfacet normal 0 1 0\nouter loop\n\
+            vertex 0 0 0\nvertex 1 0 1\nvertex 1 0 0\nendloop\nendfacet\nendsolid quad\n";
+        let mesh = parse_stl(ascii).unwrap();
+        // The shared corners are merged.
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles.len(), 2);
+
+        // Same triangles in binary, with a header that starts with solid as well.
+        let mut binary = vec![0u8; 80];
+        binary[..5].copy_from_slice(b"solid");
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for triangle in [
+            [0.0f32, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0],
+        ]
+        .iter()
+        {
+            binary.extend_from_slice(&[0u8; 12]);
+            for value in triangle.iter() {
+                binary.extend_from_slice(&value.to_le_bytes());
+            }
+            binary.extend_from_slice(&[0u8; 2]);
+        }
+        let binary_mesh = parse_stl(&binary).unwrap();
+        assert_eq!(binary_mesh.positions, mesh.positions);
+        assert_eq!(binary_mesh.triangles, mesh.triangles);
+    }
+}
@@ -0,0 +1,303 @@
+use std::io::{Error, ErrorKind};
+
+/*
+    Minimal JSON document, enough to read glTF files. Objects keep the order of their members,
+    lookups are linear which is fine for the small objects in scene files.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn invalid(message: &str, position: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{} at byte {} of the JSON", message, position),
+    )
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, Error> {
+        let mut parser = Parser {
+            data: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.data.len() {
+            return Err(invalid("Unexpected data after the value", parser.position));
+        }
+        Ok(value)
+    }
+
+    // Member of an object, None for other values or missing members.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // Array of numbers, like the factors and transforms in glTF.
+    pub fn as_numbers(&self) -> Option<Vec<f64>> {
+        self.as_array()?.iter().map(Json::as_f64).collect()
+    }
+}
+
+// Objects and arrays are parsed recursively, deeper files are refused instead of overflowing
+// the stack. glTF files nest only a few levels.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+    // Number of values the parser is in.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.position < self.data.len() && self.data[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.data.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        if self.peek() != Some(byte) {
+            return Err(invalid(
+                &format!("Expected '{}'", byte as char),
+                self.position,
+            ));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, Error> {
+        if !self.data[self.position..].starts_with(word.as_bytes()) {
+            return Err(invalid("Invalid literal", self.position));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(invalid("Values nested too deeply", self.position));
+        }
+        self.depth += 1;
+        let value = match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(invalid("Expected a value", self.position)),
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, Error> {
+        self.expect(b'{')?;
+        let mut members = vec![];
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(invalid("Expected a member name", self.position));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(invalid("Expected ',' or '}'", self.position)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, Error> {
+        self.expect(b'[')?;
+        let mut items = vec![];
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(invalid("Expected ',' or ']'", self.position)),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, Error> {
+        let start = self.position;
+        while self.position < self.data.len()
+            && matches!(
+                self.data[self.position],
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+            )
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| invalid("Invalid number", start))
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self
+            .data
+            .get(self.position..self.position + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| invalid("Invalid unicode escape", self.position))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| invalid("Unterminated string", self.position))?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .data
+                        .get(self.position)
+                        .ok_or_else(|| invalid("Unterminated string", self.position))?;
+                    self.position += 1;
+                    let character = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Characters outside the basic plane are written as surrogate pairs.
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.data[self.position..].starts_with(b"\\u")
+                            {
+                                self.position += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xD800) << 10)
+                                    + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return Err(invalid("Invalid escape", self.position - 1)),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("Invalid UTF-8 in string", self.position))
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let json = Json::parse(
+            r#" {"name": "cube \"A\" é😀", "scale": [1, -2.5e1, 0.5],
+                "extras": {"visible": true, "parent": null}, "empty": []} "#,
+        )
+        .unwrap();
+
+        assert_eq!(json.get("name").unwrap().as_str(), Some("cube \"A\" é😀"));
+        assert_eq!(
+            json.get("scale").unwrap().as_numbers(),
+            Some(vec![1.0, -25.0, 0.5])
+        );
+        let extras = json.get("extras").unwrap();
+        assert_eq!(extras.get("visible"), Some(&Json::Bool(true)));
+        assert_eq!(extras.get("parent"), Some(&Json::Null));
+        assert_eq!(json.get("empty").unwrap().as_array().unwrap().len(), 0);
+        assert!(json.get("missing").is_none());
+
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{\"a\": 1} x").is_err());
+
+        // Hostile nesting is an error, not a stack overflow.
+        let nested = "[".repeat(100_000);
+        assert!(Json::parse(&nested).is_err());
+        let allowed = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&allowed).is_ok());
+    }
+}
@@ -1,14 +1,68 @@
 use super::Vec3;
+
+/*
+    Lights without a surface, they are only found through direct lighting (not by scattered
+    rays). Units follow glTF: candela for point and spot lights, lux for directional lights.
+*/
 #[derive(Clone)]
 pub enum LightType {
-    /*Point,
-
-    Area {}
-    */
+    Point,
+    // Cone around the direction, the light fades out between the inner and outer angle.
+    Spot {
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    // Parallel light shining along the direction, the position is unused.
+    Directional {
+        direction: Vec3,
+    },
 }
+
 #[derive(Clone)]
 pub struct Light {
     pub position: Vec3,
+    pub color: Vec3,
     pub intensity: f64,
     pub light_type: LightType,
 }
+
+impl Light {
+    /*
+        Direction from the point to the light, distance to the light and the light arriving at
+        the point (before the cosine), None when the point is outside of a spot cone.
+    */
+    pub fn illuminate(&self, point: Vec3) -> Option<(Vec3, f64, Vec3)> {
+        let radiance = self.color * self.intensity;
+        match &self.light_type {
+            LightType::Directional { direction } => {
+                Some((-Vec3::normalize(*direction), f64::MAX, radiance))
+            }
+            LightType::Point | LightType::Spot { .. } => {
+                let to_light = self.position - point;
+                let distance2 = Vec3::dot(to_light, to_light);
+                let distance = distance2.sqrt();
+                let direction = to_light / distance;
+
+                let falloff = match &self.light_type {
+                    &LightType::Spot {
+                        direction: spot,
+                        inner_angle,
+                        outer_angle,
+                    } => {
+                        let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+                        let cos = Vec3::dot(-direction, Vec3::normalize(spot));
+                        let t =
+                            ((cos - cos_outer) / (cos_inner - cos_outer).max(1e-4)).clamp(0.0, 1.0);
+                        t * t
+                    }
+                    _ => 1.0,
+                };
+                if falloff <= 0.0 {
+                    return None;
+                }
+                Some((direction, distance, radiance * (falloff / distance2)))
+            }
+        }
+    }
+}
@@ -1,27 +1,54 @@
 #![warn(clippy::all)]
 
+mod animation;
+mod aov;
+mod bvh;
 mod camera;
+mod checkpoint;
+mod color;
+mod cryptomatte;
+mod csg;
+mod curve;
+mod denoise;
+mod displacement;
+mod film;
+mod gltf;
+mod hair;
+mod import;
+mod json;
 mod light;
 mod material;
 mod math;
+mod mesh;
+mod options;
+mod output;
+mod particles;
+mod patch;
+mod ply;
+#[cfg(feature = "draw-debugger")]
+mod preview;
+mod primitive;
 mod ray;
 mod renderer;
+mod sampler;
 mod scene;
+mod sdf;
 mod shape;
+mod spectrum;
+mod subdivision;
+mod texture;
 mod threadpool;
 
 use camera::Camera;
+use color::{ColorPipeline, ToneMap};
+use film::Filter;
 use math::vector::Vec3;
-
-// For reading and opening files
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use output::BitDepth;
+use sampler::SamplerType;
 
 use renderer::RenderSettings;
 
 extern crate libc;
-extern crate png;
 
 pub const SCREEN_WIDTH: usize = 1200;
 pub const SCREEN_HEIGHT: usize = 800;
@@ -31,27 +58,88 @@ fn main() -> Result<(), std::io::Error> {
     let render_setting: RenderSettings = RenderSettings {
         screen_width: SCREEN_WIDTH,
         screen_height: SCREEN_HEIGHT,
+        spectral: false,
+        frames: None,
+        stereo: None,
+        filter: Filter::Box { radius: 0.5 },
+        sampler: SamplerType::Sobol,
+        seed: 0,
+        threads: 16,
+        color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Eight),
+        denoise: None,
+        aovs: None,
+        crop: None,
+        checkpoint: None,
+        scene: String::new(),
     };
 
-    // Scene is just a read only data object.
-    let scene = scene::create_scene();
-
-    // Create or overwrite file.
-    let path = Path::new(r"other\images\progress.png");
-    let file = File::create(path)?;
-    let ref mut w = BufWriter::new(file);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match options::parse(&args, render_setting) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}\n\nScenes: {}", options::USAGE, scene::SCENES.join(", "));
+            return Ok(());
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, options::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let render_setting = &options.settings;
 
-    let mut encoder = png::Encoder::new(w, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
-    encoder.set_color(png::ColorType::RGB);
-    encoder.set_depth(png::BitDepth::Eight);
+    // Scene is just a read only data object.
+    let scene = match options.scene() {
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let mut writer = encoder.write_header().unwrap();
+    // Frame range mode writes numbered images.
+    if let Some(frames) = render_setting.frames.clone() {
+        match renderer::render_animation(scene, render_setting, frames, &options.output) {
+            Ok(written) => println!("{} frames created", written),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    let image_data = renderer::render_scene(scene, &render_setting).unwrap();
+    // Create or overwrite files, stereo renders can write an image per eye.
+    std::fs::create_dir_all(&options.output)?;
+    let views = match renderer::render_views(scene, render_setting) {
+        Ok(views) => views,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    for view in views {
+        let path = options.output.join(format!("progress{}.png", view.suffix));
+        let depth = render_setting.color.bit_depth;
+        output::write_png(&path, view.width, view.height, depth, &view.image)?;
 
-    writer.write_image_data(&image_data).unwrap();
+        println!("New image created: {}", path.display());
 
-    println!("New image created: {}", path.display());
+        if let Some(format) = render_setting.aovs {
+            let path = options.output.join(format!("progress{}", view.suffix));
+            let path = path.to_string_lossy();
+            let color = &render_setting.color;
+            aov::write_layers(
+                &path,
+                format,
+                view.width,
+                view.height,
+                &view.layers,
+                &view.manifests,
+                color,
+            )?;
+            println!("Passes written: {}", path);
+        }
+    }
 
     Ok(())
 }
@@ -1,50 +1,214 @@
+use crate::hair::{self, HairBsdf};
 use crate::math::schlick;
 use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
+use crate::spectrum;
+use crate::texture::Texture;
 use crate::Vec3;
 
-extern crate rand;
-use rand::Rng;
-
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Material {
+    // Name in the ID mattes, materials without a name are named by the scene.
+    name: Option<Arc<str>>,
     albedo: Vec3, // Common
+    // Multiplies the albedo, sampled with the uvs of the hit.
+    texture: Option<Arc<Texture>>,
     material_type: MaterialType,
 }
 
+#[derive(Clone)]
 pub enum MaterialType {
     Lambertian,
-    Metal { fuzz: f64 },
-    Dielectric { refract: f64 },
+    Metal {
+        fuzz: f64,
+    },
+    Dielectric {
+        refract: Ior,
+    },
+    /*
+        Hair fibers (Chiang et al.), the albedo is the color of the hair. beta_m and beta_n are
+        the longitudinal and azimuthal roughness (0 to 1), alpha the tilt of the scales in
+        degrees (about 2 for human hair).
+    */
+    Hair {
+        beta_m: f64,
+        beta_n: f64,
+        alpha: f64,
+    },
+}
+
+// Index of refraction of hair keratin.
+const HAIR_IOR: f64 = 1.55;
+
+// Index of refraction, optionally depending on the wavelength (in nm).
+#[derive(Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / lambda^2, lambda in micrometers.
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b * lambda^2 / (lambda^2 - c)), lambda in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    // Schott N-BK7 crown glass.
+    pub fn bk7() -> Ior {
+        Ior::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    // Schott SF11 dense flint glass, strong dispersion.
+    pub fn sf11() -> Ior {
+        Ior::Sellmeier {
+            b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+            c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+        }
+    }
+
+    pub fn eval(&self, lambda: f64) -> f64 {
+        let micro = lambda / 1000.0;
+        let micro2 = micro * micro;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / micro2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * micro2 / (micro2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
 }
 
 // Because material are often created once but used for multiple objects, retuning Arc<>
 pub fn new(albedo: Vec3, material_type: MaterialType) -> Arc<Material> {
+    create(albedo, None, material_type)
+}
+
+pub fn textured(
+    albedo: Vec3,
+    texture: &Arc<Texture>,
+    material_type: MaterialType,
+) -> Arc<Material> {
+    create(albedo, Some(Arc::clone(texture)), material_type)
+}
+
+fn create(
+    albedo: Vec3,
+    texture: Option<Arc<Texture>>,
+    material_type: MaterialType,
+) -> Arc<Material> {
     Arc::new(Material {
+        name: None,
         albedo,
+        texture,
         material_type,
     })
 }
 
-pub fn scatter(material: &Material, ray_in: &Ray) -> Option<(Vec3, Ray)> {
+// Names the material, a material that is already shared is copied as a new material.
+pub fn with_name(material: Arc<Material>, name: &str) -> Arc<Material> {
+    let material = Arc::try_unwrap(material).unwrap_or_else(|shared| (*shared).clone());
+    Arc::new(Material {
+        name: Some(name.into()),
+        ..material
+    })
+}
+
+pub fn name(material: &Material) -> Option<&Arc<str>> {
+    material.name.as_ref()
+}
+
+// Albedo as RGB, or as the spectral value of the wavelengths carried by the ray.
+fn albedo(material: &Material, ray_in: &Ray) -> Vec3 {
+    let color = albedo_rgb(material, ray_in);
+    match &ray_in.wavelengths {
+        Some(wavelengths) => spectrum::upsample_wavelengths(color, wavelengths),
+        None => color,
+    }
+}
+
+fn albedo_rgb(material: &Material, ray_in: &Ray) -> Vec3 {
+    // The color of the hit (particles, vertex colors) replaces the albedo of the material.
+    match &ray_in.is_intersected {
+        Some(hit) => {
+            let color = hit.color.unwrap_or(material.albedo);
+            match &material.texture {
+                Some(texture) => color * texture.sample(hit.uv),
+                None => color,
+            }
+        }
+        None => material.albedo,
+    }
+}
+
+/*
+    RGB color of the surface for the feature buffers of the denoiser. Glass has no color of its
+    own, it is white so the denoiser keeps what is seen through it.
+*/
+pub fn surface_albedo(material: &Material, ray_in: &Ray) -> Vec3 {
+    match &material.material_type {
+        MaterialType::Dielectric { .. } => Vec3::fill(1.0),
+        _ => albedo_rgb(material, ray_in),
+    }
+}
+
+/*
+    Diffuse part of the BRDF (albedo / pi) for the direct light of point lights, None for
+    materials that only scatter in sharp directions.
+*/
+pub fn diffuse(material: &Material, ray_in: &Ray) -> Option<Vec3> {
+    match &material.material_type {
+        MaterialType::Lambertian => Some(albedo(material, ray_in) / std::f64::consts::PI),
+        _ => None,
+    }
+}
+
+/*
+    Mirrors, glass and nearly sharp metal show the surfaces they reflect or refract, the
+    denoiser takes its features from those surfaces.
+*/
+pub fn is_specular(material: &Material) -> bool {
+    match material.material_type {
+        MaterialType::Metal { fuzz } => fuzz < 0.1,
+        MaterialType::Dielectric { .. } => true,
+        _ => false,
+    }
+}
+
+pub fn scatter(
+    material: &Material,
+    ray_in: &Ray,
+    sampler: &mut dyn Sampler,
+) -> Option<(Vec3, Ray)> {
     match &material.material_type {
         &MaterialType::Lambertian => {
             if let Some(hit) = &ray_in.is_intersected {
-                let target = hit.normal + Vec3::rand_unit_vector();
+                let target = hit.normal + sampler::sphere(sampler.get_2d());
 
                 //
-                return Some((material.albedo, Ray::new(hit.position, target)));
+                return Some((
+                    albedo(material, ray_in),
+                    ray_in.scattered(hit.position, target),
+                ));
             }
             None
         }
         &MaterialType::Metal { fuzz } => {
             if let Some(hit) = &ray_in.is_intersected {
                 let target = Vec3::reflect(Vec3::normalize(ray_in.direction), hit.normal);
-                let scattered_ray =
-                    Ray::new(hit.position, target + Vec3::rand_in_unit_sphere() * fuzz);
+                let fuzz = sampler::ball(sampler.get_2d(), sampler.get_1d()) * fuzz;
+                let scattered_ray = ray_in.scattered(hit.position, target + fuzz);
 
                 if Vec3::dot(scattered_ray.direction, hit.normal) > 0.0 {
-                    return Some((material.albedo, scattered_ray));
+                    return Some((albedo(material, ray_in), scattered_ray));
                 } else {
                     return None;
                 }
@@ -52,8 +216,19 @@ pub fn scatter(material: &Material, ray_in: &Ray) -> Option<(Vec3, Ray)> {
             None
         }
         &MaterialType::Dielectric { refract } => {
-            let mut rng = rand::thread_rng();
-            let attenuation = Vec3::fill(1.0);
+            let mut attenuation = Vec3::fill(1.0);
+            let mut wavelengths = ray_in.wavelengths;
+
+            // A dispersive interface bends every wavelength differently, so only the hero
+            // wavelength can continue along the sampled direction.
+            let refract = match &mut wavelengths {
+                Some(w) if refract.is_dispersive() => {
+                    attenuation = w.terminate_secondary();
+                    refract.eval(w.hero())
+                }
+                _ => refract.eval(spectrum::LAMBDA_REFERENCE),
+            };
+
             if let Some(hit) = &ray_in.is_intersected {
                 let etai = if hit.front_face {
                     1.0 / refract
@@ -65,21 +240,111 @@ pub fn scatter(material: &Material, ray_in: &Ray) -> Option<(Vec3, Ray)> {
 
                 let cos_theta = Vec3::dot(-unit_direction, hit.normal).min(1.0);
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let new_ray = |direction: Vec3| Ray {
+                    wavelengths,
+                    ..ray_in.scattered(hit.position, direction)
+                };
+
                 if etai * sin_theta > 1.0 {
                     let reflect = Vec3::reflect(unit_direction, hit.normal);
-                    return Some((attenuation, Ray::new(hit.position, reflect)));
+                    return Some((attenuation, new_ray(reflect)));
                 }
                 let reflect_prob = schlick(cos_theta, etai);
-                let rand: f64 = rng.gen();
-                if rand < reflect_prob {
+                if sampler.get_1d() < reflect_prob {
                     let reflect = Vec3::reflect(unit_direction, hit.normal);
-                    return Some((attenuation, Ray::new(hit.position, reflect)));
+                    return Some((attenuation, new_ray(reflect)));
                 }
 
                 let refracted = Vec3::refract(unit_direction, hit.normal, etai);
-                return Some((attenuation, Ray::new(hit.position, refracted)));
+                return Some((attenuation, new_ray(refracted)));
             }
             None
         }
+        &MaterialType::Hair {
+            beta_m,
+            beta_n,
+            alpha,
+        } => {
+            let hit = ray_in.is_intersected.as_ref()?;
+
+            // Frame of the fiber, x along the fiber and z towards the viewer.
+            let wo = -Vec3::normalize(ray_in.direction);
+            let x = hit
+                .tangent
+                .unwrap_or_else(|| Vec3::orthonormal_basis(hit.normal).0);
+            let across = wo - x * Vec3::dot(wo, x);
+            let z = if Vec3::dot(across, across) > 1e-12 {
+                Vec3::normalize(across)
+            } else {
+                hit.normal
+            };
+            let y = Vec3::cross(z, x);
+
+            let sigma_a = hair::sigma_a_from_color(albedo(material, ray_in), beta_n);
+            let h = 2.0 * hit.uv.1 - 1.0;
+            let bsdf = HairBsdf::new(h, HAIR_IOR, sigma_a, beta_m, beta_n, alpha);
+
+            let wo_local = Vec3(Vec3::dot(wo, x), Vec3::dot(wo, y), Vec3::dot(wo, z));
+            let (u0, u1) = (sampler.get_2d(), sampler.get_2d());
+            let (wi, weight) = bsdf.sample(wo_local, [u0.0, u0.1, u1.0, u1.1])?;
+            let direction = x * wi.0 + y * wi.1 + z * wi.2;
+            Some((weight, ray_in.scattered(hit.position, direction)))
+        }
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::math::vector::Vec2;
+    use crate::spectrum::Wavelengths;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    // Always refracts at dielectrics, reflection is picked below the Fresnel reflectance.
+    struct Refracting;
+
+    impl Sampler for Refracting {
+        fn start_sample(&mut self, _pixel: (usize, usize), _index: u32) {}
+
+        fn get_1d(&mut self) -> f64 {
+            1.0
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            (1.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let ior = Ior::sf11();
+        let glass = new(Vec3::zero(), MaterialType::Dielectric { refract: ior });
+        // A ray entering the glass (the ground plane) at 45 degrees with the hero wavelength.
+        let refract = |lambda: f64| {
+            let mut ray = Ray::new(Vec3(-1.0, 1.0, 0.0), Vec3(1.0, -1.0, 0.0));
+            ray.wavelengths = Some(Wavelengths {
+                lambda: Vec3(lambda, 550.0, 650.0),
+                secondary_terminated: false,
+            });
+            ray.set_intersection(1.0, Arc::clone(&glass), Vec3::up(), Vec2(0.0, 0.0));
+            let (_, scattered) = scatter(&glass, &ray, &mut Refracting).unwrap();
+            Vec3::normalize(scattered.direction)
+        };
+
+        let (blue, red) = (refract(450.0), refract(650.0));
+        // Snell's law with the index of each wavelength.
+        let sin45 = 0.5f64.sqrt();
+        assert_approx_eq!(blue.0, sin45 / ior.eval(450.0), ASSERT_MARGIN);
+        assert_approx_eq!(red.0, sin45 / ior.eval(650.0), ASSERT_MARGIN);
+        // Blue is bent more towards the normal.
+        assert!(red.0 - blue.0 > 0.005);
     }
 }
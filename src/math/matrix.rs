@@ -1,6 +1,12 @@
 use super::Vec3;
 use super::Vec4;
-#[derive(Debug)]
+use std::ops;
+
+/*
+    Row major 4x4 matrix, using row vectors: a point is transformed as p * M.
+    This means the translation is stored in the last row and A * B applies A first, then B.
+*/
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Mat4(pub Vec4, pub Vec4, pub Vec4, pub Vec4);
 
 impl Mat4 {
@@ -17,4 +23,359 @@ impl Mat4 {
             Vec4(position.0, position.1, position.2, 1.0),
         )
     }
+
+    pub fn identity() -> Self {
+        Self(
+            Vec4(1.0, 0.0, 0.0, 0.0),
+            Vec4(0.0, 1.0, 0.0, 0.0),
+            Vec4(0.0, 0.0, 1.0, 0.0),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn translation(t: Vec3) -> Self {
+        let mut m = Self::identity();
+        m.3 = Vec4(t.0, t.1, t.2, 1.0);
+        m
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        Self(
+            Vec4(s.0, 0.0, 0.0, 0.0),
+            Vec4(0.0, s.1, 0.0, 0.0),
+            Vec4(0.0, 0.0, s.2, 0.0),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    // Rotations match Vec3::rotate_x/y/z.
+    pub fn rotation_x(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self(
+            Vec4(1.0, 0.0, 0.0, 0.0),
+            Vec4(0.0, cos, sin, 0.0),
+            Vec4(0.0, -sin, cos, 0.0),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn rotation_y(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self(
+            Vec4(cos, 0.0, -sin, 0.0),
+            Vec4(0.0, 1.0, 0.0, 0.0),
+            Vec4(sin, 0.0, cos, 0.0),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn rotation_z(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self(
+            Vec4(cos, sin, 0.0, 0.0),
+            Vec4(-sin, cos, 0.0, 0.0),
+            Vec4(0.0, 0.0, 1.0, 0.0),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    // Rotation by a unit quaternion (x, y, z, w), as used by glTF.
+    pub fn rotation_quaternion(q: Vec4) -> Self {
+        let Vec4(x, y, z, w) = q;
+        Self(
+            Vec4(
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y + z * w),
+                2.0 * (x * z - y * w),
+                0.0,
+            ),
+            Vec4(
+                2.0 * (x * y - z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z + x * w),
+                0.0,
+            ),
+            Vec4(
+                2.0 * (x * z + y * w),
+                2.0 * (y * z - x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ),
+            Vec4(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    // Scale, then rotate (x, y, z in degrees), then translate.
+    pub fn trs(translation: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+        Self::scale(scale)
+            * Self::rotation_x(rotation.0)
+            * Self::rotation_y(rotation.1)
+            * Self::rotation_z(rotation.2)
+            * Self::translation(translation)
+    }
+
+    pub fn from_array(m: [[f64; 4]; 4]) -> Self {
+        let row = |r: [f64; 4]| Vec4(r[0], r[1], r[2], r[3]);
+        Self(row(m[0]), row(m[1]), row(m[2]), row(m[3]))
+    }
+
+    pub fn to_array(self) -> [[f64; 4]; 4] {
+        let row = |r: Vec4| [r.0, r.1, r.2, r.3];
+        [row(self.0), row(self.1), row(self.2), row(self.3)]
+    }
+
+    #[allow(dead_code)]
+    pub fn transpose(&self) -> Self {
+        let m = self.to_array();
+        let mut t = [[0.0; 4]; 4];
+        for (r, row) in t.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = m[c][r];
+            }
+        }
+        Self::from_array(t)
+    }
+
+    // General inverse using cofactors, returns None for singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let m = self.to_array();
+        let mut inv = [[0.0; 4]; 4];
+
+        // Skips the removed row/column, without allocating (used for every moving object).
+        let minor = |r: usize, c: usize| {
+            let skip = |i: usize, removed: usize| if i >= removed { i + 1 } else { i };
+            let a = |i: usize, j: usize| m[skip(i, r)][skip(j, c)];
+            a(0, 0) * (a(1, 1) * a(2, 2) - a(1, 2) * a(2, 1))
+                - a(0, 1) * (a(1, 0) * a(2, 2) - a(1, 2) * a(2, 0))
+                + a(0, 2) * (a(1, 0) * a(2, 1) - a(1, 1) * a(2, 0))
+        };
+
+        let mut cofactors = [[0.0; 4]; 4];
+        for (r, row) in cofactors.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                let sign = if (r + c) % 2 == 0 { 1.0 } else { -1.0 };
+                *value = sign * minor(r, c);
+            }
+        }
+
+        let det: f64 = (0..4).map(|c| m[0][c] * cofactors[0][c]).sum();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        // Inverse is the transposed cofactor matrix divided by the determinant.
+        for (r, row) in inv.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = cofactors[c][r] / det;
+            }
+        }
+
+        Some(Self::from_array(inv))
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let v = self.0 * p.0 + self.1 * p.1 + self.2 * p.2 + self.3;
+        if v.3 != 1.0 && v.3 != 0.0 {
+            return Vec3(v.0 / v.3, v.1 / v.3, v.2 / v.3);
+        }
+        Vec3::from(v)
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        Vec3::from(self.0 * v.0 + self.1 * v.1 + self.2 * v.2)
+    }
+
+    /*
+        Normals have to stay perpendicular to the surface, so they are transformed with the
+        inverse transpose. Expects the inverse of the matrix that transforms the points.
+    */
+    pub fn transform_normal(inverse: &Self, n: Vec3) -> Vec3 {
+        Vec3(
+            Vec3::dot(n, Vec3::from(inverse.0)),
+            Vec3::dot(n, Vec3::from(inverse.1)),
+            Vec3::dot(n, Vec3::from(inverse.2)),
+        )
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Self;
+
+    fn mul(self, _rhs: Self) -> Self {
+        let a = self.to_array();
+        let b = _rhs.to_array();
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| a[r][k] * b[k][c]).sum();
+            }
+        }
+        Self::from_array(m)
+    }
+}
+
+/*
+    Decomposed transform (rotation in degrees), can be interpolated for moving objects.
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct Trs {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: Vec3,
+}
+
+impl Trs {
+    pub fn identity() -> Trs {
+        Trs {
+            translation: Vec3::zero(),
+            rotation: Vec3::zero(),
+            scale: Vec3::fill(1.0),
+        }
+    }
+
+    pub fn lerp(a: &Trs, b: &Trs, t: f64) -> Trs {
+        let lerp = |a: Vec3, b: Vec3| a * (1.0 - t) + b * t;
+        Trs {
+            translation: lerp(a.translation, b.translation),
+            rotation: lerp(a.rotation, b.rotation),
+            scale: lerp(a.scale, b.scale),
+        }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        Transform::trs(self.translation, self.rotation, self.scale)
+    }
+}
+
+/*
+    Matrix together with its inverse, the inverse is needed for every ray that is transformed
+    into object space so it is calculated once.
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4,
+}
+
+impl Transform {
+    pub fn new(matrix: Mat4) -> Self {
+        Transform {
+            matrix,
+            inverse: matrix
+                .inverse()
+                .expect("Transform matrix is not invertible"),
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(Mat4::identity())
+    }
+
+    pub fn trs(translation: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+        Self::new(Mat4::trs(translation, rotation, scale))
+    }
+
+    // Apply this transform inside of a parent transform (child -> parent -> world).
+    pub fn then(&self, parent: &Transform) -> Self {
+        Transform {
+            matrix: self.matrix * parent.matrix,
+            inverse: parent.inverse * self.inverse,
+        }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        Mat4::transform_normal(&self.inverse, n)
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    fn assert_vec3(a: Vec3, b: Vec3) {
+        assert_approx_eq!(a.0, b.0, ASSERT_MARGIN);
+        assert_approx_eq!(a.1, b.1, ASSERT_MARGIN);
+        assert_approx_eq!(a.2, b.2, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_mat4_rotation_matches_vec3() {
+        let p = Vec3(3.0, 0.5, -2.0);
+        assert_vec3(Mat4::rotation_x(30.0).transform_point(p), p.rotate_x(30.0));
+        assert_vec3(Mat4::rotation_y(30.0).transform_point(p), p.rotate_y(30.0));
+        assert_vec3(Mat4::rotation_z(30.0).transform_point(p), p.rotate_z(30.0));
+    }
+
+    #[test]
+    fn test_mat4_trs() {
+        let m = Mat4::trs(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 90.0, 0.0), Vec3::fill(2.0));
+        let p = m.transform_point(Vec3(1.0, 0.0, 0.0));
+        assert_vec3(p, Vec3(1.0, 2.0, 1.0));
+
+        // Vectors ignore the translation.
+        let v = m.transform_vector(Vec3(1.0, 0.0, 0.0));
+        assert_vec3(v, Vec3(0.0, 0.0, -2.0));
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let m = Mat4::trs(
+            Vec3(1.0, -2.0, 3.0),
+            Vec3(10.0, 20.0, 30.0),
+            Vec3(1.0, 2.0, 3.0),
+        );
+        let inv = m.inverse().unwrap();
+        let identity = (m * inv).to_array();
+        let expected = Mat4::identity().to_array();
+        for r in 0..4 {
+            for c in 0..4 {
+                assert_approx_eq!(identity[r][c], expected[r][c], ASSERT_MARGIN);
+            }
+        }
+
+        assert!(Mat4::scale(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_mat4_transpose() {
+        let m = Mat4::translation(Vec3(1.0, 2.0, 3.0));
+        let t = m.transpose();
+        assert_eq!(t.0, Vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(t.transpose(), m);
+    }
+
+    #[test]
+    fn test_transform_normal() {
+        // Non uniform scale must keep the normal perpendicular to the surface.
+        let t = Transform::new(Mat4::scale(Vec3(1.0, 4.0, 1.0)));
+        let tangent = t.matrix.transform_vector(Vec3(1.0, -1.0, 0.0));
+        let normal = t.normal(Vec3(1.0, 1.0, 0.0));
+        assert_approx_eq!(Vec3::dot(tangent, normal), 0.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_mat4_rotation_quaternion() {
+        // 90 degrees around y, the same as rotation_y.
+        let half = std::f64::consts::FRAC_PI_4;
+        let q = Mat4::rotation_quaternion(Vec4(0.0, half.sin(), 0.0, half.cos()));
+        let p = Vec3(1.0, 2.0, 3.0);
+        assert_vec3(
+            q.transform_point(p),
+            Mat4::rotation_y(90.0).transform_point(p),
+        );
+        assert_vec3(q.transform_point(Vec3(1.0, 0.0, 0.0)), Vec3(0.0, 0.0, -1.0));
+    }
 }
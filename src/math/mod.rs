@@ -1,6 +1,7 @@
 pub mod matrix;
 pub mod vector;
 
+use std::f64::consts::PI;
 use vector::{Vec3, Vec4};
 
 pub fn schlick(cosine: f64, idx: f64) -> f64 {
@@ -8,3 +9,171 @@ pub fn schlick(cosine: f64, idx: f64) -> f64 {
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+const EQN_EPS: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x > -EQN_EPS && x < EQN_EPS
+}
+
+/*
+    Polynomial root solvers, coefficients from the constant term up: c[0] + c[1] x + c[2] x^2 ..
+    Based on "Solving Quartics and Cubics for Graphics" (Schwarze, Graphics Gems I).
+*/
+pub fn solve_quadratic(c: [f64; 3]) -> Vec<f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let d = p * p - q;
+
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        vec![]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+pub fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    // Normal form x^3 + Ax^2 + Bx + C = 0
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let c = c[0] / c[3];
+
+    // Substitute x = y - A/3 to eliminate the quadric term: x^3 + px + q = 0
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // Three real solutions
+        let phi = (-q / (-cb_p).sqrt()).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.0).cos(),
+            -t * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        let u = (sqrt_d - q).cbrt();
+        let v = -(sqrt_d + q).cbrt();
+        vec![u + v]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+pub fn solve_quartic(coefficients: [f64; 5]) -> Vec<f64> {
+    // Normal form x^4 + Ax^3 + Bx^2 + Cx + D = 0
+    let a = coefficients[3] / coefficients[4];
+    let b = coefficients[2] / coefficients[4];
+    let c = coefficients[1] / coefficients[4];
+    let d = coefficients[0] / coefficients[4];
+
+    // Substitute x = y - A/4 to eliminate the cubic term: x^4 + px^2 + qx + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if is_zero(r) {
+        // No absolute term: y(y^3 + py + q) = 0
+        let mut roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        // Solve the resolvent cubic and use one of the roots to build two quadrics.
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return vec![];
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return vec![];
+        };
+
+        let v = if q < 0.0 { -v } else { v };
+        let mut roots = solve_quadratic([z - u, v, 1.0]);
+        roots.extend(solve_quadratic([z + u, -v, 1.0]));
+        roots
+    };
+
+    // Resubstitute and polish with a newton step, the closed form loses precision.
+    let eval = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+    let derivative = |x: f64| ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        let slope = derivative(*root);
+        if slope != 0.0 {
+            *root -= eval(*root) / slope;
+        }
+    }
+    roots
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots
+    }
+
+    #[test]
+    fn test_solve_cubic() {
+        // (x - 1)(x - 2)(x + 3) = x^3 - 7x + 6
+        let roots = sorted(solve_cubic([6.0, -7.0, 0.0, 1.0]));
+        assert_eq!(roots.len(), 3);
+        assert_approx_eq!(roots[0], -3.0, ASSERT_MARGIN);
+        assert_approx_eq!(roots[1], 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(roots[2], 2.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_solve_quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4) = x^4 - 10x^3 + 35x^2 - 50x + 24
+        let roots = sorted(solve_quartic([24.0, -50.0, 35.0, -10.0, 1.0]));
+        assert_eq!(roots.len(), 4);
+        for (i, root) in roots.iter().enumerate() {
+            assert_approx_eq!(*root, i as f64 + 1.0, ASSERT_MARGIN);
+        }
+
+        // x^4 + 1 has no real roots
+        assert!(solve_quartic([1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+    }
+}
@@ -87,6 +87,7 @@ impl Vec3 {
         Self(rng.0, rng.1, rng.2)
     }
 
+    #[allow(dead_code)]
     pub fn rand_in_unit_sphere() -> Self {
         let mut rng = rand::thread_rng();
         let rngs: (f64, f64, f64, f64) = rng.gen();
@@ -112,6 +113,7 @@ impl Vec3 {
         return -in_unit_sphere;
     }
 
+    #[allow(dead_code)]
     pub fn rand_unit_vector() -> Self {
         let mut rng = rand::thread_rng();
         let a: f64 = rng.gen_range(0.0, 2.0 * PI);
@@ -120,6 +122,7 @@ impl Vec3 {
         Vec3(r * a.cos(), r * a.sin(), z)
     }
 
+    #[allow(dead_code)]
     pub fn rand_in_unit_disk() -> Self {
         let mut rng = rand::thread_rng();
         let size: f64 = rng.gen();
@@ -157,6 +160,22 @@ impl Vec3 {
         vec - normal * Vec3::dot(vec, normal) * 2.0
     }
 
+    // Two vectors that form an orthonormal basis with the (normalized) normal.
+    // "Building an Orthonormal Basis, Revisited" (Duff et al. 2017)
+    pub fn orthonormal_basis(normal: Vec3) -> (Self, Self) {
+        let sign = 1.0f64.copysign(normal.2);
+        let a = -1.0 / (sign + normal.2);
+        let b = normal.0 * normal.1 * a;
+        (
+            Vec3(
+                1.0 + sign * normal.0 * normal.0 * a,
+                sign * b,
+                -sign * normal.0,
+            ),
+            Vec3(b, sign + normal.1 * normal.1 * a, -normal.1),
+        )
+    }
+
     pub fn refract(vec: Vec3, normal: Vec3, eta: f64) -> Self {
         let cos_theta = Vec3::dot(-vec, normal);
         let out_parallel: Vec3 = (vec + normal * cos_theta) * eta;
@@ -284,6 +303,14 @@ impl ops::Div<f64> for Vec3 {
     }
 }
 
+impl ops::Div<Vec3> for Vec3 {
+    type Output = Self;
+
+    fn div(self, _rhs: Self) -> Self::Output {
+        Self(self.0 / _rhs.0, self.1 / _rhs.1, self.2 / _rhs.2)
+    }
+}
+
 impl ops::Neg for Vec3 {
     type Output = Self;
 
@@ -296,6 +323,83 @@ impl ops::Neg for Vec3 {
     }
 }
 
+/***
+ *  Vector 2 implementations
+***/
+
+impl Vector for Vec2 {
+    fn squared(&self) -> f64 {
+        self.0 * self.0 + self.1 * self.1
+    }
+
+    fn normalize(&self) -> Self {
+        let length = self.length();
+        Self(self.0 / length, self.1 / length)
+    }
+
+    fn dot(&self, other: Self) -> f64 {
+        self.0 * other.0 + self.1 * other.1
+    }
+}
+
+// Vector2 operators overload
+
+impl ops::Add for Vec2 {
+    type Output = Self;
+
+    fn add(self, _rhs: Self) -> Self {
+        Self(self.0 + _rhs.0, self.1 + _rhs.1)
+    }
+}
+
+impl ops::Sub for Vec2 {
+    type Output = Self;
+
+    fn sub(self, _rhs: Self) -> Self {
+        Self(self.0 - _rhs.0, self.1 - _rhs.1)
+    }
+}
+
+impl ops::Mul<f64> for Vec2 {
+    type Output = Self;
+
+    fn mul(self, _rhs: f64) -> Self {
+        Self(self.0 * _rhs, self.1 * _rhs)
+    }
+}
+
+impl ops::Div<f64> for Vec2 {
+    type Output = Self;
+
+    fn div(self, _rhs: f64) -> Self::Output {
+        Self(self.0 / _rhs, self.1 / _rhs)
+    }
+}
+
+// Vector4 operators overload
+
+impl ops::Add for Vec4 {
+    type Output = Self;
+
+    fn add(self, _rhs: Self) -> Self {
+        Self(
+            self.0 + _rhs.0,
+            self.1 + _rhs.1,
+            self.2 + _rhs.2,
+            self.3 + _rhs.3,
+        )
+    }
+}
+
+// scalar mul
+impl ops::Mul<f64> for Vec4 {
+    type Output = Self;
+
+    fn mul(self, _rhs: f64) -> Self {
+        Self(self.0 * _rhs, self.1 * _rhs, self.2 * _rhs, self.3 * _rhs)
+    }
+}
+
 /***
  *  Tests
 ***/
@@ -0,0 +1,214 @@
+use super::bvh::{Aabb, Bvh};
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::primitive::{self, Hit};
+use super::ray::Ray;
+
+/*
+    Indexed triangle mesh with its own BVH over the triangles. Normals and uvs are per vertex
+    and interpolated over the triangles, without normals the mesh is shaded flat. Colors are
+    optional as well, when given they replace the albedo of the material.
+*/
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub triangles: Vec<[usize; 3]>,
+    pub colors: Vec<Vec3>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        triangles: Vec<[usize; 3]>,
+    ) -> Mesh {
+        let bounds: Vec<Option<Aabb>> = triangles
+            .iter()
+            .map(|tri| Some(tri.iter().fold(Aabb::empty(), |b, &i| b.grow(positions[i]))))
+            .collect();
+        Mesh {
+            bvh: Bvh::build(&bounds),
+            positions,
+            normals,
+            uvs,
+            triangles,
+            colors: vec![],
+        }
+    }
+
+    // Mesh with normals averaged from the triangles around each vertex.
+    pub fn smooth(positions: Vec<Vec3>, uvs: Vec<Vec2>, triangles: Vec<[usize; 3]>) -> Mesh {
+        let normals = smooth_normals(&positions, &triangles);
+        Mesh::new(positions, normals, uvs, triangles)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds().unwrap_or_else(Aabb::empty)
+    }
+
+    fn corners(&self, index: usize) -> [Vec3; 3] {
+        let [a, b, c] = self.triangles[index];
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+
+    fn hit(&self, index: usize, t: f64, b1: f64, b2: f64) -> Hit {
+        let [a, b, c] = self.triangles[index];
+        let corners = self.corners(index);
+        let geometric = Vec3::cross(corners[1] - corners[0], corners[2] - corners[0]).normalize();
+        let b0 = 1.0 - b1 - b2;
+
+        let normal = if self.normals.is_empty() {
+            geometric
+        } else {
+            let n = self.normals[a] * b0 + self.normals[b] * b1 + self.normals[c] * b2;
+            // Keep the shading normal on the same side as the triangle.
+            let n = n.normalize();
+            if Vec3::dot(n, geometric) < 0.0 {
+                -n
+            } else {
+                n
+            }
+        };
+
+        let uv = if self.uvs.is_empty() {
+            Vec2(b1, b2)
+        } else {
+            let (ua, ub, uc) = (self.uvs[a], self.uvs[b], self.uvs[c]);
+            Vec2(
+                ua.0 * b0 + ub.0 * b1 + uc.0 * b2,
+                ua.1 * b0 + ub.1 * b1 + uc.1 * b2,
+            )
+        };
+
+        Hit { t, normal, uv }
+    }
+
+    fn color(&self, index: usize, b1: f64, b2: f64) -> Option<Vec3> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let [a, b, c] = self.triangles[index];
+        Some(self.colors[a] * (1.0 - b1 - b2) + self.colors[b] * b1 + self.colors[c] * b2)
+    }
+
+    /*
+        Closest triangle hit between t_min and t_max with the interpolated vertex color, when
+        there are colors. o and d are in the space of the mesh.
+    */
+    pub fn intersect(
+        &self,
+        o: Vec3,
+        d: Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(Hit, Option<Vec3>)> {
+        let mut ray = Ray::new(o, d);
+        ray.travel_distance = t_max;
+
+        let mut closest = None;
+        self.bvh.traverse(&mut ray, t_min, |i, ray| {
+            if let Some((t, b1, b2)) =
+                primitive::triangle(o, d, self.corners(i), t_min, ray.travel_distance)
+            {
+                ray.travel_distance = t;
+                closest = Some((i, t, b1, b2));
+            }
+        });
+        closest.map(|(i, t, b1, b2)| (self.hit(i, t, b1, b2), self.color(i, b1, b2)))
+    }
+
+    /*
+        All triangles along the line of the ray sorted on t, for closed meshes in csg. The bool
+        is true where the line enters the mesh, taken from the triangle because the shading
+        normal can face the other way near the silhouette.
+    */
+    pub fn crossings(&self, o: Vec3, d: Vec3) -> Vec<(Hit, bool)> {
+        let mut ray = Ray::new(o, d);
+        let mut hits = vec![];
+        self.bvh.traverse(&mut ray, f64::NEG_INFINITY, |i, _| {
+            let corners = self.corners(i);
+            if let Some((t, b1, b2)) =
+                primitive::triangle(o, d, corners, f64::NEG_INFINITY, f64::MAX)
+            {
+                let geometric = Vec3::cross(corners[1] - corners[0], corners[2] - corners[0]);
+                hits.push((self.hit(i, t, b1, b2), Vec3::dot(geometric, d) < 0.0));
+            }
+        });
+        hits.sort_by(|a, b| {
+            a.0.t
+                .partial_cmp(&b.0.t)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        hits
+    }
+}
+
+// Area weighted average of the normals of the triangles around each vertex.
+pub fn smooth_normals(positions: &[Vec3], triangles: &[[usize; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for &[a, b, c] in triangles.iter() {
+        // The length of the cross product is twice the area.
+        let n = Vec3::cross(positions[b] - positions[a], positions[c] - positions[a]);
+        for &i in [a, b, c].iter() {
+            normals[i] += n;
+        }
+    }
+    normals
+        .iter()
+        .map(|n| {
+            if n.squared() > 0.0 {
+                n.normalize()
+            } else {
+                Vec3::up()
+            }
+        })
+        .collect()
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_mesh_intersect() {
+        // Two triangles forming a unit square in the xz plane.
+        let positions = vec![
+            Vec3::zero(),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 0.0, 1.0),
+            Vec3(0.0, 0.0, 1.0),
+        ];
+        let uvs = vec![
+            Vec2(0.0, 0.0),
+            Vec2(1.0, 0.0),
+            Vec2(1.0, 1.0),
+            Vec2(0.0, 1.0),
+        ];
+        let mesh = Mesh::smooth(positions, uvs, vec![[0, 2, 1], [0, 3, 2]]);
+
+        let down = Vec3(0.0, -1.0, 0.0);
+        let (hit, color) = mesh
+            .intersect(Vec3(0.25, 1.0, 0.75), down, 0.001, f64::MAX)
+            .unwrap();
+        assert!(color.is_none());
+        assert_approx_eq!(hit.t, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.normal.1, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.uv.0, 0.25, ASSERT_MARGIN);
+        assert_approx_eq!(hit.uv.1, 0.75, ASSERT_MARGIN);
+
+        assert!(mesh
+            .intersect(Vec3(1.5, 1.0, 0.5), down, 0.001, f64::MAX)
+            .is_none());
+    }
+}
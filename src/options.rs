@@ -0,0 +1,342 @@
+use super::aov::AovOutput;
+use super::camera::{Aperture, ApertureMask};
+use super::color::{ColorPipeline, Lut3d, ToneMap};
+use super::film::Filter;
+use super::output::BitDepth;
+use super::renderer::{Crop, RenderSettings, Stereo, StereoLayout};
+use super::sampler::{self, SamplerType};
+use super::scene::{self, Scene};
+use super::texture::Texture;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "Usage: cpu_raytracer [options]
+
+Scene:
+  --scene <name|file>       built-in scene (see below) or a .gltf, .glb, .ply, .stl or .bin
+                            (particles) file, default spheres
+  --aperture-mask <png>     image of the camera aperture, bright pixels let light through
+
+Render settings:
+  --spectral                trace wavelengths instead of RGB, needed for dispersion
+  --frames <first>-<last>   render these frames of the scene animation
+  --stereo <layout>         render both eyes 6.5 cm apart, side-by-side or separate
+  --convergence <meters>    distance at which the eyes converge, default parallel
+  --filter <name>           box, tent, gaussian, mitchell or lanczos, default box
+  --sampler <name>          independent, stratified, sobol or blue-noise, default sobol
+  --seed <number>           the same seed gives the same image, default 0
+  --threads <number>        threads rendering the image, default 16
+  --crop <x>,<y>,<w>,<h>    render only this part of the image
+  --checkpoint <file>       save the render in progress and resume it from this file
+
+Output:
+  --output <directory>      where the images are written, default other/images
+  --tone-map <name>         clamp, reinhard, aces or agx, default clamp
+  --exposure <stops>        exposure adjustment before the tone map
+  --white-balance <kelvin>  color temperature of the light that should look white
+  --lut <file>              .cube 3D LUT applied after the tone map
+  --bit-depth <8|16>        bits per channel of the images, default 8
+  --denoise                 denoise the image, for previews with few samples
+  --aovs <exr|png>          write the render passes and ID mattes
+
+  --help                    show this help";
+
+/*
+    Everything that can be chosen when starting the renderer: the scene and the render
+    settings. Options that are not given keep the defaults of the settings in main.
+*/
+pub struct Options {
+    // Name of a built-in scene or path of a scene file.
+    pub scene: String,
+    pub aperture_mask: Option<PathBuf>,
+    pub output: PathBuf,
+    pub settings: RenderSettings,
+}
+
+impl Options {
+    /*
+        Builds the scene of the options, files are loaded and the aperture mask is put on its
+        camera.
+    */
+    pub fn scene(&self) -> Result<Scene, String> {
+        let mut scene = match scene::by_name(&self.scene) {
+            Some(scene) => scene,
+            None if !Path::new(&self.scene).exists() => {
+                return Err(format!("Unknown scene {}, see --help", self.scene))
+            }
+            None => scene::load(Path::new(&self.scene))
+                .map_err(|e| format!("Could not load scene {}: {}", self.scene, e))?,
+        };
+
+        if let Some(path) = &self.aperture_mask {
+            let texture = File::open(path)
+                .and_then(|file| Texture::decode_png(BufReader::new(file)))
+                .map_err(|e| format!("Could not load {}: {}", path.display(), e))?;
+            let cat_eye = scene.camera.cat_eye;
+            let mask = Aperture::Mask(std::sync::Arc::new(ApertureMask::new(&texture)));
+            scene.camera = scene.camera.with_aperture(mask, cat_eye);
+        }
+        Ok(scene)
+    }
+}
+
+/*
+    Parses the arguments (without the program name) on top of the default settings. Returns
+    None when the help is asked for.
+*/
+pub fn parse(args: &[String], defaults: RenderSettings) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        scene: scene::SCENES[0].to_string(),
+        aperture_mask: None,
+        output: PathBuf::from("other/images"),
+        settings: defaults,
+    };
+    let mut stereo_layout = None;
+    let mut convergence = f64::INFINITY;
+    let (mut tone_map, mut bit_depth) = (None, None);
+    let (mut exposure, mut white_balance, mut lut) = (None, None, None);
+
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|v| v.as_str())
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+        let settings = &mut options.settings;
+        match option.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--scene" => options.scene = value()?.to_string(),
+            "--aperture-mask" => options.aperture_mask = Some(PathBuf::from(value()?)),
+            "--output" => options.output = PathBuf::from(value()?),
+            "--spectral" => settings.spectral = true,
+            "--frames" => {
+                let value = value()?;
+                let (first, last) = match value.split_once('-') {
+                    Some((first, last)) => (number(option, first)?, number(option, last)?),
+                    None => (number(option, value)?, number(option, value)?),
+                };
+                settings.frames = Some(first..=last);
+            }
+            "--stereo" => {
+                stereo_layout = Some(match value()? {
+                    "side-by-side" => StereoLayout::SideBySide,
+                    "separate" => StereoLayout::Separate,
+                    other => return Err(unknown(option, other)),
+                })
+            }
+            "--convergence" => convergence = number(option, value()?)?,
+            "--filter" => {
+                settings.filter = match value()? {
+                    "box" => Filter::Box { radius: 0.5 },
+                    "tent" => Filter::Tent { radius: 1.0 },
+                    "gaussian" => Filter::Gaussian {
+                        radius: 1.5,
+                        sigma: 0.5,
+                    },
+                    "mitchell" => Filter::Mitchell {
+                        radius: 2.0,
+                        b: 1.0 / 3.0,
+                        c: 1.0 / 3.0,
+                    },
+                    "lanczos" => Filter::Lanczos {
+                        radius: 3.0,
+                        tau: 3.0,
+                    },
+                    other => return Err(unknown(option, other)),
+                }
+            }
+            "--sampler" => {
+                settings.sampler = match value()? {
+                    "independent" => SamplerType::Independent,
+                    "stratified" => SamplerType::Stratified,
+                    "sobol" => SamplerType::Sobol,
+                    "blue-noise" => SamplerType::BlueNoise,
+                    other => return Err(unknown(option, other)),
+                }
+            }
+            "--seed" => settings.seed = number(option, value()?)?,
+            "--threads" => settings.threads = number(option, value()?)?,
+            "--crop" => {
+                let value = value()?;
+                let numbers = value
+                    .split(',')
+                    .map(|v| number(option, v))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                if numbers.len() != 4 {
+                    return Err(format!("{} needs x,y,width,height, not {}", option, value));
+                }
+                settings.crop = Some(Crop {
+                    x: numbers[0],
+                    y: numbers[1],
+                    width: numbers[2],
+                    height: numbers[3],
+                });
+            }
+            "--checkpoint" => settings.checkpoint = Some(PathBuf::from(value()?)),
+            "--tone-map" => {
+                tone_map = Some(match value()? {
+                    "clamp" => ToneMap::Clamp,
+                    "reinhard" => ToneMap::Reinhard { white: 4.0 },
+                    "aces" => ToneMap::Aces,
+                    "agx" => ToneMap::Agx,
+                    other => return Err(unknown(option, other)),
+                })
+            }
+            "--exposure" => exposure = Some(number(option, value()?)?),
+            "--white-balance" => white_balance = Some(number(option, value()?)?),
+            "--lut" => {
+                let path = value()?;
+                let cube = Lut3d::load_cube(Path::new(path))
+                    .map_err(|e| format!("Could not load {}: {}", path, e))?;
+                lut = Some(cube);
+            }
+            "--bit-depth" => {
+                bit_depth = Some(match value()? {
+                    "8" => BitDepth::Eight,
+                    "16" => BitDepth::Sixteen,
+                    other => return Err(unknown(option, other)),
+                })
+            }
+            "--denoise" => settings.denoise = Some(Default::default()),
+            "--aovs" => {
+                settings.aovs = Some(match value()? {
+                    "exr" => AovOutput::Exr,
+                    "png" => AovOutput::Png,
+                    other => return Err(unknown(option, other)),
+                })
+            }
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+
+    // Checkpoints are only resumed with the same scene, for files also with the same contents.
+    options.settings.scene = options.scene.clone();
+    let path = Path::new(&options.scene);
+    if !scene::SCENES.contains(&options.scene.as_str()) && path.exists() {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Could not load scene {}: {}", options.scene, e))?;
+        let hash = data
+            .iter()
+            .fold(0, |h, &b| sampler::hash_combine(h, b as u64));
+        options.settings.scene = format!("{} {:016x}", options.scene, hash);
+    }
+
+    let settings = &mut options.settings;
+    if let Some(layout) = stereo_layout {
+        settings.stereo = Some(Stereo {
+            interocular: 0.065,
+            convergence,
+            layout,
+        });
+    }
+    let color = &settings.color;
+    let mut color = ColorPipeline::new(
+        tone_map.unwrap_or(color.tone_map),
+        bit_depth.unwrap_or(color.bit_depth),
+    )
+    .with_exposure(exposure.unwrap_or(color.exposure));
+    if let Some(kelvin) = white_balance.or(settings.color.white_balance) {
+        color = color.with_white_balance(kelvin);
+    }
+    if let Some(lut) = lut {
+        color = color.with_lut(lut);
+    }
+    settings.color = color;
+
+    Ok(Some(options))
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} needs a number, not {}", option, value))
+}
+
+fn unknown(option: &str, value: &str) -> String {
+    format!("Unknown value {} for {}", value, option)
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn defaults() -> RenderSettings {
+        RenderSettings {
+            screen_width: 12,
+            screen_height: 8,
+            spectral: false,
+            frames: None,
+            stereo: None,
+            filter: Filter::Box { radius: 0.5 },
+            sampler: SamplerType::Sobol,
+            seed: 0,
+            threads: 1,
+            color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Eight),
+            denoise: None,
+            aovs: None,
+            crop: None,
+            checkpoint: None,
+            scene: String::new(),
+        }
+    }
+
+    fn parse_args(args: &str) -> Result<Option<Options>, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        parse(&args, defaults())
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_args("").unwrap().unwrap();
+        assert_eq!(options.scene, "spheres");
+        assert!(options.settings.frames.is_none());
+
+        let options = parse_args(
+            "--scene csg --spectral --frames 3-5 --stereo separate --seed 9 --threads 4 \
+             --crop 1,2,3,4 --tone-map agx --exposure 1.5 --bit-depth 16",
+        )
+        .unwrap()
+        .unwrap();
+        let settings = &options.settings;
+        assert_eq!(options.scene, "csg");
+        assert_eq!(settings.scene, "csg");
+        assert!(settings.spectral);
+        assert_eq!(settings.frames, Some(3..=5));
+        assert!(settings.stereo.is_some());
+        assert_eq!(settings.seed, 9);
+        assert_eq!(settings.threads, 4);
+        assert_eq!(
+            settings.crop,
+            Some(Crop {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 4
+            })
+        );
+        assert_eq!(settings.color.exposure, 1.5);
+        assert_eq!(settings.color.bit_depth.bytes_pixel(), 6);
+
+        // Scene files are told apart by their contents as well.
+        let stl = parse_args("--scene other/models/hex_nut.stl")
+            .unwrap()
+            .unwrap();
+        assert!(stl.settings.scene.starts_with("other/models/hex_nut.stl "));
+
+        assert!(parse_args("--help").unwrap().is_none());
+        assert!(parse_args("--seed").is_err());
+        assert!(parse_args("--seed many").is_err());
+        assert!(parse_args("--stereo above").is_err());
+        assert!(parse_args("--filter round").is_err());
+        assert!(parse_args("--tone-map filmic").is_err());
+        assert!(parse_args("--crop 1,2,3").is_err());
+        assert!(parse_args("--fast").is_err());
+    }
+}
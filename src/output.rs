@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+extern crate png;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    pub fn bytes_pixel(&self) -> usize {
+        match self {
+            BitDepth::Eight => 3,
+            BitDepth::Sixteen => 6,
+        }
+    }
+}
+
+/*
+    Write rgb image data to a png file, the file is created or overwritten. Sixteen bit values
+    are big endian.
+*/
+pub fn write_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    depth: BitDepth,
+    image_data: &[u8],
+) -> Result<(), std::io::Error> {
+    let file = File::create(path)?;
+    encode_png(BufWriter::new(file), width, height, depth, image_data)
+}
+
+// Write rgb image data as png to any writer, like a buffer in memory.
+pub fn encode_png<W: Write>(
+    w: W,
+    width: usize,
+    height: usize,
+    depth: BitDepth,
+    image_data: &[u8],
+) -> Result<(), std::io::Error> {
+    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(match depth {
+        BitDepth::Eight => png::BitDepth::Eight,
+        BitDepth::Sixteen => png::BitDepth::Sixteen,
+    });
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image_data)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerKind {
+    // Linear light, like the image itself.
+    Radiance,
+    // Surface colors between 0 and 1.
+    Color,
+    // Directions with components between -1 and 1.
+    Vector,
+    // Distances, infinite where nothing was hit.
+    Depth,
+    // Whole numbers, 0 where nothing was hit.
+    Id,
+    // Two pairs of id and coverage, see cryptomatte.
+    Matte,
+}
+
+impl LayerKind {
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            LayerKind::Radiance | LayerKind::Color => &["R", "G", "B"],
+            LayerKind::Vector => &["X", "Y", "Z"],
+            LayerKind::Depth => &["Z"],
+            LayerKind::Id => &["id"],
+            LayerKind::Matte => &["R", "G", "B", "A"],
+        }
+    }
+}
+
+// Float image with the channels of the kind interleaved per pixel, the image itself has no name.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub kind: LayerKind,
+    pub values: Vec<f32>,
+}
+
+/*
+    Write layers to an uncompressed scanline OpenEXR file with 32 bit float channels. Channels
+    are named layer.channel (only the channel for the unnamed layer), which compositors show as
+    separate layers. Attributes are extra string attributes in the header, like metadata.
+*/
+pub fn write_exr(
+    path: &Path,
+    width: usize,
+    height: usize,
+    layers: &[Layer],
+    attributes: &[(String, String)],
+) -> Result<(), std::io::Error> {
+    // Channels have to be sorted by name, values are read from (layer, channel offset).
+    let mut channels: Vec<(String, &Layer, usize)> = vec![];
+    for layer in layers {
+        for (offset, channel) in layer.kind.channels().iter().enumerate() {
+            let name = if layer.name.is_empty() {
+                channel.to_string()
+            } else {
+                format!("{}.{}", layer.name, channel)
+            };
+            channels.push((name, layer, offset));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = vec![];
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+
+    let mut list = vec![];
+    for (name, _, _) in channels.iter() {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        // Float pixels, not linear, 3 reserved bytes, no subsampling.
+        list.extend_from_slice(&2i32.to_le_bytes());
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute("channels", "chlist", &list);
+    attribute("compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes().to_vec())
+        .collect();
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    for (name, value) in attributes {
+        attribute(name, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    // Magic number and version 2, followed by the offset of every scanline.
+    let mut data = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    data.extend_from_slice(&header);
+    let line_size = width * channels.len() * 4;
+    let first_line = data.len() + height * 8;
+    for y in 0..height {
+        let offset = first_line + y * (line_size + 8);
+        data.extend_from_slice(&(offset as u64).to_le_bytes());
+    }
+
+    for y in 0..height {
+        data.extend_from_slice(&(y as i32).to_le_bytes());
+        data.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for (_, layer, offset) in channels.iter() {
+            let stride = layer.kind.channels().len();
+            for x in 0..width {
+                let value = layer.values[(y * width + x) * stride + offset];
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    std::fs::write(path, data)
+}
@@ -0,0 +1,189 @@
+use super::bvh::{Aabb, Bvh};
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::ply::Ply;
+use super::primitive::Hit;
+use super::ray::Ray;
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/*
+    Large amount of spheres sharing one material, with a BVH over the particles. Colors are
+    optional, when given they replace the albedo of the material.
+*/
+pub struct Particles {
+    positions: Vec<Vec3>,
+    radii: Vec<f64>,
+    colors: Vec<Vec3>,
+    bvh: Bvh,
+}
+
+impl Particles {
+    pub fn new(positions: Vec<Vec3>, radii: Vec<f64>, colors: Vec<Vec3>) -> Particles {
+        assert_eq!(
+            positions.len(),
+            radii.len(),
+            "Every particle needs a radius"
+        );
+        assert!(
+            colors.is_empty() || colors.len() == positions.len(),
+            "Every particle needs a color"
+        );
+        let bounds: Vec<Option<Aabb>> = positions
+            .iter()
+            .zip(radii.iter())
+            .map(|(&p, &r)| Some(Aabb::new(p - Vec3::fill(r), p + Vec3::fill(r))))
+            .collect();
+        Particles {
+            bvh: Bvh::build(&bounds),
+            positions,
+            radii,
+            colors,
+        }
+    }
+
+    /*
+        Loads the vertex element of a PLY file, x, y and z with an optional radius (also read
+        from pscale or scale) and optional red, green and blue (0 to 255 for integer types).
+    */
+    pub fn load_ply(path: &Path, default_radius: f64) -> Result<Particles, Error> {
+        let ply = Ply::load(path)?;
+        let vertex = ply
+            .element("vertex")
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "PLY file has no vertices"))?;
+        let positions = vertex
+            .vectors(["x", "y", "z"])
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "PLY vertex has no x, y and z"))?;
+        let radii = match ["radius", "pscale", "scale"]
+            .iter()
+            .find_map(|name| vertex.scalar(name))
+        {
+            Some(radii) => radii.to_vec(),
+            None => vec![default_radius; vertex.count],
+        };
+        let colors = vertex.colors().unwrap_or_default();
+
+        Ok(Particles::new(positions, radii, colors))
+    }
+
+    /*
+        Loads raw little endian 32 bit floats, per particle x, y, z and the radius, followed by
+        red, green and blue (0 to 1) when the file has colors.
+    */
+    pub fn load_binary(path: &Path, has_colors: bool) -> Result<Particles, Error> {
+        let data = fs::read(path)?;
+        let floats = if has_colors { 7 } else { 4 };
+        let record = floats * 4;
+        if data.len() % record != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Particle file size is not a multiple of the record size",
+            ));
+        }
+
+        let count = data.len() / record;
+        let mut positions = Vec::with_capacity(count);
+        let mut radii = Vec::with_capacity(count);
+        let mut colors = Vec::with_capacity(if has_colors { count } else { 0 });
+        for chunk in data.chunks(record) {
+            let value = |i: usize| {
+                f32::from_le_bytes([
+                    chunk[i * 4],
+                    chunk[i * 4 + 1],
+                    chunk[i * 4 + 2],
+                    chunk[i * 4 + 3],
+                ]) as f64
+            };
+            positions.push(Vec3(value(0), value(1), value(2)));
+            radii.push(value(3));
+            if has_colors {
+                colors.push(Vec3(value(4), value(5), value(6)));
+            }
+        }
+
+        Ok(Particles::new(positions, radii, colors))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds().unwrap_or_else(Aabb::empty)
+    }
+
+    fn intersect_sphere(&self, i: usize, o: Vec3, d: Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+        let oc = o - self.positions[i];
+        let a = d.squared();
+        let half_b = Vec3::dot(oc, d);
+        let c = oc.squared() - self.radii[i] * self.radii[i];
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [(-half_b - root) / a, (-half_b + root) / a]
+            .iter()
+            .copied()
+            .find(|&t| t >= t_min && t < t_max)
+    }
+
+    // Closest hit between t_min and t_max with the color of the particle, when there are colors.
+    pub fn intersect(
+        &self,
+        o: Vec3,
+        d: Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(Hit, Option<Vec3>)> {
+        let mut ray = Ray::new(o, d);
+        ray.travel_distance = t_max;
+
+        let mut closest = None;
+        self.bvh.traverse(&mut ray, t_min, |i, ray| {
+            if let Some(t) = self.intersect_sphere(i, o, d, t_min, ray.travel_distance) {
+                ray.travel_distance = t;
+                closest = Some((i, t));
+            }
+        });
+
+        closest.map(|(i, t)| {
+            let normal = (o + d * t - self.positions[i]) / self.radii[i];
+            let uv = Vec2(
+                ((-normal.2).atan2(normal.0) + PI) / (2.0 * PI),
+                (-normal.1).acos() / PI,
+            );
+            (Hit { t, normal, uv }, self.colors.get(i).copied())
+        })
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_particles_intersect() {
+        let positions = (0..100).map(|i| Vec3(i as f64, 0.0, 0.0)).collect();
+        let colors = (0..100).map(|i| Vec3::fill(i as f64 / 100.0)).collect();
+        let particles = Particles::new(positions, vec![0.25; 100], colors);
+
+        let (hit, color) = particles
+            .intersect(Vec3(42.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0), 0.001, f64::MAX)
+            .unwrap();
+        assert_approx_eq!(hit.t, 4.75, ASSERT_MARGIN);
+        assert_approx_eq!(hit.normal.1, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(color.unwrap().0, 0.42, ASSERT_MARGIN);
+
+        assert!(particles
+            .intersect(Vec3(42.5, 5.0, 0.0), Vec3(0.0, -1.0, 0.0), 0.001, f64::MAX)
+            .is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::mesh::{self, Mesh};
+
+/*
+    Bicubic Bezier patch, 4 rows of 4 control points. Rows go along v, the points in a row
+    along u, the surface faces the side of cross(du, dv).
+*/
+#[derive(Clone, Copy)]
+pub struct BezierPatch {
+    pub control: [[Vec3; 4]; 4],
+}
+
+// Cubic Bernstein polynomials and their derivatives.
+fn bernstein(t: f64) -> ([f64; 4], [f64; 4]) {
+    let s = 1.0 - t;
+    (
+        [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+        [
+            -3.0 * s * s,
+            3.0 * s * s - 6.0 * t * s,
+            6.0 * t * s - 3.0 * t * t,
+            3.0 * t * t,
+        ],
+    )
+}
+
+impl BezierPatch {
+    // Point on the patch with the derivatives along u and v.
+    pub fn eval(&self, u: f64, v: f64) -> (Vec3, Vec3, Vec3) {
+        let (bu, du) = bernstein(u);
+        let (bv, dv) = bernstein(v);
+
+        let mut p = Vec3::zero();
+        let mut p_u = Vec3::zero();
+        let mut p_v = Vec3::zero();
+        for (row, points) in self.control.iter().enumerate() {
+            for (column, &point) in points.iter().enumerate() {
+                p += point * (bu[column] * bv[row]);
+                p_u += point * (du[column] * bv[row]);
+                p_v += point * (bu[column] * dv[row]);
+            }
+        }
+        (p, p_u, p_v)
+    }
+
+    // Normal from the derivatives, degenerate edges (all points at a pole) fall back to None.
+    pub fn normal(&self, u: f64, v: f64) -> Option<Vec3> {
+        let (_, p_u, p_v) = self.eval(u, v);
+        let n = Vec3::cross(p_u, p_v);
+        if n.squared() < 1e-20 {
+            return None;
+        }
+        Some(n.normalize())
+    }
+}
+
+/*
+    Tessellates the patches into a grid of resolution x resolution quads each. Uvs are the
+    parameters of the patch. Normals come from the surface, except where the patch is
+    degenerate and the normal is averaged from the triangles instead.
+*/
+pub fn tessellate(patches: &[BezierPatch], resolution: usize) -> Mesh {
+    let resolution = resolution.max(1);
+    let side = resolution + 1;
+
+    let mut positions = Vec::with_capacity(patches.len() * side * side);
+    let mut normals = Vec::with_capacity(positions.capacity());
+    let mut uvs = Vec::with_capacity(positions.capacity());
+    let mut triangles = Vec::with_capacity(patches.len() * resolution * resolution * 2);
+
+    for patch in patches.iter() {
+        let first = positions.len();
+        for j in 0..side {
+            for i in 0..side {
+                let (u, v) = (i as f64 / resolution as f64, j as f64 / resolution as f64);
+                positions.push(patch.eval(u, v).0);
+                normals.push(patch.normal(u, v));
+                uvs.push(Vec2(u, v));
+            }
+        }
+
+        for j in 0..resolution {
+            for i in 0..resolution {
+                let corner = first + j * side + i;
+                triangles.push([corner, corner + 1, corner + side + 1]);
+                triangles.push([corner, corner + side + 1, corner + side]);
+            }
+        }
+    }
+
+    let averaged = mesh::smooth_normals(&positions, &triangles);
+    let normals = normals
+        .iter()
+        .zip(averaged)
+        .map(|(n, averaged)| n.unwrap_or(averaged))
+        .collect();
+    Mesh::new(positions, normals, uvs, triangles)
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_flat_patch() {
+        let mut control = [[Vec3::zero(); 4]; 4];
+        for (row, points) in control.iter_mut().enumerate() {
+            for (column, point) in points.iter_mut().enumerate() {
+                *point = Vec3(column as f64, 0.0, -(row as f64));
+            }
+        }
+        let patch = BezierPatch { control };
+
+        let (p, _, _) = patch.eval(0.5, 0.25);
+        assert_approx_eq!(p.0, 1.5, ASSERT_MARGIN);
+        assert_approx_eq!(p.2, -0.75, ASSERT_MARGIN);
+        assert_approx_eq!(patch.normal(0.5, 0.5).unwrap().1, 1.0, ASSERT_MARGIN);
+
+        let mesh = tessellate(&[patch], 4);
+        assert_eq!(mesh.positions.len(), 25);
+        assert_eq!(mesh.triangles.len(), 32);
+    }
+}
@@ -0,0 +1,402 @@
+use super::math::vector::Vec3;
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/*
+    Reader for PLY files (ascii and binary, both byte orders). Every element is stored per
+    property, so a single column (like x of all vertices) can be taken out without copying.
+*/
+pub struct Ply {
+    pub elements: Vec<Element>,
+}
+
+pub struct Element {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<(String, Column)>,
+    // Declared type of the (items of the) properties.
+    types: Vec<Type>,
+}
+
+pub enum Column {
+    Scalar(Vec<f64>),
+    List(Vec<Vec<f64>>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum PropertyType {
+    Scalar(Type),
+    List(Type, Type),
+}
+
+// Name, count and properties of an element as declared in the header.
+type Layout = (String, usize, Vec<(String, PropertyType)>);
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn parse_type(name: &str) -> Result<Type, Error> {
+    Ok(match name {
+        "char" | "int8" => Type::I8,
+        "uchar" | "uint8" => Type::U8,
+        "short" | "int16" => Type::I16,
+        "ushort" | "uint16" => Type::U16,
+        "int" | "int32" => Type::I32,
+        "uint" | "uint32" => Type::U32,
+        "float" | "float32" => Type::F32,
+        "double" | "float64" => Type::F64,
+        _ => return Err(invalid(format!("Unknown PLY type {}", name))),
+    })
+}
+
+impl Type {
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8,
+        }
+    }
+
+    // Largest value of integer types, which is full intensity for colors.
+    fn full_scale(self) -> f64 {
+        match self {
+            Type::I8 => i8::MAX as f64,
+            Type::U8 => u8::MAX as f64,
+            Type::I16 => i16::MAX as f64,
+            Type::U16 => u16::MAX as f64,
+            Type::I32 => i32::MAX as f64,
+            Type::U32 => u32::MAX as f64,
+            Type::F32 | Type::F64 => 1.0,
+        }
+    }
+}
+
+// Source of values, either whitespace separated text or binary data.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    format: Format,
+}
+
+impl<'a> Reader<'a> {
+    /*
+        Number of values of the type that fit in the rest of the data, at most count. Text
+        values take at least a byte. Keeps sizes from the header from allocating more than the
+        file can hold.
+    */
+    fn capacity(&self, count: usize, value_size: usize) -> usize {
+        let size = match self.format {
+            Format::Ascii => 1,
+            _ => value_size.max(1),
+        };
+        count.min((self.data.len() - self.position) / size)
+    }
+
+    fn read(&mut self, kind: Type) -> Result<f64, Error> {
+        if self.format == Format::Ascii {
+            return self.read_ascii();
+        }
+
+        let size = kind.size();
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .ok_or_else(|| invalid("Unexpected end of PLY data".to_string()))?;
+        self.position += size;
+
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            buffer[..size].reverse();
+        }
+        let b2 = [buffer[0], buffer[1]];
+        let b4 = [buffer[0], buffer[1], buffer[2], buffer[3]];
+        Ok(match kind {
+            Type::I8 => buffer[0] as i8 as f64,
+            Type::U8 => buffer[0] as f64,
+            Type::I16 => i16::from_le_bytes(b2) as f64,
+            Type::U16 => u16::from_le_bytes(b2) as f64,
+            Type::I32 => i32::from_le_bytes(b4) as f64,
+            Type::U32 => u32::from_le_bytes(b4) as f64,
+            Type::F32 => f32::from_le_bytes(b4) as f64,
+            Type::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    fn read_ascii(&mut self) -> Result<f64, Error> {
+        while self.position < self.data.len() && self.data[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.position < self.data.len() && !self.data[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        let token = std::str::from_utf8(&self.data[start..self.position])
+            .map_err(|_| invalid("Invalid PLY text".to_string()))?;
+        token
+            .parse()
+            .map_err(|_| invalid(format!("Invalid PLY value '{}'", token)))
+    }
+}
+
+impl Ply {
+    pub fn load(path: &Path) -> Result<Ply, Error> {
+        Ply::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Ply, Error> {
+        // The header is text, ending with the end_header line.
+        let marker = b"end_header";
+        let header_end = data
+            .windows(marker.len())
+            .position(|w| w == marker)
+            .ok_or_else(|| invalid("PLY header has no end_header".to_string()))?;
+        let body_start = data[header_end..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |i| header_end + i + 1);
+        let header = std::str::from_utf8(&data[..header_end])
+            .map_err(|_| invalid("Invalid PLY header".to_string()))?;
+
+        let mut lines = header.lines();
+        if lines.next().map(str::trim) != Some("ply") {
+            return Err(invalid("Not a PLY file".to_string()));
+        }
+
+        let mut format = None;
+        let mut layout: Vec<Layout> = vec![];
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", _] => format = Some(Format::Ascii),
+                ["format", "binary_little_endian", _] => format = Some(Format::LittleEndian),
+                ["format", "binary_big_endian", _] => format = Some(Format::BigEndian),
+                ["element", name, count] => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid element count {}", count)))?;
+                    layout.push((name.to_string(), count, vec![]));
+                }
+                ["property", "list", count_type, item_type, name] => {
+                    let property =
+                        PropertyType::List(parse_type(count_type)?, parse_type(item_type)?);
+                    match layout.last_mut() {
+                        Some(element) => element.2.push((name.to_string(), property)),
+                        None => return Err(invalid("PLY property before element".to_string())),
+                    }
+                }
+                ["property", kind, name] => match layout.last_mut() {
+                    Some(element) => element
+                        .2
+                        .push((name.to_string(), PropertyType::Scalar(parse_type(kind)?))),
+                    None => return Err(invalid("PLY property before element".to_string())),
+                },
+                _ => {}
+            }
+        }
+
+        let mut reader = Reader {
+            data: &data[body_start..],
+            position: 0,
+            format: format.ok_or_else(|| invalid("PLY header has no format".to_string()))?,
+        };
+
+        let mut elements = Vec::with_capacity(layout.len());
+        for (name, count, properties) in layout {
+            // Every element takes at least a value or list length per property.
+            let element_size = properties
+                .iter()
+                .map(|(_, kind)| match kind {
+                    PropertyType::Scalar(t) | PropertyType::List(t, _) => t.size(),
+                })
+                .sum();
+            let capacity = reader.capacity(count, element_size);
+            let mut columns: Vec<Column> = properties
+                .iter()
+                .map(|(_, kind)| match kind {
+                    PropertyType::Scalar(_) => Column::Scalar(Vec::with_capacity(capacity)),
+                    PropertyType::List(_, _) => Column::List(Vec::with_capacity(capacity)),
+                })
+                .collect();
+
+            for _ in 0..count {
+                for ((_, kind), column) in properties.iter().zip(columns.iter_mut()) {
+                    match (kind, column) {
+                        (PropertyType::Scalar(t), Column::Scalar(values)) => {
+                            values.push(reader.read(*t)?)
+                        }
+                        (PropertyType::List(count_type, item_type), Column::List(lists)) => {
+                            let length = reader.read(*count_type)? as usize;
+                            let mut list =
+                                Vec::with_capacity(reader.capacity(length, item_type.size()));
+                            for _ in 0..length {
+                                list.push(reader.read(*item_type)?);
+                            }
+                            lists.push(list);
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+
+            let types = properties
+                .iter()
+                .map(|(_, kind)| match kind {
+                    PropertyType::Scalar(t) | PropertyType::List(_, t) => *t,
+                })
+                .collect();
+            elements.push(Element {
+                name,
+                count,
+                properties: properties
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .zip(columns)
+                    .collect(),
+                types,
+            });
+        }
+
+        Ok(Ply { elements })
+    }
+
+    pub fn element(&self, name: &str) -> Option<&Element> {
+        self.elements.iter().find(|e| e.name == name)
+    }
+}
+
+impl Element {
+    pub fn scalar(&self, name: &str) -> Option<&[f64]> {
+        self.properties.iter().find_map(|(n, column)| match column {
+            Column::Scalar(values) if n == name => Some(values.as_slice()),
+            _ => None,
+        })
+    }
+
+    pub fn list(&self, name: &str) -> Option<&[Vec<f64>]> {
+        self.properties.iter().find_map(|(n, column)| match column {
+            Column::List(lists) if n == name => Some(lists.as_slice()),
+            _ => None,
+        })
+    }
+
+    // Three scalar properties as vectors, like x, y and z.
+    pub fn vectors(&self, names: [&str; 3]) -> Option<Vec<Vec3>> {
+        let (x, y, z) = (
+            self.scalar(names[0])?,
+            self.scalar(names[1])?,
+            self.scalar(names[2])?,
+        );
+        Some((0..self.count).map(|i| Vec3(x[i], y[i], z[i])).collect())
+    }
+
+    /*
+        Red, green and blue between 0 and 1. Integer colors go up to the largest value of their
+        type (255 for uchar), float colors are used as they are.
+    */
+    pub fn colors(&self) -> Option<Vec<Vec3>> {
+        let colors = self.vectors(["red", "green", "blue"])?;
+        let scale = |name: &str| {
+            let index = self.properties.iter().position(|(n, _)| n == name)?;
+            Some(1.0 / self.types[index].full_scale())
+        };
+        let scale = Vec3(scale("red")?, scale("green")?, scale("blue")?);
+        Some(colors.into_iter().map(|c| c * scale).collect())
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ascii() {
+        let data = b"ply\nformat ascii 1.0\ncomment test\nelement vertex 3\nproperty float x\n\
+            property float y\nproperty float z\nelement face 1\n\
+            property list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let ply = Ply::parse(data).unwrap();
+
+        let vertex = ply.element("vertex").unwrap();
+        assert_eq!(vertex.count, 3);
+        assert_eq!(vertex.scalar("x").unwrap(), &[0.0, 1.0, 0.0]);
+        let faces = ply.element("face").unwrap().list("vertex_indices").unwrap();
+        assert_eq!(faces[0], vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let mut data = b"ply\nformat binary_big_endian 1.0\nelement point 2\n\
+            property double x\nproperty uchar red\nend_header\n"
+            .to_vec();
+        for &(x, red) in [(1.5f64, 255u8), (-2.0, 0)].iter() {
+            data.extend_from_slice(&x.to_be_bytes());
+            data.push(red);
+        }
+        let ply = Ply::parse(&data).unwrap();
+
+        let point = ply.element("point").unwrap();
+        assert_eq!(point.scalar("x").unwrap(), &[1.5, -2.0]);
+        assert_eq!(point.scalar("red").unwrap(), &[255.0, 0.0]);
+    }
+
+    #[test]
+    fn test_sizes_beyond_the_data() {
+        // Counts and list lengths from the header are not trusted to allocate.
+        let data = b"ply\nformat binary_little_endian 1.0\nelement vertex 4000000000\n\
+            property float x\nend_header\n\0\0\0\0";
+        assert!(Ply::parse(data).is_err());
+
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement face 1\n\
+            property list uint int vertex_indices\nend_header\n"
+            .to_vec();
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        assert!(Ply::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_colors_by_type() {
+        let colors = |kind: &str, values: &str| {
+            let data = format!(
+                "ply\nformat ascii 1.0\nelement vertex 1\nproperty {0} red\n\
+                 property {0} green\nproperty {0} blue\nend_header\n{1}\n",
+                kind, values
+            );
+            Ply::parse(data.as_bytes()).unwrap().elements[0]
+                .colors()
+                .unwrap()[0]
+        };
+        // Dark colors are not mistaken for float colors.
+        assert_eq!(colors("uchar", "1 0 255"), Vec3(1.0 / 255.0, 0.0, 1.0));
+        assert_eq!(colors("ushort", "65535 0 0"), Vec3(1.0, 0.0, 0.0));
+        assert_eq!(colors("float", "0.5 2 0"), Vec3(0.5, 2.0, 0.0));
+    }
+}
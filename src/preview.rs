@@ -0,0 +1,188 @@
+use super::color::{self, ColorPipeline};
+use super::film::Film;
+use super::output::{self, BitDepth};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/*
+    Live view of the render for headless machines (draw-debugger feature). A small HTTP server
+    serves the latest progressive frame, open http://<address>/ in a browser to watch it. The
+    server only listens on this machine, use an ssh tunnel (ssh -L 8000:localhost:8000 <host>)
+    to watch a remote render or change the address to 0.0.0.0 to listen on all interfaces.
+*/
+pub const ADDRESS: &str = "127.0.0.1:8000";
+
+// Seconds between progressive frames.
+pub const INTERVAL: f32 = 0.5;
+
+// Latest frame as png, with a count so the page can tell when the render moved on.
+#[derive(Default)]
+struct Frame {
+    png: Vec<u8>,
+    number: u64,
+}
+
+// Started by the first frame, keeps serving until the program ends. None if it could not start.
+static SERVER: OnceLock<Option<Arc<Mutex<Frame>>>> = OnceLock::new();
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Render preview</title></head>
+<body style="margin: 0; background: #202020; color: #a0a0a0; font-family: sans-serif">
+<img id="frame" style="display: block; margin: auto; max-width: 100%">
+<p id="status" style="text-align: center">Waiting for the first frame</p>
+<script>
+let shown = -1;
+async function update() {
+    try {
+        const number = Number(await (await fetch("/frame", { cache: "no-store" })).text());
+        if (number !== shown) {
+            const image = new Image();
+            image.onload = () => {
+                document.getElementById("frame").src = image.src;
+                document.getElementById("status").textContent = "Frame " + number;
+            };
+            image.src = "/frame.png?" + number;
+            shown = number;
+        }
+    } catch (error) {
+        document.getElementById("status").textContent = "Render stopped";
+    }
+    setTimeout(update, 500);
+}
+update();
+</script>
+</body>
+</html>
+"#;
+
+// Shows the rgb image data in the preview, starts the server on the first call.
+pub fn publish(width: usize, height: usize, depth: BitDepth, image_data: &[u8]) {
+    let frame = match SERVER.get_or_init(start) {
+        Some(frame) => frame,
+        None => return,
+    };
+
+    let mut png = vec![];
+    if let Err(error) = output::encode_png(&mut png, width, height, depth, image_data) {
+        println!("Could not encode the preview: {}", error);
+        return;
+    }
+    let mut frame = frame.lock().unwrap();
+    frame.png = png;
+    frame.number += 1;
+}
+
+// Shows the film of a render in progress, pixels without samples are black.
+pub fn publish_film(film: &Film, pipeline: &ColorPipeline) {
+    let (width, height) = film.size();
+    let mut image = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            color::quantize(
+                pipeline.display(film.pixel(x, y)),
+                BitDepth::Eight,
+                &mut image,
+            );
+        }
+    }
+    publish(width, height, BitDepth::Eight, &image);
+}
+
+fn start() -> Option<Arc<Mutex<Frame>>> {
+    let listener = match TcpListener::bind(ADDRESS) {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!(
+                "Preview not available, could not listen on {}: {}",
+                ADDRESS, error
+            );
+            return None;
+        }
+    };
+    println!("Preview at http://{}/", ADDRESS);
+
+    let frame = Arc::new(Mutex::new(Frame::default()));
+    let shared = Arc::clone(&frame);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let frame = Arc::clone(&shared);
+            // A slow browser should not hold up the others.
+            thread::spawn(move || {
+                let _ = respond(stream, &frame);
+            });
+        }
+    });
+    Some(frame)
+}
+
+fn respond(mut stream: TcpStream, frame: &Mutex<Frame>) -> Result<(), std::io::Error> {
+    // Only the request line is needed, the rest of the header is ignored.
+    let mut request = [0; 1024];
+    let length = stream.read(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..length]);
+
+    let (status, content_type, body) = match path(&request) {
+        Some("/") => ("200 OK", "text/html", PAGE.as_bytes().to_vec()),
+        Some("/frame") => {
+            let number = frame.lock().unwrap().number;
+            ("200 OK", "text/plain", number.to_string().into_bytes())
+        }
+        Some("/frame.png") => {
+            let frame = frame.lock().unwrap();
+            if frame.png.is_empty() {
+                (
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"No frame yet".to_vec(),
+                )
+            } else {
+                ("200 OK", "image/png", frame.png.clone())
+            }
+        }
+        Some(_) => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+        None => ("400 Bad Request", "text/plain", b"Bad request".to_vec()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+// Path of a GET request without the query, like /frame.png for "GET /frame.png?12 HTTP/1.1".
+fn path(request: &str) -> Option<&str> {
+    let mut words = request.lines().next()?.split(' ');
+    if words.next()? != "GET" {
+        return None;
+    }
+    words.next()?.split('?').next()
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(path("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"), Some("/"));
+        assert_eq!(
+            path("GET /frame.png?12 HTTP/1.1\r\n\r\n"),
+            Some("/frame.png")
+        );
+        assert_eq!(path("POST /frame HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(path(""), None);
+    }
+}
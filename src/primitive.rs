@@ -0,0 +1,449 @@
+use super::bvh::Aabb;
+use super::math::solve_quartic;
+use super::math::vector::{Vec2, Vec3, Vector};
+
+use std::f64::consts::PI;
+
+/*
+    Intersection routines of the analytic primitives. Everything is in the space of the
+    primitive (position at the origin), the ray direction does not have to be normalized.
+    Normals are returned pointing outwards, set_intersection flips them when needed.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub t: f64,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+
+const EPSILON: f64 = 1e-9;
+// Flat primitives get a bit of thickness so the bounding box can be hit.
+const BOUNDS_PADDING: f64 = 1e-4;
+
+// Keep the closest of the hits within the range.
+fn closest(
+    current: Option<Hit>,
+    t: f64,
+    t_min: f64,
+    t_max: f64,
+    hit: impl FnOnce() -> Hit,
+) -> Option<Hit> {
+    let limit = current.map_or(t_max, |c| c.t);
+    if t >= t_min && t < limit {
+        return Some(hit());
+    }
+    current
+}
+
+fn angle_u(x: f64, z: f64) -> f64 {
+    (z.atan2(x) + PI) / (2.0 * PI)
+}
+
+// Disc shaped cap in a plane with constant y.
+fn cap(o: Vec3, d: Vec3, y: f64, radius: f64) -> Option<(f64, Vec3)> {
+    if d.1.abs() < EPSILON {
+        return None;
+    }
+    let t = (y - o.1) / d.1;
+    let p = o + d * t;
+    if p.0 * p.0 + p.2 * p.2 <= radius * radius {
+        return Some((t, p));
+    }
+    None
+}
+
+fn cap_uv(p: Vec3, radius: f64) -> Vec2 {
+    Vec2(0.5 + p.0 / (2.0 * radius), 0.5 + p.2 / (2.0 * radius))
+}
+
+// Box centered at the origin.
+pub fn cuboid(o: Vec3, d: Vec3, half_size: Vec3, t_min: f64, t_max: f64) -> Option<Hit> {
+    let mut t_near = f64::NEG_INFINITY;
+    let mut t_far = f64::INFINITY;
+    for &(o, d, half) in [
+        (o.0, d.0, half_size.0),
+        (o.1, d.1, half_size.1),
+        (o.2, d.2, half_size.2),
+    ]
+    .iter()
+    {
+        if d.abs() < EPSILON {
+            if o < -half || o > half {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (-half - o) / d;
+        let t1 = (half - o) / d;
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+
+    if t_near > t_far {
+        return None;
+    }
+
+    let t = if t_near >= t_min { t_near } else { t_far };
+    if t < t_min || t >= t_max {
+        return None;
+    }
+
+    Some(cuboid_hit(o + d * t, t, half_size))
+}
+
+pub fn cuboid_hit(p: Vec3, t: f64, half_size: Vec3) -> Hit {
+    // The face is on the axis where the point is relatively the furthest out.
+    let rel = Vec3(p.0 / half_size.0, p.1 / half_size.1, p.2 / half_size.2);
+    let uv = |a: f64, b: f64| Vec2((a + 1.0) / 2.0, (b + 1.0) / 2.0);
+    let (normal, uv) = if rel.0.abs() >= rel.1.abs() && rel.0.abs() >= rel.2.abs() {
+        (Vec3(rel.0.signum(), 0.0, 0.0), uv(rel.2, rel.1))
+    } else if rel.1.abs() >= rel.2.abs() {
+        (Vec3(0.0, rel.1.signum(), 0.0), uv(rel.0, rel.2))
+    } else {
+        (Vec3(0.0, 0.0, rel.2.signum()), uv(rel.0, rel.1))
+    };
+    Hit { t, normal, uv }
+}
+
+/*
+    Triangle intersection (Moller-Trumbore), returns t and the barycentric coordinates of the
+    second and third corner. Both sides of the triangle are hit.
+*/
+pub fn triangle(
+    o: Vec3,
+    d: Vec3,
+    corners: [Vec3; 3],
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = corners[1] - corners[0];
+    let edge2 = corners[2] - corners[0];
+    let p = Vec3::cross(d, edge2);
+    let det = Vec3::dot(edge1, p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = o - corners[0];
+    let b1 = Vec3::dot(s, p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = Vec3::cross(s, edge1);
+    let b2 = Vec3::dot(d, q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(edge2, q) * inv_det;
+    if t < t_min || t >= t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// Parallelogram with a corner at the origin and the edges u and v.
+pub fn quad(o: Vec3, d: Vec3, u: Vec3, v: Vec3, t_min: f64, t_max: f64) -> Option<Hit> {
+    let n = Vec3::cross(u, v);
+    let denom = Vec3::dot(n, d);
+    if denom.abs() < EPSILON {
+        return None;
+    }
+
+    let t = -Vec3::dot(n, o) / denom;
+    if t < t_min || t >= t_max {
+        return None;
+    }
+
+    // Coordinates of the hit in the basis of the edges.
+    let p = o + d * t;
+    let w = n / Vec3::dot(n, n);
+    let alpha = Vec3::dot(w, Vec3::cross(p, v));
+    let beta = Vec3::dot(w, Vec3::cross(u, p));
+    if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+
+    Some(Hit {
+        t,
+        normal: n.normalize(),
+        uv: Vec2(alpha, beta),
+    })
+}
+
+pub fn disc(o: Vec3, d: Vec3, normal: Vec3, radius: f64, t_min: f64, t_max: f64) -> Option<Hit> {
+    let denom = Vec3::dot(normal, d);
+    if denom.abs() < EPSILON {
+        return None;
+    }
+
+    let t = -Vec3::dot(normal, o) / denom;
+    if t < t_min || t >= t_max {
+        return None;
+    }
+
+    let p = o + d * t;
+    if p.squared() > radius * radius {
+        return None;
+    }
+
+    let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+    Some(Hit {
+        t,
+        normal,
+        uv: Vec2(
+            0.5 + Vec3::dot(p, tangent) / (2.0 * radius),
+            0.5 + Vec3::dot(p, bitangent) / (2.0 * radius),
+        ),
+    })
+}
+
+// Capped cylinder along the y axis, from the origin up to height.
+pub fn cylinder(o: Vec3, d: Vec3, radius: f64, height: f64, t_min: f64, t_max: f64) -> Option<Hit> {
+    let mut hit = None;
+
+    let a = d.0 * d.0 + d.2 * d.2;
+    if a > EPSILON {
+        let b = 2.0 * (o.0 * d.0 + o.2 * d.2);
+        let c = o.0 * o.0 + o.2 * o.2 - radius * radius;
+        let disc = b * b - 4.0 * a * c;
+        if disc >= 0.0 {
+            for &t in [
+                (-b - disc.sqrt()) / (2.0 * a),
+                (-b + disc.sqrt()) / (2.0 * a),
+            ]
+            .iter()
+            {
+                let p = o + d * t;
+                if p.1 >= 0.0 && p.1 <= height {
+                    hit = closest(hit, t, t_min, t_max, || Hit {
+                        t,
+                        normal: Vec3(p.0 / radius, 0.0, p.2 / radius),
+                        uv: Vec2(angle_u(p.0, p.2), p.1 / height),
+                    });
+                }
+            }
+        }
+    }
+
+    for &(y, normal) in [(0.0, -1.0), (height, 1.0)].iter() {
+        if let Some((t, p)) = cap(o, d, y, radius) {
+            hit = closest(hit, t, t_min, t_max, || Hit {
+                t,
+                normal: Vec3(0.0, normal, 0.0),
+                uv: cap_uv(p, radius),
+            });
+        }
+    }
+
+    hit
+}
+
+// Cone along the y axis with the base at the origin and the apex at height.
+pub fn cone(o: Vec3, d: Vec3, radius: f64, height: f64, t_min: f64, t_max: f64) -> Option<Hit> {
+    let mut hit = None;
+
+    // x^2 + z^2 = k^2 (height - y)^2
+    let k2 = (radius / height) * (radius / height);
+    let oy = height - o.1;
+    let dy = -d.1;
+    let a = d.0 * d.0 + d.2 * d.2 - k2 * dy * dy;
+    let b = 2.0 * (o.0 * d.0 + o.2 * d.2 - k2 * oy * dy);
+    let c = o.0 * o.0 + o.2 * o.2 - k2 * oy * oy;
+
+    let roots = if a.abs() < EPSILON {
+        if b.abs() < EPSILON {
+            vec![]
+        } else {
+            vec![-c / b]
+        }
+    } else {
+        let disc = b * b - 4.0 * a * c;
+        if disc < 0.0 {
+            vec![]
+        } else {
+            vec![
+                (-b - disc.sqrt()) / (2.0 * a),
+                (-b + disc.sqrt()) / (2.0 * a),
+            ]
+        }
+    };
+
+    for t in roots {
+        let p = o + d * t;
+        if p.1 >= 0.0 && p.1 <= height {
+            hit = closest(hit, t, t_min, t_max, || Hit {
+                t,
+                normal: Vec3(p.0, k2 * (height - p.1), p.2).normalize(),
+                uv: Vec2(angle_u(p.0, p.2), p.1 / height),
+            });
+        }
+    }
+
+    if let Some((t, p)) = cap(o, d, 0.0, radius) {
+        hit = closest(hit, t, t_min, t_max, || Hit {
+            t,
+            normal: Vec3(0.0, -1.0, 0.0),
+            uv: cap_uv(p, radius),
+        });
+    }
+
+    hit
+}
+
+// Torus around the y axis.
+pub fn torus(o: Vec3, d: Vec3, major: f64, minor: f64, t_min: f64, t_max: f64) -> Option<Hit> {
+    torus_hits(o, d, major, minor)
+        .into_iter()
+        .find(|hit| hit.t >= t_min && hit.t < t_max)
+}
+
+// All crossings of the line with the torus, sorted on t.
+pub fn torus_hits(o: Vec3, d: Vec3, major: f64, minor: f64) -> Vec<Hit> {
+    // Solve with a normalized direction, starting close to the torus for precision.
+    let length = d.length();
+    let d = d / length;
+    let start = (-Vec3::dot(o, d) - (major + minor)).max(0.0);
+    let o = o + d * start;
+
+    let r2 = major * major;
+    let n = Vec3::dot(o, d);
+    let k = Vec3::dot(o, o) + r2 - minor * minor;
+    let roots = solve_quartic([
+        k * k - 4.0 * r2 * (o.0 * o.0 + o.2 * o.2),
+        4.0 * n * k - 8.0 * r2 * (o.0 * d.0 + o.2 * d.2),
+        4.0 * n * n + 2.0 * k - 4.0 * r2 * (d.0 * d.0 + d.2 * d.2),
+        4.0 * n,
+        1.0,
+    ]);
+
+    let mut hits: Vec<Hit> = roots
+        .into_iter()
+        .map(|s| {
+            let p = o + d * s;
+            let ring = Vec3(p.0, 0.0, p.2).normalize() * major;
+            Hit {
+                t: (start + s) / length,
+                normal: (p - ring).normalize(),
+                uv: Vec2(
+                    angle_u(p.0, p.2),
+                    angle_u(Vec3(p.0, 0.0, p.2).length() - major, p.1),
+                ),
+            }
+        })
+        .collect();
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap_or(std::cmp::Ordering::Equal));
+    hits
+}
+
+/***
+ *  Bounds, in the space of the primitive
+***/
+
+pub fn cuboid_bounds(half_size: Vec3) -> Aabb {
+    Aabb::new(-half_size, half_size)
+}
+
+pub fn quad_bounds(u: Vec3, v: Vec3) -> Aabb {
+    let b = Aabb::new(Vec3::zero(), u).grow(v).grow(u + v);
+    Aabb::new(
+        b.min - Vec3::fill(BOUNDS_PADDING),
+        b.max + Vec3::fill(BOUNDS_PADDING),
+    )
+}
+
+pub fn disc_bounds(normal: Vec3, radius: f64) -> Aabb {
+    let extent = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt() + BOUNDS_PADDING;
+    let e = Vec3(extent(normal.0), extent(normal.1), extent(normal.2));
+    Aabb::new(-e, e)
+}
+
+pub fn cylinder_bounds(radius: f64, height: f64) -> Aabb {
+    Aabb::new(Vec3(-radius, 0.0, -radius), Vec3(radius, height, radius))
+}
+
+pub fn torus_bounds(major: f64, minor: f64) -> Aabb {
+    let outer = major + minor;
+    Aabb::new(Vec3(-outer, -minor, -outer), Vec3(outer, minor, outer))
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+    const DOWN: Vec3 = Vec3(0.0, -1.0, 0.0);
+
+    #[test]
+    fn test_cuboid() {
+        let hit = cuboid(
+            Vec3(0.0, 5.0, 0.0),
+            DOWN,
+            Vec3(1.0, 2.0, 1.0),
+            0.001,
+            f64::MAX,
+        )
+        .unwrap();
+        assert_approx_eq!(hit.t, 3.0, ASSERT_MARGIN);
+        assert_eq!(hit.normal, Vec3(0.0, 1.0, 0.0));
+
+        // From the inside the far side is hit.
+        let hit = cuboid(Vec3::zero(), DOWN, Vec3(1.0, 2.0, 1.0), 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(hit.t, 2.0, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_cylinder_and_cone() {
+        let hit = cylinder(Vec3(0.0, 5.0, 0.0), DOWN, 1.0, 2.0, 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(hit.t, 3.0, ASSERT_MARGIN);
+        assert_eq!(hit.normal, Vec3(0.0, 1.0, 0.0));
+
+        let side = Vec3(-1.0, 0.0, 0.0);
+        let hit = cone(Vec3(5.0, 1.0, 0.0), side, 1.0, 2.0, 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(hit.t, 4.5, ASSERT_MARGIN);
+        assert!(hit.normal.0 > 0.0 && hit.normal.1 > 0.0);
+    }
+
+    #[test]
+    fn test_torus() {
+        // Straight down through the tube.
+        let hit = torus(Vec3(2.0, 10.0, 0.0), DOWN, 2.0, 0.5, 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(hit.t, 9.5, ASSERT_MARGIN);
+        assert_approx_eq!(hit.normal.1, 1.0, ASSERT_MARGIN);
+
+        // Through the hole.
+        assert!(torus(Vec3(0.0, 10.0, 0.0), DOWN, 2.0, 0.5, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_quad_and_disc() {
+        let u = Vec3(2.0, 0.0, 0.0);
+        let v = Vec3(0.0, 0.0, 2.0);
+        let hit = quad(Vec3(0.5, 1.0, 1.5), DOWN, u, v, 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(hit.t, 1.0, ASSERT_MARGIN);
+        assert_approx_eq!(hit.uv.0, 0.25, ASSERT_MARGIN);
+        assert_approx_eq!(hit.uv.1, 0.75, ASSERT_MARGIN);
+        assert!(quad(Vec3(2.5, 1.0, 0.5), DOWN, u, v, 0.001, f64::MAX).is_none());
+
+        assert!(disc(Vec3(0.5, 1.0, 0.0), DOWN, Vec3::up(), 1.0, 0.001, f64::MAX).is_some());
+        assert!(disc(Vec3(1.5, 1.0, 0.0), DOWN, Vec3::up(), 1.0, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_triangle() {
+        let corners = [Vec3::zero(), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)];
+        let (t, b1, b2) = triangle(Vec3(0.25, 2.0, 0.5), DOWN, corners, 0.001, f64::MAX).unwrap();
+        assert_approx_eq!(t, 2.0, ASSERT_MARGIN);
+        assert_approx_eq!(b1, 0.25, ASSERT_MARGIN);
+        assert_approx_eq!(b2, 0.5, ASSERT_MARGIN);
+        assert!(triangle(Vec3(0.75, 2.0, 0.5), DOWN, corners, 0.001, f64::MAX).is_none());
+    }
+}
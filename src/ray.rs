@@ -1,4 +1,6 @@
 use super::material::Material;
+use super::math::vector::Vec2;
+use super::spectrum::Wavelengths;
 use super::Vec3;
 use std::sync::Arc;
 
@@ -7,6 +9,14 @@ pub struct IntersectData {
     pub position: Vec3,
     pub normal: Vec3,
     pub front_face: bool,
+    // Surface coordinates, for texturing.
+    pub uv: Vec2,
+    // Direction along the fiber, only set for curves.
+    pub tangent: Option<Vec3>,
+    // Color of the surface that replaces the albedo of the material (colored particles).
+    pub color: Option<Vec3>,
+    // Index of the scene object that was hit, objects in instances share the index.
+    pub object: Option<usize>,
 }
 
 pub struct Ray {
@@ -14,6 +24,10 @@ pub struct Ray {
     pub direction: Vec3,
     pub origin: Vec3,
     pub travel_distance: f64,
+    // Only set when rendering in spectral mode.
+    pub wavelengths: Option<Wavelengths>,
+    // Moment within the shutter interval, used for moving objects.
+    pub time: f64,
 }
 
 impl Ray {
@@ -23,6 +37,17 @@ impl Ray {
             direction,
             origin,
             travel_distance: std::f64::MAX,
+            wavelengths: None,
+            time: 0.0,
+        }
+    }
+
+    // New ray that continues the path of this ray (keeps the sampled wavelengths and time).
+    pub fn scattered(&self, origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            wavelengths: self.wavelengths,
+            time: self.time,
+            ..Ray::new(origin, direction)
         }
     }
 
@@ -31,7 +56,7 @@ impl Ray {
     }
 
     //pub fn set_intersection(&mut self, ray: &Ray, mat: Rc<Material>, normal: Vec3) {
-    pub fn set_intersection(&mut self, t: f64, material: Arc<Material>, normal: Vec3) {
+    pub fn set_intersection(&mut self, t: f64, material: Arc<Material>, normal: Vec3, uv: Vec2) {
         let _is_inside = Vec3::dot(self.direction, normal) < 0.0;
         self.travel_distance = t;
         self.is_intersected = Some(IntersectData {
@@ -39,6 +64,31 @@ impl Ray {
             position: self.at(t),
             front_face: _is_inside,
             normal: if _is_inside { normal } else { -normal },
+            uv,
+            tangent: None,
+            color: None,
+            object: None,
         });
     }
+
+    // Sets the tangent of the current intersection, call after set_intersection.
+    pub fn set_tangent(&mut self, tangent: Vec3) {
+        if let Some(hit) = &mut self.is_intersected {
+            hit.tangent = Some(tangent);
+        }
+    }
+
+    // Sets the scene object of the current intersection, call after set_intersection.
+    pub fn set_object(&mut self, object: usize) {
+        if let Some(hit) = &mut self.is_intersected {
+            hit.object = Some(object);
+        }
+    }
+
+    // Sets the color of the current intersection, call after set_intersection.
+    pub fn set_color(&mut self, color: Vec3) {
+        if let Some(hit) = &mut self.is_intersected {
+            hit.color = Some(color);
+        }
+    }
 }
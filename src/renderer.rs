@@ -1,140 +1,867 @@
+use super::aov::{self, AovOutput, AovSample};
+use super::camera::Eye;
+use super::checkpoint;
+use super::color::ColorPipeline;
+use super::cryptomatte::Manifest;
+use super::denoise::Denoiser;
+use super::film::{Features, Film, Filter};
 use super::material;
-use super::math::vector::Vec3;
+use super::math::vector::{Vec3, Vector};
+use super::output::{self, Layer};
+#[cfg(feature = "draw-debugger")]
+use super::preview;
 use super::ray::Ray;
-use super::scene::Scene;
-use super::shape;
+use super::sampler::{self, Sampler, SamplerType};
+use super::scene::{Names, Scene};
+use super::spectrum::{self, Wavelengths};
 use super::threadpool::ThreadPool;
-extern crate rand;
-use rand::prelude::*;
 
-use std::ops::Deref;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
 pub const MAX_RAY_DEPTH: u16 = 50;
 pub const RAYS_PER_PIXEL: u16 = 500;
-pub const NUM_THREADS: usize = 16;
-const BYTES_PIXEL: usize = 3;
 
+#[derive(Clone)]
 pub struct RenderSettings {
     pub screen_width: usize,
     pub screen_height: usize,
+    // Trace wavelengths instead of RGB, needed for dispersion.
+    pub spectral: bool,
+    // Render these frames of the scene animation instead of a single image.
+    pub frames: Option<RangeInclusive<usize>>,
+    // Render a left and right eye with the scene camera.
+    pub stereo: Option<Stereo>,
+    // Reconstruction filter that spreads the samples over the pixels.
+    pub filter: Filter,
+    // Generator of the numbers for the pixel, lens, time, wavelength and BSDF samples.
+    pub sampler: SamplerType,
+    // The same seed gives the same image, independent of the number of threads.
+    pub seed: u64,
+    // Number of threads rendering the pixels.
+    pub threads: usize,
+    // Film to image conversion and the bit depth of the images.
+    pub color: ColorPipeline,
+    // Denoises the film before it is converted, for previews with few samples.
+    pub denoise: Option<Denoiser>,
+    // Render passes (albedo, normal, depth, ids, direct, indirect and per light) and their format.
+    pub aovs: Option<AovOutput>,
+    // Renders only this part of the image, the image and its passes are the size of the crop.
+    pub crop: Option<Crop>,
+    // Saves the render in progress to this file and resumes it from there, see checkpoint.
+    pub checkpoint: Option<PathBuf>,
+    // Scene a checkpoint belongs to, the name of a built-in scene or a scene file with the hash
+    // of its contents.
+    pub scene: String,
 }
 
-pub fn render_scene(scene: Scene, render_setting: &RenderSettings) -> Result<Vec<u8>, String> {
+// Rectangle of pixels, x and y are the top left pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Clone, Copy)]
+pub enum StereoLayout {
+    // One image twice as wide, left eye on the left.
+    SideBySide,
+    // An image per eye.
+    Separate,
+}
+
+// Distances in meters, an infinite convergence keeps the eyes parallel.
+#[derive(Clone)]
+pub struct Stereo {
+    pub interocular: f64,
+    pub convergence: f64,
+    pub layout: StereoLayout,
+}
+
+/*
+    Rendered image with the suffix for its file name, stereo renders can give one per eye.
+    Layers has the float image and its passes when passes are rendered, manifests has the names
+    in the ID mattes of the passes.
+*/
+pub struct View {
+    pub suffix: &'static str,
+    pub width: usize,
+    pub height: usize,
+    pub image: Vec<u8>,
+    pub layers: Vec<Layer>,
+    pub manifests: Vec<Manifest>,
+}
+
+pub fn render_scene(mut scene: Scene, render_setting: &RenderSettings) -> Result<View, String> {
+    let (image_width, image_height) = (render_setting.screen_width, render_setting.screen_height);
+    let crop = render_setting.crop.unwrap_or(Crop {
+        x: 0,
+        y: 0,
+        width: image_width,
+        height: image_height,
+    });
+    if crop.width == 0
+        || crop.height == 0
+        || crop.x + crop.width > image_width
+        || crop.y + crop.height > image_height
+    {
+        return Err(format!(
+            "Crop {:?} is not within the {}x{} image",
+            crop, image_width, image_height
+        ));
+    }
+    if render_setting.threads == 0 {
+        return Err("Rendering needs at least one thread".into());
+    }
+    if render_setting.checkpoint.is_some() && render_setting.aovs.is_some() {
+        return Err("Checkpoints do not store the render passes, turn off one of them".into());
+    }
+
+    // Pixels around the crop splat their samples into it as well, see Film::around.
+    let reach = (render_setting.filter.radius() + 0.5).ceil() as usize;
+    let (x0, y0) = (crop.x.saturating_sub(reach), crop.y.saturating_sub(reach));
+    let region_width = (crop.x + crop.width + reach).min(image_width) - x0;
+    let region_height = (crop.y + crop.height + reach).min(image_height) - y0;
+    let jobs = region_width * region_height;
+
+    let mut film = Film::new(image_width, image_height);
+    let fingerprint = fingerprint(render_setting);
+    let mut next = 0;
+    if let Some(path) = &render_setting.checkpoint {
+        let loaded = checkpoint::load(path, fingerprint, &mut film)
+            .map_err(|e| format!("Could not resume the render: {}", e))?;
+        if let Some(done) = loaded {
+            println!(
+                "Resuming from {}, {} of {} pixels done",
+                path.display(),
+                done,
+                jobs
+            );
+            next = done.min(jobs);
+        }
+    }
+    let resumed = next;
+
     // Create jobs
     println!("Preparing..");
 
     let now = Instant::now();
 
-    let mut pool = ThreadPool::new(NUM_THREADS, scene);
-
-    // Create buffer on heap.
-    let buffer_size: usize =
-        render_setting.screen_width * render_setting.screen_height * BYTES_PIXEL;
-
-    let mut image = {
-        let mut v: Vec<u8> = Vec::with_capacity(buffer_size);
-        unsafe {
-            v.set_len(buffer_size);
-        };
+    scene.build_bvh();
+    let names = Arc::new(Names::new(&scene));
 
-        v
-    };
+    let mut pool = ThreadPool::new(render_setting.threads, scene);
 
     println!(
         "Start rendering..
     Size {}x{}
     Number of threads: {}
     Max Ray Depth: {}
-    Ray Per Pixel {}",
+    Ray Per Pixel {}
+    Spectral: {}
+    Filter: {:?}
+    Sampler: {:?} (seed {})",
         render_setting.screen_width,
         render_setting.screen_height,
-        NUM_THREADS,
+        render_setting.threads,
         MAX_RAY_DEPTH,
-        RAYS_PER_PIXEL
+        RAYS_PER_PIXEL,
+        render_setting.spectral,
+        render_setting.filter,
+        render_setting.sampler,
+        render_setting.seed
     );
 
-    let mut index = 0;
-    for y in 0..super::SCREEN_HEIGHT {
-        for x in 0..super::SCREEN_WIDTH {
-            pool.schedule((x, y, index), &render_pixel_job);
-            index = index + BYTES_PIXEL;
+    if let Some(crop) = render_setting.crop {
+        println!(
+            "    Crop {}x{} at ({}, {})",
+            crop.width, crop.height, crop.x, crop.y
+        );
+    }
+
+    let settings = Arc::new(render_setting.clone());
+    for job in resumed..jobs {
+        let settings = Arc::clone(&settings);
+        let names = Arc::clone(&names);
+        let coordinate = (x0 + job % region_width, y0 + job / region_width);
+        pool.schedule(coordinate, move |scene, coordinate| {
+            render_pixel_job(scene, &names, &settings, coordinate)
+        });
+    }
+
+    /*
+        Samples are splatted into neighbouring pixels, so tiles are summed into one film. Float
+        sums depend on the order, tiles are merged in scan order (not the order the threads finish
+        them) to get the same image for every run. It also lets a checkpoint store the merged
+        tiles as a single count.
+    */
+    let mut finished: Vec<Option<Film>> = (0..jobs).map(|_| None).collect();
+    let mut saved = Instant::now();
+    #[cfg(feature = "draw-debugger")]
+    let mut published = Instant::now();
+    for _ in resumed..jobs {
+        let ((x, y), tile) = pool.results.recv().unwrap();
+        finished[(y - y0) * region_width + (x - x0)] = Some(tile);
+        while let Some(tile) = finished.get_mut(next).and_then(Option::take) {
+            film.merge(&tile);
+            next += 1;
+        }
+
+        if let Some(path) = &render_setting.checkpoint {
+            if saved.elapsed().as_secs_f32() > checkpoint::INTERVAL {
+                // A failed checkpoint should not stop the render itself.
+                if let Err(e) = checkpoint::save(path, fingerprint, next, &film) {
+                    println!("Could not save checkpoint {}: {}", path.display(), e);
+                }
+                saved = Instant::now();
+            }
+        }
+
+        #[cfg(feature = "draw-debugger")]
+        {
+            if published.elapsed().as_secs_f32() > preview::INTERVAL {
+                preview::publish_film(&film, &render_setting.color);
+                published = Instant::now();
+            }
         }
     }
 
     pool.wait_all();
 
-    // Combine results into image.
-    for worker in pool.workers.iter_mut() {
-        let lock = worker.results.lock().unwrap();
-        //let ref_list = worker.results.into_inner().unwrap();
-        for (index, (r, g, b)) in lock.deref().clone() {
-            image[index] = r;
-            image[index + 1] = g;
-            image[index + 2] = b;
+    // The render is done, a checkpoint left behind would be resumed by the next render.
+    if let Some(path) = &render_setting.checkpoint {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Could not remove {}: {}", path.display(), e))?;
         }
     }
 
+    let film = match render_setting.crop {
+        Some(crop) => film.crop(crop.x, crop.y, crop.width, crop.height),
+        None => film,
+    };
+    let (width, height) = film.size();
+    let pixels: Vec<Vec3> = match &render_setting.denoise {
+        Some(denoiser) => {
+            println!("Denoising..");
+            denoiser.apply(&film)
+        }
+        None => (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| film.pixel(x, y))
+            .collect(),
+    };
+
+    let color = &render_setting.color;
+    let mut image = Vec::with_capacity(width * height * color.bit_depth.bytes_pixel());
+    for pixel in pixels.iter() {
+        color.encode(*pixel, &mut image);
+    }
+    #[cfg(feature = "draw-debugger")]
+    preview::publish(width, height, color.bit_depth, &image);
+
+    let mut layers = vec![];
+    let mut manifests = vec![];
+    if render_setting.aovs.is_some() {
+        manifests = film.manifests();
+        layers = film.layers();
+        // The image layer is the denoised image, the passes are not denoised.
+        layers[0].values = pixels
+            .iter()
+            .flat_map(|p| vec![p.0 as f32, p.1 as f32, p.2 as f32])
+            .collect();
+    }
+
     println!(
         "Finished rendering: {} Seconds",
         now.elapsed().as_secs_f32()
     );
 
-    Ok(image)
+    Ok(View {
+        suffix: "",
+        width,
+        height,
+        image,
+        layers,
+        manifests,
+    })
 }
 
-fn render_pixel_job(scene: &Scene, coordinate: (usize, usize, usize)) -> (u8, u8, u8) {
-    let mut rng = rand::thread_rng();
+/*
+    Scene and settings that change the samples of the pixels, a checkpoint is only resumed with
+    the same.
+*/
+fn fingerprint(settings: &RenderSettings) -> u64 {
+    let settings = format!(
+        "{} {}x{} {:?} {} {:?} {:?} {} {} {}",
+        settings.scene,
+        settings.screen_width,
+        settings.screen_height,
+        settings.crop,
+        settings.spectral,
+        settings.filter,
+        settings.sampler,
+        settings.seed,
+        RAYS_PER_PIXEL,
+        MAX_RAY_DEPTH
+    );
+    settings
+        .bytes()
+        .fold(0, |h, b| sampler::hash_combine(h, b as u64))
+}
 
-    let mut pixel_color = Vec3::zero();
-    for _n_rp in 0..RAYS_PER_PIXEL {
-        let rand_coord: (f64, f64) = rng.gen();
+// Rows of the left and right image next to each other, row is the length of a row of one image.
+fn side_by_side<T: Copy>(left: &[T], right: &[T], row: usize) -> Vec<T> {
+    left.chunks(row)
+        .zip(right.chunks(row))
+        .flat_map(|(left, right)| left.iter().chain(right.iter()).copied())
+        .collect()
+}
 
-        let mut r = scene.camera.generate_ray(
-            coordinate.0 as f64 + rand_coord.0,
-            coordinate.1 as f64 + rand_coord.1,
-        );
-        pixel_color += raytrace(&scene, &mut r, 0);
+// Renders the scene, or each eye of the scene camera for stereo settings.
+pub fn render_views(scene: Scene, render_setting: &RenderSettings) -> Result<Vec<View>, String> {
+    let stereo = match &render_setting.stereo {
+        Some(stereo) => stereo,
+        None => return Ok(vec![render_scene(scene, render_setting)?]),
+    };
+
+    let mut eyes = vec![];
+    for &(eye, suffix) in [(Eye::Left, "_left"), (Eye::Right, "_right")].iter() {
+        println!("Eye {}", &suffix[1..]);
+        let mut eye_scene = scene.clone();
+        eye_scene.camera =
+            scene
+                .camera
+                .clone()
+                .with_eye(eye, stereo.interocular, stereo.convergence);
+        let eye_setting = RenderSettings {
+            checkpoint: render_setting
+                .checkpoint
+                .as_ref()
+                .map(|path| checkpoint::with_suffix(path, suffix)),
+            ..render_setting.clone()
+        };
+        eyes.push(View {
+            suffix,
+            ..render_scene(eye_scene, &eye_setting)?
+        });
     }
 
-    to_color(pixel_color, RAYS_PER_PIXEL)
+    match stereo.layout {
+        StereoLayout::Separate => Ok(eyes),
+        StereoLayout::SideBySide => {
+            let width = eyes[0].width;
+            let row = width * render_setting.color.bit_depth.bytes_pixel();
+            let layers = eyes[0]
+                .layers
+                .iter()
+                .zip(eyes[1].layers.iter())
+                .map(|(left, right)| Layer {
+                    values: side_by_side(
+                        &left.values,
+                        &right.values,
+                        width * left.kind.channels().len(),
+                    ),
+                    ..left.clone()
+                })
+                .collect();
+            let mut manifests = eyes[0].manifests.clone();
+            for (manifest, right) in manifests.iter_mut().zip(eyes[1].manifests.iter()) {
+                manifest.merge(right);
+            }
+            Ok(vec![View {
+                suffix: "",
+                width: width * 2,
+                height: eyes[0].height,
+                image: side_by_side(&eyes[0].image, &eyes[1].image, row),
+                layers,
+                manifests,
+            }])
+        }
+    }
+}
+
+/*
+    Renders every frame of the range to a numbered png (frame_0001.png) in the directory.
+    The scene is set to the state of each frame, the BVH is rebuilt by render_scene.
+*/
+pub fn render_animation(
+    scene: Scene,
+    render_setting: &RenderSettings,
+    frames: RangeInclusive<usize>,
+    directory: &Path,
+) -> Result<usize, String> {
+    std::fs::create_dir_all(directory)
+        .map_err(|e| format!("Could not create {}: {}", directory.display(), e))?;
+
+    let mut written = 0;
+    for frame in frames {
+        println!("Frame {}", frame);
+
+        let mut frame_scene = scene.clone();
+        if let Some(animation) = &scene.animation {
+            animation.apply(&mut frame_scene, frame as f64);
+        }
+
+        // Every frame has its own noise, otherwise the noise stays in place while the scene moves.
+        let frame_setting = RenderSettings {
+            seed: sampler::hash_combine(render_setting.seed, frame as u64),
+            checkpoint: render_setting
+                .checkpoint
+                .as_ref()
+                .map(|path| checkpoint::with_suffix(path, &format!("_{:04}", frame))),
+            ..render_setting.clone()
+        };
+        for view in render_views(frame_scene, &frame_setting)? {
+            let path = directory.join(format!("frame_{:04}{}.png", frame, view.suffix));
+            let depth = render_setting.color.bit_depth;
+            output::write_png(&path, view.width, view.height, depth, &view.image)
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+
+            if let Some(format) = render_setting.aovs {
+                let path = directory.join(format!("frame_{:04}{}", frame, view.suffix));
+                let path = path.to_string_lossy();
+                aov::write_layers(
+                    &path,
+                    format,
+                    view.width,
+                    view.height,
+                    &view.layers,
+                    &view.manifests,
+                    &render_setting.color,
+                )
+                .map_err(|e| format!("Could not write the passes of {}: {}", path, e))?;
+            }
+        }
+        written += 1;
+    }
+
+    Ok(written)
 }
 
-fn raytrace(scene: &Scene, ray: &mut Ray, depth: u16) -> Vec3 {
+// Traces the samples of the pixel, returns the tile of pixels they are splatted into.
+fn render_pixel_job(
+    scene: &Scene,
+    names: &Names,
+    settings: &RenderSettings,
+    coordinate: (usize, usize),
+) -> Film {
+    let mut sampler = sampler::new(settings.sampler, RAYS_PER_PIXEL as u32, settings.seed);
+
+    let filter = &settings.filter;
+    let image_size = (settings.screen_width, settings.screen_height);
+    let mut tile = Film::around(coordinate.0, coordinate.1, filter, image_size);
+    for n_rp in 0..RAYS_PER_PIXEL {
+        sampler.start_sample(coordinate, n_rp as u32);
+        let rand_coord = sampler.get_2d();
+        let x = coordinate.0 as f64 + rand_coord.0;
+        let y = coordinate.1 as f64 + rand_coord.1;
+
+        // Samples blocked by the lens are black.
+        let mut r = match scene.camera.generate_ray(x, y, sampler.as_mut()) {
+            Some(ray) => ray,
+            None => {
+                tile.add_sample(x, y, Vec3::zero(), filter);
+                continue;
+            }
+        };
+
+        let mut features = Features::miss();
+        let mut aov = settings.aovs.map(|_| AovSample::new(scene.lights.len()));
+        if settings.spectral {
+            let wavelengths = Wavelengths::sample(sampler.get_1d());
+            r.wavelengths = Some(wavelengths);
+            let radiance = raytrace(
+                scene,
+                names,
+                &mut r,
+                0,
+                sampler.as_mut(),
+                Some(&mut features),
+                aov.as_mut(),
+            );
+            let to_rgb = |radiance: Vec3| spectrum::to_rgb(radiance, &wavelengths);
+            tile.add_sample(x, y, to_rgb(radiance), filter);
+            if let Some(aov) = &mut aov {
+                aov.direct = to_rgb(aov.direct);
+                aov.indirect = to_rgb(aov.indirect);
+                for light in aov.lights.iter_mut() {
+                    *light = to_rgb(*light);
+                }
+            }
+        } else {
+            let color = raytrace(
+                scene,
+                names,
+                &mut r,
+                0,
+                sampler.as_mut(),
+                Some(&mut features),
+                aov.as_mut(),
+            );
+            tile.add_sample(x, y, color, filter);
+        }
+        tile.add_features(coordinate.0, coordinate.1, &features);
+        if let Some(aov) = &aov {
+            tile.add_aovs(x, y, aov, filter);
+        }
+    }
+
+    tile
+}
+
+/*
+    Features are filled in at the first surface that is not specular, or when the path leaves
+    the scene. The passes are filled in by the camera ray.
+*/
+fn raytrace(
+    scene: &Scene,
+    names: &Names,
+    ray: &mut Ray,
+    depth: u16,
+    sampler: &mut dyn Sampler,
+    features: Option<&mut Features>,
+    mut aov: Option<&mut AovSample>,
+) -> Vec3 {
     if depth >= MAX_RAY_DEPTH {
         return Vec3(0.0, 0.0, 0.0);
     }
 
-    // TODO: BVH for intersecting?
-    for it in scene.objects.iter() {
-        //it.intersect(ray, 0.001);
-        shape::intersect(it, ray, 0.001);
-    }
+    scene.intersect(ray, 0.001);
 
     if let Some(hit) = &ray.is_intersected {
-        if let Some((attenuation, mut scattered_ray)) = material::scatter(&hit.material, ray) {
-            return attenuation * raytrace(scene, &mut scattered_ray, depth + 1);
+        let features = match features {
+            Some(features) if !material::is_specular(&hit.material) => {
+                *features = Features {
+                    albedo: material::surface_albedo(&hit.material, ray),
+                    normal: hit.normal,
+                    depth: (hit.position - ray.origin).length(),
+                };
+                None
+            }
+            features => features,
+        };
+
+        let per_light = aov.as_deref_mut().map(|aov| &mut aov.lights[..]);
+        let direct = direct_light(scene, ray, per_light);
+        if let Some(aov) = aov.as_deref_mut() {
+            aov.albedo = material::surface_albedo(&hit.material, ray);
+            aov.normal = hit.normal;
+            // The direction of camera rays is not normalized.
+            aov.depth = Some(ray.travel_distance * ray.direction.length());
+            aov.object = hit.object;
+            let material = names.material(&hit.material);
+            aov.material = material.map(|(id, _)| *id);
+            aov.object_name = hit.object.map(|i| Arc::clone(names.object(i)));
+            aov.material_name = material.map(|(_, name)| Arc::clone(name));
+            aov.direct = direct;
         }
 
-        return Vec3(0.0, 0.0, 0.0);
+        if let Some((attenuation, mut scattered_ray)) =
+            material::scatter(&hit.material, ray, sampler)
+        {
+            let indirect = raytrace(
+                scene,
+                names,
+                &mut scattered_ray,
+                depth + 1,
+                sampler,
+                features,
+                None,
+            );
+            let bounce = attenuation * indirect;
+            if let Some(aov) = aov {
+                // Sky that lights the surface is direct light.
+                if scattered_ray.is_intersected.is_none() {
+                    aov.direct += bounce;
+                    let sky = aov.lights.len() - 1;
+                    aov.lights[sky] = bounce;
+                } else {
+                    aov.indirect = bounce;
+                }
+            }
+            return direct + bounce;
+        }
+
+        return direct;
+    }
+
+    if let Some(features) = features {
+        *features = Features::miss();
     }
 
     //sky
     let t = 0.5 * (ray.direction.1 + 1.0);
-    Vec3::fill(1.0) * (1.0 - t) + (Vec3(0.5, 0.7, 1.0) * t)
+    let sky = Vec3::fill(1.0) * (1.0 - t) + (Vec3(0.5, 0.7, 1.0) * t);
+
+    let sky = match &ray.wavelengths {
+        Some(wavelengths) => spectrum::upsample_wavelengths(sky, wavelengths),
+        None => sky,
+    };
+    // Sky seen by the camera.
+    if let Some(aov) = aov {
+        aov.direct = sky;
+        let last = aov.lights.len() - 1;
+        aov.lights[last] = sky;
+    }
+    sky
+}
+
+/*
+    Light of the scene lights reaching the hit of the ray, for diffuse materials. Scattered rays
+    can never hit these lights, so this is the only way they contribute. per_light gets the part
+    of every light.
+*/
+fn direct_light(scene: &Scene, ray: &Ray, mut per_light: Option<&mut [Vec3]>) -> Vec3 {
+    let hit = match &ray.is_intersected {
+        Some(hit) => hit,
+        None => return Vec3::zero(),
+    };
+    let diffuse = match material::diffuse(&hit.material, ray) {
+        Some(diffuse) if !scene.lights.is_empty() => diffuse,
+        _ => return Vec3::zero(),
+    };
+
+    let mut total = Vec3::zero();
+    for (i, light) in scene.lights.iter().enumerate() {
+        let (direction, distance, radiance) = match light.illuminate(hit.position) {
+            Some(light) => light,
+            None => continue,
+        };
+        let cos = Vec3::dot(direction, hit.normal);
+        if cos <= 0.0 {
+            continue;
+        }
+
+        let mut shadow_ray = ray.scattered(hit.position, direction);
+        shadow_ray.travel_distance = distance;
+        scene.intersect(&mut shadow_ray, 0.001);
+        if shadow_ray.is_intersected.is_some() {
+            continue;
+        }
+
+        let radiance = match &ray.wavelengths {
+            Some(wavelengths) => spectrum::upsample_wavelengths(radiance, wavelengths),
+            None => radiance,
+        };
+        let light = diffuse * radiance * cos;
+        if let Some(per_light) = per_light.as_deref_mut() {
+            per_light[i] = light;
+        }
+        total += light;
+    }
+    total
 }
 
-// Returning rgb u8
-fn to_color(vec: Vec3, samples: u16) -> (u8, u8, u8) {
-    let scale = 1.0 / samples as f64;
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::color::ToneMap;
+    use crate::output::BitDepth;
+    use crate::scene;
+    use crate::shape;
 
-    let _r = (scale * vec.0).sqrt();
-    let _g = (scale * vec.1).sqrt();
-    let _b = (scale * vec.2).sqrt();
+    #[test]
+    fn test_deterministic_pixel() {
+        let settings = RenderSettings {
+            screen_width: crate::SCREEN_WIDTH,
+            screen_height: crate::SCREEN_HEIGHT,
+            spectral: false,
+            frames: None,
+            stereo: None,
+            filter: Filter::Tent { radius: 1.0 },
+            sampler: SamplerType::Sobol,
+            seed: 7,
+            threads: 1,
+            color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Eight),
+            denoise: None,
+            aovs: None,
+            crop: None,
+            checkpoint: None,
+            scene: String::new(),
+        };
+        // The random scene is built the same way every time as well.
+        let render = |settings: &RenderSettings| {
+            let mut scene = scene::create_scene();
+            scene.build_bvh();
+            let tile = render_pixel_job(&scene, &Names::new(&scene), settings, (600, 450));
+            (599..=601)
+                .map(|x| tile.pixel(x, 450))
+                .collect::<Vec<Vec3>>()
+        };
 
-    let _r = f64::clamp(_r, 0.0, 0.999999) * 256.0;
-    let _g = f64::clamp(_g, 0.0, 0.999999) * 256.0;
-    let _b = f64::clamp(_b, 0.0, 0.999999) * 256.0;
+        let first = render(&settings);
+        assert_eq!(first, render(&settings));
+        let other_seed = RenderSettings {
+            seed: 8,
+            ..settings.clone()
+        };
+        assert_ne!(first, render(&other_seed));
+    }
 
-    (_r as u8, _g as u8, _b as u8)
+    #[test]
+    fn test_checkpoint_of_another_scene() {
+        let path = std::env::temp_dir().join(format!("scene_{}.ckpt", std::process::id()));
+        let settings = RenderSettings {
+            screen_width: 4,
+            screen_height: 3,
+            spectral: false,
+            frames: None,
+            stereo: None,
+            filter: Filter::Box { radius: 0.5 },
+            sampler: SamplerType::Sobol,
+            seed: 0,
+            threads: 1,
+            color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Eight),
+            denoise: None,
+            aovs: None,
+            crop: None,
+            checkpoint: Some(path.clone()),
+            scene: "spheres".to_string(),
+        };
+        checkpoint::save(&path, fingerprint(&settings), 1, &Film::new(4, 3)).unwrap();
+
+        let csg = RenderSettings {
+            scene: "csg".to_string(),
+            ..settings.clone()
+        };
+        let error = render_scene(scene::by_name("csg").unwrap(), &csg)
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Could not resume"), "{}", error);
+
+        // The scene it was written for resumes it, the finished render removes it.
+        assert!(render_scene(scene::create_scene(), &settings).is_ok());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_threads_give_the_same_image() {
+        let settings = RenderSettings {
+            screen_width: 6,
+            screen_height: 4,
+            spectral: false,
+            frames: None,
+            stereo: None,
+            filter: Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            sampler: SamplerType::Sobol,
+            seed: 3,
+            threads: 1,
+            color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Sixteen),
+            denoise: None,
+            aovs: None,
+            crop: None,
+            checkpoint: None,
+            scene: String::new(),
+        };
+        let single = render_scene(scene::create_scene(), &settings).unwrap();
+        let threaded = RenderSettings {
+            threads: 4,
+            ..settings.clone()
+        };
+        let multiple = render_scene(scene::create_scene(), &threaded).unwrap();
+        assert_eq!(single.image, multiple.image);
+
+        let none = RenderSettings {
+            threads: 0,
+            ..settings
+        };
+        assert!(render_scene(scene::create_scene(), &none).is_err());
+    }
+
+    #[test]
+    fn test_names_of_the_scene() {
+        let materials = |scene: &Scene| {
+            let names = Names::new(scene);
+            let mut found = vec![];
+            for obj in scene.objects.iter() {
+                shape::for_each_material(obj, &mut |material| {
+                    found.push(names.material(material).cloned().unwrap())
+                });
+            }
+            found
+        };
+        let mut scene = scene::create_scene();
+        let first = materials(&scene);
+        assert_eq!(first[0], (1, "ground".into()));
+        // Every material of the scene has its own id, counting from 1.
+        let mut ids: Vec<usize> = first.iter().map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids, (1..=ids.len()).collect::<Vec<usize>>());
+        let unnamed = first.iter().find(|(_, name)| name.starts_with("material_"));
+        let (id, name) = unnamed.unwrap();
+        assert_eq!(name.as_ref(), format!("material_{}", id));
+
+        // Materials created before the scene do not change its names.
+        scene::load(Path::new("other/models/demo_scene.gltf")).unwrap();
+        assert_eq!(first, materials(&scene::create_scene()));
+
+        // Building the bvh leaves the objects as they are.
+        scene.build_bvh();
+        let names = Names::new(&scene);
+        for (i, obj) in scene.objects.iter().enumerate() {
+            match shape::name(obj) {
+                Some(name) => assert_eq!(names.object(i), name),
+                None => assert_eq!(names.object(i).as_ref(), format!("object_{}", i)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_scene_renders() {
+        let settings = RenderSettings {
+            screen_width: crate::SCREEN_WIDTH,
+            screen_height: crate::SCREEN_HEIGHT,
+            spectral: false,
+            frames: None,
+            stereo: None,
+            filter: Filter::Box { radius: 0.5 },
+            sampler: SamplerType::Sobol,
+            seed: 0,
+            threads: 1,
+            color: ColorPipeline::new(ToneMap::Clamp, BitDepth::Eight),
+            denoise: None,
+            aovs: None,
+            crop: None,
+            checkpoint: None,
+            scene: String::new(),
+        };
+        let files = ["other/models/hex_nut.stl", "other/models/demo_scene.gltf"];
+        for name in scene::SCENES.iter().chain(files.iter()) {
+            let mut scene = match scene::by_name(name) {
+                Some(scene) => scene,
+                None => scene::load(Path::new(name)).unwrap(),
+            };
+            if let Some(animation) = scene.animation.clone() {
+                animation.apply(&mut scene, 1.0);
+            }
+            scene.build_bvh();
+            let names = Names::new(&scene);
+            // The center pixel and a corner, with a ray for every pixel sample.
+            for &(x, y) in [(600, 400), (0, 0)].iter() {
+                let color = render_pixel_job(&scene, &names, &settings, (x, y)).pixel(x, y);
+                assert!(
+                    color.0.is_finite() && color.1.is_finite() && color.2.is_finite(),
+                    "Scene {} gives {:?} at {}, {}",
+                    name,
+                    color,
+                    x,
+                    y
+                );
+            }
+        }
+        assert!(scene::by_name("teapot").is_none());
+    }
 }
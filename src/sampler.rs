@@ -0,0 +1,442 @@
+use super::math::vector::Vec3;
+
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/*
+    Random numbers for the samples of a pixel. Every call takes the next dimension of the
+    current sample, samplers that know all samples of the pixel spread them evenly over each
+    dimension (or pair of dimensions for get_2d). Numbers only depend on the seed, pixel, sample
+    index and dimension.
+*/
+pub trait Sampler {
+    // Starts the sample of the pixel, the dimensions start over.
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerType {
+    // Uncorrelated random numbers.
+    Independent,
+    // Jittered strata over the samples, 2D samples use a grid when the sample count is square.
+    Stratified,
+    // Sobol points with hash based Owen scrambling, shuffled for every pair of dimensions.
+    Sobol,
+    // The same Sobol points in every pixel, shifted by a blue noise mask so the error of
+    // neighbouring pixels differs as much as possible.
+    BlueNoise,
+}
+
+// Sampler for the samples of one pixel, samples is the number of samples per pixel.
+pub fn new(sampler_type: SamplerType, samples: u32, seed: u64) -> Box<dyn Sampler> {
+    let state = State {
+        seed,
+        pixel: (0, 0),
+        pixel_seed: 0,
+        index: 0,
+        dimension: 0,
+    };
+    match sampler_type {
+        SamplerType::Independent => Box::new(Independent { state }),
+        SamplerType::Stratified => Box::new(Stratified { state, samples }),
+        SamplerType::Sobol => Box::new(Sobol {
+            state,
+            blue_noise: false,
+        }),
+        SamplerType::BlueNoise => Box::new(Sobol {
+            state,
+            blue_noise: true,
+        }),
+    }
+}
+
+// Uniform direction on the unit sphere.
+pub fn sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let angle = 2.0 * PI * u.1;
+    Vec3(r * angle.cos(), r * angle.sin(), z)
+}
+
+// Uniform point in the unit ball.
+pub fn ball(u: (f64, f64), w: f64) -> Vec3 {
+    sphere(u) * w.cbrt()
+}
+
+// Mixes the bits (splitmix64 finalizer).
+pub fn hash(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub fn hash_combine(seed: u64, value: u64) -> u64 {
+    hash(seed ^ hash(value).wrapping_add(0x9e37_79b9_7f4a_7c15))
+}
+
+// Uniform number in [0, 1) from the top 53 bits.
+fn to_unit(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Same for 32 bit fixed point values.
+fn to_unit_32(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
+
+struct State {
+    seed: u64,
+    pixel: (usize, usize),
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl State {
+    fn start(&mut self, pixel: (usize, usize), index: u32) {
+        self.pixel = pixel;
+        self.pixel_seed = hash_combine(hash_combine(self.seed, pixel.0 as u64), pixel.1 as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    // Seed of the next dimension of the pixel.
+    fn next_dimension(&mut self) -> u64 {
+        self.dimension += 1;
+        hash_combine(self.pixel_seed, self.dimension)
+    }
+}
+
+struct Independent {
+    state: State,
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let index = self.state.index as u64;
+        to_unit(hash_combine(self.state.next_dimension(), index))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/*
+    Random permutation of 0..length for any length, without storing it (Kensler, Correlated
+    Multi-Jittered Sampling).
+*/
+fn permute(index: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    let mut i = index;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    ((i as u64 + seed as u64) % length as u64) as u32
+}
+
+struct Stratified {
+    state: State,
+    samples: u32,
+}
+
+impl Stratified {
+    // Stratum of the sample in a random order per dimension and the jitter within it.
+    fn stratum(&mut self, strata: u32) -> (u32, u64) {
+        let seed = self.state.next_dimension();
+        let index = self.state.index % strata;
+        let stratum = permute(index, strata, seed as u32);
+        (stratum, hash_combine(seed, self.state.index as u64))
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let samples = self.samples.max(1);
+        let (stratum, jitter) = self.stratum(samples);
+        (stratum as f64 + to_unit(jitter)) / samples as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = (self.samples as f64).sqrt() as u32;
+        if n < 2 || n * n != self.samples {
+            return (self.get_1d(), self.get_1d());
+        }
+
+        let (stratum, jitter) = self.stratum(n * n);
+        let (x, y) = (stratum % n, stratum / n);
+        (
+            (x as f64 + to_unit(jitter)) / n as f64,
+            (y as f64 + to_unit(hash(jitter))) / n as f64,
+        )
+    }
+}
+
+// Hash based permutation in which each bit only depends on the lower bits (Laine and Karras).
+fn laine_karras_permutation(x: u32, seed: u32) -> u32 {
+    let mut x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// Owen scrambling of a 32 bit fixed point value, every bit is flipped based on the bits above.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// First two dimensions of the Sobol sequence as 32 bit fixed point values.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut v = 1u32 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/*
+    Pairs of dimensions use the first two Sobol dimensions with a shuffled index (Burley,
+    Practical Hash-based Owen Scrambling), which keeps every pair well stratified.
+*/
+struct Sobol {
+    state: State,
+    blue_noise: bool,
+}
+
+impl Sobol {
+    fn point(&mut self) -> (f64, f64) {
+        // Blue noise uses the same points in every pixel, only the shift differs.
+        let seed = if self.blue_noise {
+            self.state.dimension += 1;
+            hash_combine(self.state.seed, self.state.dimension)
+        } else {
+            self.state.next_dimension()
+        };
+
+        let index = nested_uniform_scramble(self.state.index, seed as u32);
+        let (x, y) = sobol_2d(index);
+        let x = to_unit_32(nested_uniform_scramble(x, (seed >> 32) as u32));
+        let y = to_unit_32(nested_uniform_scramble(y, hash(seed) as u32));
+        if !self.blue_noise {
+            return (x, y);
+        }
+
+        // Toroidal shift by the mask, at a different offset for each dimension.
+        let offset = hash(seed);
+        let shift = |x: f64, offset: u64| {
+            let mask_x = (self.state.pixel.0 + (offset & 0xffff) as usize) % MASK_SIZE;
+            let mask_y = (self.state.pixel.1 + (offset >> 16 & 0xffff) as usize) % MASK_SIZE;
+            (x + blue_noise_mask()[mask_y * MASK_SIZE + mask_x]).fract()
+        };
+        (shift(x, offset), shift(y, offset >> 32))
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.point().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        self.point()
+    }
+}
+
+const MASK_SIZE: usize = 64;
+
+/*
+    Blue noise threshold mask made with void-and-cluster (Ulichney): starting from an even
+    pattern, points are ranked by removing the tightest cluster and adding in the largest void.
+    Simplified by also filling the second half at the largest void. Values are between 0 and 1.
+*/
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let n = MASK_SIZE * MASK_SIZE;
+        let sigma = 1.5;
+        // Gaussian weight of every wrapped offset.
+        let kernel: Vec<f64> = (0..n)
+            .map(|i| {
+                let (dx, dy) = (i % MASK_SIZE, i / MASK_SIZE);
+                let dx = dx.min(MASK_SIZE - dx) as f64;
+                let dy = dy.min(MASK_SIZE - dy) as f64;
+                (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+            })
+            .collect();
+        let update = |energy: &mut Vec<f64>, point: usize, sign: f64| {
+            let (px, py) = (point % MASK_SIZE, point / MASK_SIZE);
+            for (i, e) in energy.iter_mut().enumerate() {
+                let dx = (i % MASK_SIZE + MASK_SIZE - px) % MASK_SIZE;
+                let dy = (i / MASK_SIZE + MASK_SIZE - py) % MASK_SIZE;
+                *e += sign * kernel[dy * MASK_SIZE + dx];
+            }
+        };
+        // Point with the highest (cluster) or lowest (void) energy of the points with the state.
+        let extreme = |energy: &[f64], filled: &[bool], state: bool, highest: bool| {
+            (0..n)
+                .filter(|&i| filled[i] == state)
+                .max_by(|&a, &b| {
+                    let order = energy[a].partial_cmp(&energy[b]).unwrap();
+                    if highest {
+                        order
+                    } else {
+                        order.reverse()
+                    }
+                })
+                .unwrap()
+        };
+
+        // Initial pattern of random points, moved until the tightest cluster is the largest void.
+        let mut filled = vec![false; n];
+        let mut energy = vec![0.0; n];
+        let ones = n / 10;
+        let mut seed = 0;
+        while filled.iter().filter(|&&f| f).count() < ones {
+            seed += 1;
+            let point = hash(seed) as usize % n;
+            if !filled[point] {
+                filled[point] = true;
+                update(&mut energy, point, 1.0);
+            }
+        }
+        for _ in 0..n {
+            let cluster = extreme(&energy, &filled, true, true);
+            filled[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            let void = extreme(&energy, &filled, false, false);
+            filled[void] = true;
+            update(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0; n];
+        let (mut removed, mut removed_energy) = (filled.clone(), energy.clone());
+        for r in (0..ones).rev() {
+            let cluster = extreme(&removed_energy, &removed, true, true);
+            removed[cluster] = false;
+            update(&mut removed_energy, cluster, -1.0);
+            rank[cluster] = r;
+        }
+        for r in ones..n {
+            let void = extreme(&energy, &filled, false, false);
+            filled[void] = true;
+            update(&mut energy, void, 1.0);
+            rank[void] = r;
+        }
+
+        rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+    })
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    // Every stratum of size 1/samples gets exactly one of the samples in the first dimensions.
+    fn assert_stratified(sampler_type: SamplerType, samples: u32) {
+        let mut sampler = new(sampler_type, samples, 7);
+        let mut strata = vec![0; samples as usize];
+        let mut grid = vec![0; samples as usize];
+        for i in 0..samples {
+            sampler.start_sample((3, 5), i);
+            let u = sampler.get_1d();
+            strata[(u * samples as f64) as usize] += 1;
+            let (x, y) = sampler.get_2d();
+            let n = (samples as f64).sqrt() as usize;
+            grid[(y * n as f64) as usize * n + (x * n as f64) as usize] += 1;
+        }
+        assert!(strata.iter().all(|&c| c == 1), "{:?}", sampler_type);
+        assert!(grid.iter().all(|&c| c == 1), "{:?}", sampler_type);
+    }
+
+    #[test]
+    fn test_samplers_stratified() {
+        assert_stratified(SamplerType::Stratified, 16);
+        assert_stratified(SamplerType::Sobol, 16);
+        assert_stratified(SamplerType::Sobol, 64);
+
+        // Samples only depend on the pixel, index and dimension.
+        let mut a = new(SamplerType::Independent, 16, 1);
+        let mut b = new(SamplerType::Independent, 16, 1);
+        a.start_sample((1, 2), 3);
+        b.start_sample((1, 2), 3);
+        assert_eq!(a.get_2d(), b.get_2d());
+        let (x, y) = (a.get_1d(), a.get_1d());
+        assert!(x != y);
+    }
+
+    #[test]
+    fn test_permute() {
+        let mut seen = [false; 37];
+        for i in 0..37 {
+            seen[permute(i, 37, 12345) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_blue_noise_mask() {
+        // Every threshold is used once.
+        let mut mask = blue_noise_mask().to_vec();
+        mask.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = mask.len() as f64;
+        assert!(mask
+            .iter()
+            .enumerate()
+            .all(|(i, &v)| (v - (i as f64 + 0.5) / n).abs() < 1e-12));
+    }
+}
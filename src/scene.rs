@@ -1,17 +1,110 @@
+use super::animation::{Animation, CameraAnimation, Track};
+use super::bvh::{Aabb, Bvh};
+use super::camera::{Aperture, FisheyeMapping, Lens, Projection};
+use super::csg::Operation;
+use super::curve::{CurveShape, Curves, Strand};
+use super::displacement::{self, Displacement, HeightMap, Noise};
+use super::gltf;
+use super::import;
 use super::light::Light;
+use super::light::LightType;
 use super::material::*;
+use super::mesh::Mesh;
+use super::particles::Particles;
+use super::patch::{self, BezierPatch};
+use super::ply::Ply;
+use super::ray::Ray;
+use super::sampler;
+use super::sdf::{Field, Sdf};
 use super::shape;
-use super::shape::{Object, ObjectType};
+use super::shape::{Group, Object, ObjectType};
+use super::subdivision;
 use super::Camera;
 use super::Vec3;
 use crate::material;
-use crate::math::vector::Vector;
+use crate::math::matrix::{Transform, Trs};
+use crate::math::vector::{Vec2, Vector};
+
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Scene {
     pub objects: Vec<Object>,
     pub lights: Vec<Light>,
     pub camera: Camera,
+    // Has to be rebuilt with build_bvh after changing the objects.
+    pub bvh: Bvh,
+    pub animation: Option<Animation>,
+}
+
+impl Scene {
+    pub fn build_bvh(&mut self) {
+        let bounds: Vec<Option<Aabb>> = self.objects.iter().map(shape::bounds).collect();
+        self.bvh = Bvh::build(&bounds);
+    }
+
+    pub fn intersect(&self, ray: &mut Ray, tolerance: f64) {
+        let objects = &self.objects;
+        self.bvh.traverse(ray, tolerance, |i, ray| {
+            let closest = ray.travel_distance;
+            shape::intersect(&objects[i], ray, tolerance);
+            if ray.travel_distance < closest {
+                ray.set_object(i);
+            }
+        });
+    }
+}
+
+/*
+    Names of the objects and materials in the ID mattes and the ids of the materials. Objects
+    without a name are named object_<index>. Materials are numbered from 1 (0 is nothing hit) in
+    the order the objects use them and named material_<id> without a name, so both only depend
+    on the scene.
+*/
+pub struct Names {
+    objects: Vec<Arc<str>>,
+    // Id and name by the address of the material.
+    materials: HashMap<usize, (usize, Arc<str>)>,
+}
+
+impl Names {
+    pub fn new(scene: &Scene) -> Names {
+        let objects = scene
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| match shape::name(obj) {
+                Some(name) => Arc::clone(name),
+                None => format!("object_{}", i).into(),
+            })
+            .collect();
+
+        let mut materials = HashMap::new();
+        for obj in scene.objects.iter() {
+            shape::for_each_material(obj, &mut |material| {
+                let id = materials.len() + 1;
+                materials
+                    .entry(Arc::as_ptr(material) as usize)
+                    .or_insert_with(|| match material::name(material) {
+                        Some(name) => (id, Arc::clone(name)),
+                        None => (id, format!("material_{}", id).into()),
+                    });
+            });
+        }
+        Names { objects, materials }
+    }
+
+    pub fn object(&self, index: usize) -> &Arc<str> {
+        &self.objects[index]
+    }
+
+    // None for a material that is not part of the scene.
+    pub fn material(&self, material: &Arc<Material>) -> Option<&(usize, Arc<str>)> {
+        self.materials.get(&(Arc::as_ptr(material) as usize))
+    }
 }
 
 /*pub fn create_scene() -> Scene {
@@ -19,7 +112,9 @@ pub struct Scene {
     let sphere_material = material::new(Vec3(0.1, 0.2, 0.5), MaterialType::Lambertian);
 
     let metal_material = material::new(Vec3(0.8, 0.6, 0.2), MaterialType::Metal { fuzz: 0.3 });
-    let dielectric_mat = material::new(Vec3::zero(), MaterialType::Dielectric { refract: 1.5 });
+    let dielectric_mat = material::new(Vec3::zero(), MaterialType::Dielectric {
+        refract: Ior::Constant(1.5),
+    });
 
     let from = Vec3(-6.0, 1.5, 0.70);
     let look_at = Vec3(0.0, 0.0, -2.0);
@@ -64,7 +159,142 @@ pub struct Scene {
 }*/
 
 extern crate rand;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Seed of the random placement in the scenes, so every run builds the same scene.
+const SCENE_SEED: u64 = 0;
+
+// Radius of particles loaded from PLY files without radii.
+const PARTICLE_RADIUS: f64 = 0.01;
+
+// The model on a ground plane, seen from the front and above by a camera that fits it in view.
+pub fn create_model_scene(model: ObjectType) -> Scene {
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    // Vertex and particle colors replace the albedo.
+    let model_material = material::new(Vec3(0.8, 0.8, 0.8), MaterialType::Lambertian);
+
+    // Stands the model on the ground, centered on the origin.
+    let bounds = shape::bounds(&shape::new(Vec3::zero(), model.clone(), &model_material))
+        .unwrap_or_else(|| Aabb::new(Vec3::zero(), Vec3::zero()));
+    let center = (bounds.min + bounds.max) / 2.0;
+    let position = Vec3(-center.0, -bounds.min.1, -center.2);
+    let model = shape::with_name(shape::new(position, model, &model_material), "model");
+
+    let size = (bounds.max - bounds.min).length().max(0.001);
+    let look_at = Vec3(0.0, center.1 - bounds.min.1, 0.0);
+    let from = look_at + Vec3::normalize(Vec3(0.0, 0.4, 1.0)) * size * 1.6;
+
+    Scene {
+        objects: vec![
+            shape::with_name(
+                shape::new(
+                    Vec3::zero(),
+                    ObjectType::Plane {
+                        distance: 0.0,
+                        normal: Vec3::up(),
+                    },
+                    &ground_material,
+                ),
+                "ground",
+            ),
+            model,
+        ],
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, size * 1.6),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Names of the built-in scenes of by_name, the first one is the default.
+pub const SCENES: [&str; 20] = [
+    "spheres",
+    "dispersion",
+    "instanced",
+    "motion-blur",
+    "turntable",
+    "primitives",
+    "csg",
+    "sdf",
+    "subdivision",
+    "displacement",
+    "hair",
+    "particles",
+    "import",
+    "gltf",
+    "bokeh",
+    "tilt-shift",
+    "panorama",
+    "cube-map",
+    "fisheye",
+    "fisheye-equidistant",
+];
+
+pub fn by_name(name: &str) -> Option<Scene> {
+    let scene = match name {
+        "spheres" => create_scene(),
+        "dispersion" => create_dispersion_scene(),
+        "instanced" => create_instanced_scene(),
+        "motion-blur" => create_motion_blur_scene(),
+        "turntable" => create_turntable_scene(),
+        "primitives" => create_primitives_scene(),
+        "csg" => create_csg_scene(),
+        "sdf" => create_sdf_scene(),
+        "subdivision" => create_subdivision_scene(),
+        "displacement" => create_displacement_scene(),
+        "hair" => create_hair_scene(),
+        "particles" => create_particles_scene(),
+        "import" => create_import_scene(),
+        "gltf" => create_gltf_scene(),
+        "bokeh" => create_bokeh_scene(),
+        "tilt-shift" => create_tilt_shift_scene(),
+        "panorama" => create_projection_scene(Projection::Equirectangular),
+        "cube-map" => create_projection_scene(Projection::CubeMap),
+        "fisheye" => create_projection_scene(Projection::Fisheye {
+            fov: 180.0,
+            mapping: FisheyeMapping::Equisolid,
+        }),
+        "fisheye-equidistant" => create_projection_scene(Projection::Fisheye {
+            fov: 180.0,
+            mapping: FisheyeMapping::Equidistant,
+        }),
+        _ => return None,
+    };
+    Some(scene)
+}
+
+/*
+    Scene from a file, the format is taken from the extension: a glTF scene (.gltf, .glb), a
+    mesh (.ply, .stl) or particles (.bin, or a .ply without faces) on a ground plane.
+*/
+pub fn load(path: &Path) -> Result<Scene, Error> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let model = match extension.as_deref() {
+        Some("gltf") | Some("glb") => {
+            let (scene, warnings) = gltf::load_scene(path)?;
+            for warning in warnings {
+                println!("{}", warning);
+            }
+            return Ok(scene);
+        }
+        Some("ply") if Ply::load(path)?.element("face").is_none() => ObjectType::Particles {
+            particles: Arc::new(Particles::load_ply(path, PARTICLE_RADIUS)?),
+        },
+        Some("bin") => ObjectType::Particles {
+            particles: Arc::new(Particles::load_binary(path, false)?),
+        },
+        _ => ObjectType::Mesh {
+            mesh: Arc::new(import::load_mesh(path)?),
+        },
+    };
+    Ok(create_model_scene(model))
+}
 
 pub fn create_scene() -> Scene {
     let from = Vec3(13.0, 2.0, 3.0);
@@ -74,42 +304,73 @@ pub fn create_scene() -> Scene {
 
     // Materials
 
-    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
-    let material1 = material::new(Vec3::zero(), MaterialType::Dielectric { refract: 1.5 });
-    let material2 = material::new(Vec3(0.4, 0.2, 0.1), MaterialType::Lambertian);
-    let material3 = material::new(Vec3(0.7, 0.6, 0.5), MaterialType::Metal { fuzz: 0.0 });
+    let ground_material = material::with_name(
+        material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian),
+        "ground",
+    );
+    let material1 = material::with_name(
+        material::new(
+            Vec3::zero(),
+            MaterialType::Dielectric {
+                refract: Ior::Constant(1.5),
+            },
+        ),
+        "glass",
+    );
+    let material2 = material::with_name(
+        material::new(Vec3(0.4, 0.2, 0.1), MaterialType::Lambertian),
+        "brown",
+    );
+    let material3 = material::with_name(
+        material::new(Vec3(0.7, 0.6, 0.5), MaterialType::Metal { fuzz: 0.0 }),
+        "mirror",
+    );
 
     let mut scene = Scene {
         objects: vec![
-            shape::new(
-                Vec3(0.0, 0.0, 0.0),
-                ObjectType::Plane {
-                    distance: (0.0),
-                    normal: (Vec3::up()),
-                },
-                &ground_material,
+            shape::with_name(
+                shape::new(
+                    Vec3(0.0, 0.0, 0.0),
+                    ObjectType::Plane {
+                        distance: (0.0),
+                        normal: (Vec3::up()),
+                    },
+                    &ground_material,
+                ),
+                "ground",
             ),
-            shape::new(
-                Vec3(0.0, 1.0, 0.0),
-                ObjectType::Sphere { radius: (1.0) },
-                &material1,
+            shape::with_name(
+                shape::new(
+                    Vec3(0.0, 1.0, 0.0),
+                    ObjectType::Sphere { radius: (1.0) },
+                    &material1,
+                ),
+                "glass_sphere",
             ),
-            shape::new(
-                Vec3(-4.0, 1.0, 0.0),
-                ObjectType::Sphere { radius: (1.0) },
-                &material2,
+            shape::with_name(
+                shape::new(
+                    Vec3(-4.0, 1.0, 0.0),
+                    ObjectType::Sphere { radius: (1.0) },
+                    &material2,
+                ),
+                "diffuse_sphere",
             ),
-            shape::new(
-                Vec3(4.0, 1.0, 0.0),
-                ObjectType::Sphere { radius: (1.0) },
-                &material3,
+            shape::with_name(
+                shape::new(
+                    Vec3(4.0, 1.0, 0.0),
+                    ObjectType::Sphere { radius: (1.0) },
+                    &material3,
+                ),
+                "metal_sphere",
             ),
         ],
         lights: vec![],
         camera: Camera::set(from, look_at, Vec3::up(), 20.0, ratio, 0.1, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
     };
 
-    let mut rngs = rand::thread_rng();
+    let mut rngs = StdRng::seed_from_u64(SCENE_SEED);
     for a in -11..11 {
         for b in -11..11 {
             let rand_mat: f64 = rngs.gen();
@@ -153,8 +414,12 @@ pub fn create_scene() -> Scene {
                         &mat2,
                     ));
                 } else {
-                    let mat3 =
-                        material::new(Vec3::zero(), MaterialType::Dielectric { refract: 1.5 });
+                    let mat3 = material::new(
+                        Vec3::zero(),
+                        MaterialType::Dielectric {
+                            refract: Ior::Constant(1.5),
+                        },
+                    );
 
                     scene.objects.push(shape::new(
                         center,
@@ -168,3 +433,1054 @@ pub fn create_scene() -> Scene {
 
     scene
 }
+
+/*
+    Flint glass spheres in front of rows of small white spheres, the edges of the white spheres
+    seen through the glass get rainbow fringes. Dispersion needs the spectral renderer
+    (--spectral), with RGB the glass has the index of the reference wavelength.
+*/
+pub fn create_dispersion_scene() -> Scene {
+    let from = Vec3(0.0, 1.5, 7.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.05, 0.05, 0.05), MaterialType::Lambertian);
+    let white_material = material::new(Vec3(0.9, 0.9, 0.9), MaterialType::Lambertian);
+    let sf11_material = material::new(
+        Vec3::zero(),
+        MaterialType::Dielectric {
+            refract: Ior::sf11(),
+        },
+    );
+    // Dense flint as well, with the simpler Cauchy fit.
+    let cauchy_material = material::new(
+        Vec3::zero(),
+        MaterialType::Dielectric {
+            refract: Ior::Cauchy {
+                a: 1.728,
+                b: 0.013_42,
+            },
+        },
+    );
+
+    let mut objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-1.1, 1.0, 0.0),
+            ObjectType::Sphere { radius: 1.0 },
+            &sf11_material,
+        ),
+        shape::new(
+            Vec3(1.1, 1.0, 0.0),
+            ObjectType::Sphere { radius: 1.0 },
+            &cauchy_material,
+        ),
+    ];
+    for row in 0..4 {
+        for i in 0..16 {
+            let x = (i as f64 - 7.5) * 0.5;
+            objects.push(shape::new(
+                Vec3(x, 0.15 + row as f64 * 0.6, -3.0),
+                ObjectType::Sphere { radius: 0.15 },
+                &white_material,
+            ));
+        }
+    }
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Forest of instanced trees, every tree shares the same geometry.
+pub fn create_instanced_scene() -> Scene {
+    let from = Vec3(0.0, 12.0, 60.0);
+    let look_at = Vec3(0.0, 0.0, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.4, 0.35, 0.3), MaterialType::Lambertian);
+    let trunk_material = material::new(Vec3(0.35, 0.2, 0.1), MaterialType::Lambertian);
+    let leaf_material = material::new(Vec3(0.1, 0.45, 0.1), MaterialType::Lambertian);
+    let autumn_material = material::new(Vec3(0.7, 0.3, 0.05), MaterialType::Lambertian);
+
+    // Tree build up out of spheres, in its own space with the root at the origin.
+    let mut tree = vec![];
+    for i in 0..4 {
+        tree.push(shape::new(
+            Vec3(0.0, 0.25 + i as f64 * 0.5, 0.0),
+            ObjectType::Sphere { radius: 0.25 },
+            &trunk_material,
+        ));
+    }
+    for &(x, y, z, r) in [
+        (0.0, 2.6, 0.0, 1.0),
+        (0.6, 2.2, 0.3, 0.7),
+        (-0.5, 2.3, -0.4, 0.7),
+        (0.0, 3.4, 0.0, 0.6),
+    ]
+    .iter()
+    {
+        tree.push(shape::new(
+            Vec3(x, y, z),
+            ObjectType::Sphere { radius: r },
+            &leaf_material,
+        ));
+    }
+    let tree = Arc::new(Group::new(tree));
+
+    let mut objects = vec![shape::new(
+        Vec3::zero(),
+        ObjectType::Plane {
+            distance: 0.0,
+            normal: Vec3::up(),
+        },
+        &ground_material,
+    )];
+
+    let mut rngs = StdRng::seed_from_u64(SCENE_SEED);
+    for a in -50..50i32 {
+        for b in -100..0 {
+            let offset: (f64, f64) = rngs.gen();
+            let rotation: f64 = rngs.gen_range(0.0, 360.0);
+            let scale: f64 = rngs.gen_range(0.6, 1.4);
+
+            let transform = Transform::trs(
+                Vec3(a as f64 + offset.0, 0.0, b as f64 + offset.1) * 3.0,
+                Vec3(0.0, rotation, 0.0),
+                Vec3::fill(scale),
+            );
+            // Every seventh tree has turned, trunk and leaves in one autumn color.
+            objects.push(if (a + b).rem_euclid(7) == 0 {
+                shape::instance_with_material(&tree, transform, &autumn_material)
+            } else {
+                shape::instance(&tree, transform)
+            });
+        }
+    }
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// The random spheres scene seen from between the large spheres with another projection.
+pub fn create_projection_scene(projection: Projection) -> Scene {
+    let from = Vec3(2.0, 1.2, 2.5);
+    let look_at = Vec3(0.0, 1.0, 0.0);
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let mut scene = create_scene();
+    scene.camera =
+        Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, 1.0).with_projection(projection);
+    scene
+}
+
+// Falling and spinning spheres, rendered with the shutter open for half a frame.
+pub fn create_motion_blur_scene() -> Scene {
+    let from = Vec3(0.0, 1.5, 8.0);
+    let look_at = Vec3(0.0, 1.0, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let red = material::new(Vec3(0.7, 0.1, 0.1), MaterialType::Lambertian);
+    let metal = material::new(Vec3(0.8, 0.8, 0.8), MaterialType::Metal { fuzz: 0.0 });
+
+    let ball = Arc::new(Group::new(vec![
+        shape::new(Vec3::zero(), ObjectType::Sphere { radius: 0.7 }, &metal),
+        shape::new(
+            Vec3(0.7, 0.0, 0.0),
+            ObjectType::Sphere { radius: 0.2 },
+            &red,
+        ),
+    ]));
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::with_motion(
+            shape::new(
+                Vec3(-2.0, 1.0, 0.0),
+                ObjectType::Sphere { radius: 0.7 },
+                &red,
+            ),
+            shape::Motion::Linear {
+                velocity: Vec3(0.0, -0.6, 0.0),
+            },
+        ),
+        shape::with_motion(
+            shape::instance(&ball, Transform::identity()),
+            shape::Motion::Transform {
+                start: Trs {
+                    translation: Vec3(1.5, 1.0, 0.0),
+                    rotation: Vec3::zero(),
+                    scale: Vec3::fill(1.0),
+                },
+                end: Trs {
+                    translation: Vec3(1.5, 1.0, 0.0),
+                    rotation: Vec3(0.0, 0.0, 60.0),
+                    scale: Vec3::fill(1.0),
+                },
+            },
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 30.0, ratio, 0.0, look_dist)
+            .with_shutter(0.0, 1.0),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Turntable of the motion blur scene: the camera orbits once in 48 frames.
+pub fn create_turntable_scene() -> Scene {
+    let mut scene = create_motion_blur_scene();
+
+    let frames = 48;
+    let steps = 16;
+    let look_at = Vec3(0.0, 1.0, 0.0);
+    let orbit: Vec<(f64, Vec3)> = (0..=steps)
+        .map(|i| {
+            let angle = 360.0 * i as f64 / steps as f64;
+            let frame = (frames * i) as f64 / steps as f64;
+            (frame, look_at + Vec3(0.0, 0.5, 7.0).rotate_y(angle))
+        })
+        .collect();
+
+    scene.animation = Some(Animation {
+        camera: Some(CameraAnimation {
+            position: Track::new(orbit),
+            look_at: Track::constant(look_at),
+            fov: Track::new(vec![(0.0, 30.0), (frames as f64, 40.0)]),
+            focus_distance: Track::constant(7.0),
+            up: Vec3::up(),
+            aperture: 0.0,
+            ratio: super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64,
+        }),
+        // The ball on the right jumps up halfway.
+        objects: vec![(
+            2,
+            Track::new(vec![
+                (0.0, Trs::identity()),
+                (
+                    frames as f64 / 2.0,
+                    Trs {
+                        translation: Vec3(0.0, 1.5, 0.0),
+                        ..Trs::identity()
+                    },
+                ),
+                (frames as f64, Trs::identity()),
+            ]),
+        )],
+    });
+
+    scene
+}
+
+// One of each analytic primitive.
+pub fn create_primitives_scene() -> Scene {
+    let from = Vec3(0.0, 4.0, 12.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let red = material::new(Vec3(0.7, 0.15, 0.1), MaterialType::Lambertian);
+    let green = material::new(Vec3(0.1, 0.6, 0.2), MaterialType::Lambertian);
+    let blue = material::new(Vec3(0.1, 0.2, 0.7), MaterialType::Lambertian);
+    let gold = material::new(Vec3(0.8, 0.6, 0.2), MaterialType::Metal { fuzz: 0.1 });
+    let glass = material::new(
+        Vec3::zero(),
+        MaterialType::Dielectric {
+            refract: Ior::bk7(),
+        },
+    );
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-4.0, 0.5, 0.0),
+            ObjectType::Cuboid {
+                half_size: Vec3(0.5, 0.5, 0.5),
+            },
+            &red,
+        ),
+        // Oriented box
+        shape::transformed(
+            shape::new(
+                Vec3::zero(),
+                ObjectType::Cuboid {
+                    half_size: Vec3(0.4, 0.8, 0.4),
+                },
+                &glass,
+            ),
+            Transform::trs(Vec3(-2.5, 0.8, 1.5), Vec3(0.0, 45.0, 0.0), Vec3::fill(1.0)),
+        ),
+        shape::new(
+            Vec3(-2.0, 0.0, 0.0),
+            ObjectType::Cylinder {
+                radius: 0.5,
+                height: 1.5,
+            },
+            &green,
+        ),
+        shape::new(
+            Vec3(0.0, 0.0, 0.0),
+            ObjectType::Cone {
+                radius: 0.7,
+                height: 1.8,
+            },
+            &blue,
+        ),
+        shape::new(
+            Vec3(2.0, 0.3, 0.0),
+            ObjectType::Torus {
+                major_radius: 0.7,
+                minor_radius: 0.3,
+            },
+            &gold,
+        ),
+        shape::new(
+            Vec3(4.0, 0.8, 0.0),
+            ObjectType::Disc {
+                normal: Vec3::normalize(Vec3(-0.3, 0.2, 1.0)),
+                radius: 0.8,
+            },
+            &red,
+        ),
+        shape::new(
+            Vec3(-5.0, 0.0, -2.0),
+            ObjectType::Quad {
+                u: Vec3(10.0, 0.0, 0.0),
+                v: Vec3(0.0, 3.0, 0.0),
+            },
+            &gold,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Sphere with a box carved out, rounded cube (intersection) and a union carved by a cylinder.
+pub fn create_csg_scene() -> Scene {
+    let from = Vec3(0.0, 3.5, 9.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let red = material::new(Vec3(0.7, 0.15, 0.1), MaterialType::Lambertian);
+    let blue = material::new(Vec3(0.1, 0.2, 0.7), MaterialType::Lambertian);
+    let gold = material::new(Vec3(0.8, 0.6, 0.2), MaterialType::Metal { fuzz: 0.1 });
+
+    let sphere = |position: Vec3, material: &Arc<Material>| {
+        shape::new(position, ObjectType::Sphere { radius: 1.0 }, material)
+    };
+    let cube = |position: Vec3, half: f64, material: &Arc<Material>| {
+        shape::new(
+            position,
+            ObjectType::Cuboid {
+                half_size: Vec3::fill(half),
+            },
+            material,
+        )
+    };
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::csg(
+            Operation::Difference,
+            sphere(Vec3(-2.5, 1.0, 0.0), &red),
+            cube(Vec3(-2.0, 1.5, 0.7), 0.6, &blue),
+        ),
+        shape::csg(
+            Operation::Intersection,
+            cube(Vec3(0.0, 1.0, 0.0), 0.8, &gold),
+            sphere(Vec3(0.0, 1.0, 0.0), &gold),
+        ),
+        shape::csg(
+            Operation::Difference,
+            shape::csg(
+                Operation::Union,
+                sphere(Vec3(2.3, 1.0, 0.0), &blue),
+                sphere(Vec3(3.0, 1.2, 0.3), &blue),
+            ),
+            shape::new(
+                Vec3(2.6, 0.0, 0.5),
+                ObjectType::Cylinder {
+                    radius: 0.4,
+                    height: 3.0,
+                },
+                &red,
+            ),
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Signed distance fields: a smooth blob, a twisted column of rounded boxes, a Mandelbulb and a
+// die with carved pips.
+pub fn create_sdf_scene() -> Scene {
+    let from = Vec3(0.0, 3.0, 9.0);
+    let look_at = Vec3(0.0, 1.0, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let red = material::new(Vec3(0.7, 0.15, 0.1), MaterialType::Lambertian);
+    let gold = material::new(Vec3(0.8, 0.6, 0.2), MaterialType::Metal { fuzz: 0.1 });
+    let blue = material::new(Vec3(0.1, 0.2, 0.7), MaterialType::Lambertian);
+    let ivory = material::new(Vec3(0.9, 0.88, 0.8), MaterialType::Lambertian);
+
+    let blob = Sdf::SmoothSubtract {
+        a: Box::new(Sdf::SmoothUnion {
+            a: Box::new(Sdf::Sphere { radius: 0.8 }),
+            b: Box::new(Sdf::Torus {
+                major_radius: 0.9,
+                minor_radius: 0.25,
+            }),
+            k: 0.3,
+        }),
+        b: Box::new(Sdf::Translate {
+            offset: Vec3(0.0, 0.8, 0.0),
+            node: Box::new(Sdf::Sphere { radius: 0.4 }),
+        }),
+        k: 0.1,
+    };
+
+    let column = Sdf::Twist {
+        amount: 0.8,
+        node: Box::new(Sdf::Repeat {
+            period: Vec3(0.0, 0.45, 0.0),
+            count: Vec3(0.0, 2.0, 0.0),
+            node: Box::new(Sdf::Round {
+                radius: 0.05,
+                node: Box::new(Sdf::Box {
+                    half_size: Vec3(0.5, 0.12, 0.5),
+                }),
+            }),
+        }),
+    };
+
+    let bulb = Sdf::Mandelbulb {
+        power: 8.0,
+        iterations: 8,
+    };
+
+    // Die with pips carved into the top and the front.
+    let pip = |offset: Vec3| Sdf::Translate {
+        offset,
+        node: Box::new(Sdf::Sphere { radius: 0.08 }),
+    };
+    let die = Sdf::Subtract(
+        Box::new(Sdf::Round {
+            radius: 0.05,
+            node: Box::new(Sdf::Box {
+                half_size: Vec3::fill(0.3),
+            }),
+        }),
+        Box::new(Sdf::Union(
+            Box::new(pip(Vec3(0.0, 0.37, 0.0))),
+            Box::new(Sdf::Union(
+                Box::new(pip(Vec3(-0.15, 0.15, 0.37))),
+                Box::new(pip(Vec3(0.15, -0.15, 0.37))),
+            )),
+        )),
+    );
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-2.6, 1.0, 0.0),
+            ObjectType::Sdf {
+                field: Arc::new(Field::new(blob)),
+            },
+            &red,
+        ),
+        shape::new(
+            Vec3(0.0, 1.1, 0.0),
+            ObjectType::Sdf {
+                field: Arc::new(Field::new(column)),
+            },
+            &blue,
+        ),
+        shape::new(
+            Vec3(2.6, 1.2, 0.0),
+            ObjectType::Sdf {
+                field: Arc::new(Field::new(bulb)),
+            },
+            &gold,
+        ),
+        shape::new(
+            Vec3(1.3, 0.35, 2.0),
+            ObjectType::Sdf {
+                field: Arc::new(Field::new(die)),
+            },
+            &ivory,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// A box cage subdivided 1, 2 and 4 times next to a Bezier patch hill.
+pub fn create_subdivision_scene() -> Scene {
+    let from = Vec3(0.0, 4.0, 9.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let red = material::new(Vec3(0.7, 0.15, 0.1), MaterialType::Lambertian);
+    let gold = material::new(Vec3(0.8, 0.6, 0.2), MaterialType::Metal { fuzz: 0.1 });
+
+    let mut objects = vec![shape::new(
+        Vec3::zero(),
+        ObjectType::Plane {
+            distance: 0.0,
+            normal: Vec3::up(),
+        },
+        &ground_material,
+    )];
+
+    let cage = subdivision::cube(Vec3(0.8, 0.8, 0.8));
+    for (i, &levels) in [1, 2, 4].iter().enumerate() {
+        objects.push(shape::new(
+            Vec3(-3.0 + i as f64 * 2.0, 0.8, 0.0),
+            ObjectType::Mesh {
+                mesh: Arc::new(cage.to_mesh(levels)),
+            },
+            &red,
+        ));
+    }
+
+    // Flat grid of control points with the inner four raised into a hill.
+    let mut control = [[Vec3::zero(); 4]; 4];
+    for (row, points) in control.iter_mut().enumerate() {
+        for (column, point) in points.iter_mut().enumerate() {
+            let inner = (1..=2).contains(&row) && (1..=2).contains(&column);
+            let height = if inner { 2.0 } else { 0.0 };
+            *point = Vec3(column as f64 * 0.6, height, -(row as f64) * 0.6);
+        }
+    }
+    objects.push(shape::new(
+        Vec3(2.2, 0.01, 1.0),
+        ObjectType::Mesh {
+            mesh: Arc::new(patch::tessellate(&[BezierPatch { control }], 16)),
+        },
+        &gold,
+    ));
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Terrain and a rock made by displacing a flat square and a subdivided box with noise, and a
+// brick wall displaced by a height map.
+pub fn create_displacement_scene() -> Scene {
+    let from = Vec3(0.0, 3.0, 9.0);
+    let look_at = Vec3(0.0, 0.5, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.4, 0.5, 0.3), MaterialType::Lambertian);
+    let rock_material = material::new(Vec3(0.5, 0.45, 0.4), MaterialType::Lambertian);
+    let wall_material = material::new(Vec3(0.6, 0.3, 0.2), MaterialType::Lambertian);
+
+    let size = 20.0;
+    let square = Mesh::smooth(
+        vec![
+            Vec3(-size, 0.0, -size),
+            Vec3(size, 0.0, -size),
+            Vec3(size, 0.0, size),
+            Vec3(-size, 0.0, size),
+        ],
+        vec![
+            Vec2(0.0, 0.0),
+            Vec2(1.0, 0.0),
+            Vec2(1.0, 1.0),
+            Vec2(0.0, 1.0),
+        ],
+        vec![[0, 2, 1], [0, 3, 2]],
+    );
+    let terrain = displacement::displace(
+        &square,
+        &Displacement::Noise {
+            noise: Noise::new(1),
+            frequency: 0.25,
+            octaves: 5,
+            amplitude: 1.5,
+        },
+        8,
+    );
+
+    let rock = displacement::displace(
+        &subdivision::cube(Vec3::fill(0.8)).to_mesh(2),
+        &Displacement::Noise {
+            noise: Noise::new(2),
+            frequency: 1.5,
+            octaves: 4,
+            amplitude: 0.3,
+        },
+        3,
+    );
+
+    // Brick wall from a height map, two bricks over one unit of the uvs.
+    let bricks = HeightMap::load_png(Path::new("other/textures/bricks.png"))
+        .expect("Could not load other/textures/bricks.png");
+    let wall = displacement::displace(
+        &Mesh::smooth(
+            vec![
+                Vec3(-2.0, 0.0, 0.0),
+                Vec3(2.0, 0.0, 0.0),
+                Vec3(2.0, 2.0, 0.0),
+                Vec3(-2.0, 2.0, 0.0),
+            ],
+            vec![
+                Vec2(0.0, 0.0),
+                Vec2(2.0, 0.0),
+                Vec2(2.0, 1.0),
+                Vec2(0.0, 1.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        ),
+        &Displacement::Texture {
+            map: bricks,
+            amplitude: 0.08,
+        },
+        7,
+    );
+
+    let objects = vec![
+        shape::new(
+            Vec3(0.0, -0.5, 0.0),
+            ObjectType::Mesh {
+                mesh: Arc::new(terrain),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-1.5, 0.0, -2.0),
+            ObjectType::Mesh {
+                mesh: Arc::new(wall),
+            },
+            &wall_material,
+        ),
+        shape::new(
+            Vec3(0.0, 1.2, 2.0),
+            ObjectType::Mesh {
+                mesh: Arc::new(rock),
+            },
+            &rock_material,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Furry ball with hair strands and a patch of grass made of ribbons.
+pub fn create_hair_scene() -> Scene {
+    let from = Vec3(0.0, 2.0, 6.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+    let mut rng = StdRng::seed_from_u64(SCENE_SEED);
+
+    let ground_material = material::new(Vec3(0.4, 0.35, 0.3), MaterialType::Lambertian);
+    let skin = material::new(Vec3(0.3, 0.2, 0.15), MaterialType::Lambertian);
+    let fur = material::new(
+        Vec3(0.8, 0.5, 0.25),
+        MaterialType::Hair {
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        },
+    );
+    let grass_material = material::new(Vec3(0.2, 0.5, 0.1), MaterialType::Lambertian);
+
+    // Strands grow out along the normal and bend down a bit under gravity.
+    let radius = 0.7;
+    let strands: Vec<Strand> = (0..8000)
+        .map(|_| {
+            let normal = sampler::sphere(rng.gen());
+            let length = 0.25 + 0.1 * rng.gen::<f64>();
+            let root = normal * radius;
+            let middle = root + normal * (length * 0.5) - Vec3::up() * (length * 0.1);
+            let tip = root + normal * length - Vec3::up() * (length * 0.35);
+            Strand {
+                points: vec![root, middle, tip],
+                root_width: 0.008,
+                tip_width: 0.002,
+            }
+        })
+        .collect();
+
+    let blades: Vec<Strand> = (0..3000)
+        .map(|_| {
+            let root = Vec3(rng.gen_range(-3.0, 3.0), 0.0, rng.gen_range(-2.0, 1.5));
+            let lean = Vec3(rng.gen_range(-0.15, 0.15), 0.0, rng.gen_range(-0.15, 0.15));
+            let height = 0.2 + 0.25 * rng.gen::<f64>();
+            Strand {
+                points: vec![
+                    root,
+                    root + Vec3::up() * (height * 0.5) + lean * 0.3,
+                    root + Vec3::up() * height + lean,
+                ],
+                root_width: 0.03,
+                tip_width: 0.005,
+            }
+        })
+        .collect();
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(Vec3(0.0, 0.9, 0.0), ObjectType::Sphere { radius }, &skin),
+        shape::new(
+            Vec3(0.0, 0.9, 0.0),
+            ObjectType::Curves {
+                curves: Arc::new(Curves::new(&strands, CurveShape::Cylinder)),
+            },
+            &fur,
+        ),
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Curves {
+                curves: Arc::new(Curves::new(&blades, CurveShape::Ribbon)),
+            },
+            &grass_material,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Spiral of 200k colored particles, all sharing one material and a single BVH.
+pub fn create_particles_scene() -> Scene {
+    let from = Vec3(0.0, 4.0, 8.0);
+    let look_at = Vec3(0.0, 0.8, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+    let mut rng = StdRng::seed_from_u64(SCENE_SEED);
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    let particle_material = material::new(Vec3(1.0, 1.0, 1.0), MaterialType::Lambertian);
+
+    let count = 200_000;
+    let mut positions = Vec::with_capacity(count);
+    let mut colors = Vec::with_capacity(count);
+    for _ in 0..count {
+        let arm = if rng.gen::<bool>() {
+            0.0
+        } else {
+            std::f64::consts::PI
+        };
+        let distance: f64 = rng.gen::<f64>().powf(0.7) * 3.0;
+        let angle = arm + distance * 1.6 + rng.gen_range(-0.35, 0.35);
+        let scatter = sampler::ball(rng.gen(), rng.gen()) * (0.15 + 0.1 * (3.0 - distance));
+        positions.push(Vec3(angle.cos() * distance, 1.0, angle.sin() * distance) + scatter);
+
+        // Warm in the center and blue towards the edge.
+        let t = distance / 3.0;
+        colors.push(Vec3(1.0, 0.8, 0.5) * (1.0 - t) + Vec3(0.3, 0.5, 1.0) * t);
+    }
+    let particles = Particles::new(positions, vec![0.012; count], colors);
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Particles {
+                particles: Arc::new(particles),
+            },
+            &particle_material,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Meshes loaded from files, a PLY sphere with vertex colors and an STL nut.
+pub fn create_import_scene() -> Scene {
+    let from = Vec3(0.0, 2.5, 6.0);
+    let look_at = Vec3(0.0, 0.6, 0.0);
+    let look_dist = (from - look_at).length();
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.5, 0.5, 0.5), MaterialType::Lambertian);
+    // The vertex colors replace the white albedo.
+    let painted_material = material::new(Vec3(1.0, 1.0, 1.0), MaterialType::Lambertian);
+    let steel_material = material::new(Vec3(0.8, 0.8, 0.85), MaterialType::Metal { fuzz: 0.2 });
+
+    let sphere = import::load_mesh(Path::new("other/models/color_sphere.ply"))
+        .expect("Could not load other/models/color_sphere.ply");
+    let nut = import::load_mesh(Path::new("other/models/hex_nut.stl"))
+        .expect("Could not load other/models/hex_nut.stl");
+
+    let objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-1.1, 1.0, 0.0),
+            ObjectType::Mesh {
+                mesh: Arc::new(sphere),
+            },
+            &painted_material,
+        ),
+        shape::new(
+            Vec3(1.3, 0.0, 0.3),
+            ObjectType::Mesh {
+                mesh: Arc::new(nut),
+            },
+            &steel_material,
+        ),
+    ];
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera: Camera::set(from, look_at, Vec3::up(), 40.0, ratio, 0.0, look_dist),
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+// Whole scene exported from a DCC tool: meshes, materials, a texture, the camera and lights.
+pub fn create_gltf_scene() -> Scene {
+    let (scene, warnings) = gltf::load_scene(Path::new("other/models/demo_scene.gltf"))
+        .expect("Could not load other/models/demo_scene.gltf");
+    for warning in warnings {
+        println!("{}", warning);
+    }
+    scene
+}
+
+// Rows of small bright spheres far behind the focus, showing hexagonal cat's eye bokeh.
+pub fn create_bokeh_scene() -> Scene {
+    let from = Vec3(0.0, 0.8, 8.0);
+    let look_at = Vec3(-0.5, 0.4, 0.0);
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.3, 0.3, 0.3), MaterialType::Lambertian);
+    let subject_material = material::new(Vec3(0.7, 0.2, 0.1), MaterialType::Lambertian);
+    let bulb_material = material::new(Vec3(1.0, 0.9, 0.7), MaterialType::Lambertian);
+
+    let mut objects = vec![
+        shape::new(
+            Vec3::zero(),
+            ObjectType::Plane {
+                distance: 0.0,
+                normal: Vec3::up(),
+            },
+            &ground_material,
+        ),
+        shape::new(
+            Vec3(-0.5, 0.4, 0.0),
+            ObjectType::Sphere { radius: 0.4 },
+            &subject_material,
+        ),
+    ];
+    for row in 0..3 {
+        for i in 0..15 {
+            objects.push(shape::new(
+                Vec3(
+                    -7.0 + i as f64,
+                    1.0 + row as f64 * 0.8,
+                    -12.0 - row as f64 * 2.0,
+                ),
+                ObjectType::Sphere { radius: 0.1 },
+                &bulb_material,
+            ));
+        }
+    }
+
+    // 85mm at f/1.4, focused on the red sphere.
+    let lens = Lens::full_frame(85.0, 1.4);
+    let focus = (from - look_at).length() - 0.4;
+    let camera = Camera::physical(from, look_at, Vec3::up(), &lens, ratio, focus).with_aperture(
+        Aperture::Polygon {
+            blades: 6,
+            rotation: 15.0,
+        },
+        0.6,
+    );
+
+    Scene {
+        objects,
+        lights: vec![Light {
+            position: Vec3(0.0, 4.0, -6.0),
+            color: Vec3::fill(1.0),
+            intensity: 3000.0,
+            light_type: LightType::Point,
+        }],
+        camera,
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
+
+/*
+    Grid of blocks of a few centimeters seen from above with the plane in focus tilted up, only
+    a thin band stays sharp which looks like a miniature. The scene is table top sized, at city
+    scale the aperture is too small to blur anything.
+*/
+pub fn create_tilt_shift_scene() -> Scene {
+    let scale = 0.02;
+    let from = Vec3(0.0, 6.0, 8.0) * scale;
+    let look_at = Vec3(0.0, 0.0, 0.0);
+    let ratio = super::SCREEN_WIDTH as f64 / super::SCREEN_HEIGHT as f64;
+
+    let ground_material = material::new(Vec3(0.35, 0.5, 0.3), MaterialType::Lambertian);
+    let colors = [
+        Vec3(0.8, 0.3, 0.2),
+        Vec3(0.9, 0.8, 0.5),
+        Vec3(0.3, 0.4, 0.7),
+        Vec3(0.9, 0.9, 0.9),
+    ];
+    let materials: Vec<_> = colors
+        .iter()
+        .map(|&c| material::new(c, MaterialType::Lambertian))
+        .collect();
+
+    let mut objects = vec![shape::new(
+        Vec3::zero(),
+        ObjectType::Plane {
+            distance: 0.0,
+            normal: Vec3::up(),
+        },
+        &ground_material,
+    )];
+    for x in -6..=6i32 {
+        for z in -8..=3 {
+            let height = 0.2 + ((x * 7 + z * 13).rem_euclid(5)) as f64 * 0.1;
+            objects.push(shape::new(
+                Vec3(x as f64, height, z as f64 * 1.2) * scale,
+                ObjectType::Cuboid {
+                    half_size: Vec3(0.3, height, 0.35) * scale,
+                },
+                &materials[(x + z).rem_euclid(4) as usize],
+            ));
+        }
+    }
+
+    let lens = Lens::full_frame(35.0, 2.0);
+    let focus = (from - look_at).length();
+    let camera = Camera::physical(from, look_at, Vec3::up(), &lens, ratio, focus)
+        .with_tilt_shift(Vec2(30.0, 0.0), Vec2(0.0, 0.1));
+
+    Scene {
+        objects,
+        lights: vec![],
+        camera,
+        bvh: Bvh::default(),
+        animation: None,
+    }
+}
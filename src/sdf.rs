@@ -0,0 +1,412 @@
+use super::bvh::Aabb;
+use super::math::vector::{Vec2, Vec3, Vector};
+use super::primitive::Hit;
+
+use std::f64::consts::PI;
+
+const MAX_STEPS: usize = 512;
+// Distance to the surface at which the sphere tracing stops.
+const HIT_EPSILON: f64 = 1e-5;
+// Distance to move past a crossing before looking for the next one.
+const CROSSING_STEP: f64 = 1e-3;
+
+/*
+    Signed distance function as a tree of nodes. Leaves are shapes around the origin,
+    the other nodes combine or deform their children.
+*/
+#[derive(Clone)]
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    Box {
+        half_size: Vec3,
+    },
+    // Around the y axis.
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    // Power 8 gives the classic Mandelbulb, bounded by the unit sphere (scaled by 1.2).
+    Mandelbulb {
+        power: f64,
+        iterations: usize,
+    },
+    Translate {
+        offset: Vec3,
+        node: Box<Sdf>,
+    },
+    // Rounds the edges by growing the shape with the radius.
+    Round {
+        radius: f64,
+        node: Box<Sdf>,
+    },
+    Union(Box<Sdf>, Box<Sdf>),
+    // a with b carved out.
+    Subtract(Box<Sdf>, Box<Sdf>),
+    SmoothUnion {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        k: f64,
+    },
+    // a with b carved out, with a smooth transition of size k.
+    SmoothSubtract {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        k: f64,
+    },
+    // Repeats the node with the period, count times in both directions per axis.
+    Repeat {
+        period: Vec3,
+        count: Vec3,
+        node: Box<Sdf>,
+    },
+    // Rotates around the y axis, amount is in radians per unit of height.
+    Twist {
+        amount: f64,
+        node: Box<Sdf>,
+    },
+}
+
+fn max_component(v: Vec3) -> f64 {
+    v.0.max(v.1).max(v.2)
+}
+
+fn vmax(a: Vec3, b: f64) -> Vec3 {
+    Vec3(a.0.max(b), a.1.max(b), a.2.max(b))
+}
+
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a * (1.0 - t) + b * t
+}
+
+impl Sdf {
+    // Distance from the point to the surface, negative inside.
+    pub fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Box { half_size } => {
+                let q = Vec3(p.0.abs(), p.1.abs(), p.2.abs()) - *half_size;
+                vmax(q, 0.0).length() + max_component(q).min(0.0)
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.0 * p.0 + p.2 * p.2).sqrt() - major_radius;
+                (ring * ring + p.1 * p.1).sqrt() - minor_radius
+            }
+            Sdf::Mandelbulb { power, iterations } => {
+                let p = p / 1.2;
+                let mut z = p;
+                let mut dr = 1.0;
+                let mut r = 0.0;
+                for _ in 0..*iterations {
+                    r = z.length();
+                    if r > 2.0 {
+                        break;
+                    }
+                    let theta = (z.2 / r).acos() * power;
+                    let phi = z.1.atan2(z.0) * power;
+                    dr = r.powf(power - 1.0) * power * dr + 1.0;
+                    let zr = r.powf(*power);
+                    z = Vec3(
+                        theta.sin() * phi.cos(),
+                        phi.sin() * theta.sin(),
+                        theta.cos(),
+                    ) * zr
+                        + p;
+                }
+                0.5 * r.ln() * r / dr * 1.2
+            }
+            Sdf::Translate { offset, node } => node.distance(p - *offset),
+            Sdf::Round { radius, node } => node.distance(p) - radius,
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Subtract(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion { a, b, k } => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                let h = f64::clamp(0.5 + 0.5 * (d2 - d1) / k, 0.0, 1.0);
+                mix(d2, d1, h) - k * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtract { a, b, k } => {
+                let (d1, d2) = (b.distance(p), a.distance(p));
+                let h = f64::clamp(0.5 - 0.5 * (d2 + d1) / k, 0.0, 1.0);
+                mix(d2, -d1, h) + k * h * (1.0 - h)
+            }
+            Sdf::Repeat {
+                period,
+                count,
+                node,
+            } => {
+                let cell = |p: f64, period: f64, count: f64| {
+                    if period <= 0.0 {
+                        return p;
+                    }
+                    p - period * f64::clamp((p / period).round(), -count, count)
+                };
+                node.distance(Vec3(
+                    cell(p.0, period.0, count.0),
+                    cell(p.1, period.1, count.1),
+                    cell(p.2, period.2, count.2),
+                ))
+            }
+            Sdf::Twist { amount, node } => {
+                let (sin, cos) = (amount * p.1).sin_cos();
+                node.distance(Vec3(cos * p.0 - sin * p.2, p.1, sin * p.0 + cos * p.2))
+            }
+        }
+    }
+
+    // Surface normal from the gradient (tetrahedron technique).
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let h = 1e-5;
+        let k = [
+            Vec3(1.0, -1.0, -1.0),
+            Vec3(-1.0, -1.0, 1.0),
+            Vec3(-1.0, 1.0, -1.0),
+            Vec3(1.0, 1.0, 1.0),
+        ];
+        k.iter()
+            .fold(Vec3::zero(), |n, &k| n + k * self.distance(p + k * h))
+            .normalize()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            Sdf::Sphere { radius } => Aabb::new(Vec3::fill(-radius), Vec3::fill(*radius)),
+            Sdf::Box { half_size } => Aabb::new(-*half_size, *half_size),
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let outer = major_radius + minor_radius;
+                Aabb::new(
+                    Vec3(-outer, -minor_radius, -outer),
+                    Vec3(outer, *minor_radius, outer),
+                )
+            }
+            Sdf::Mandelbulb { .. } => Aabb::new(Vec3::fill(-1.2), Vec3::fill(1.2)),
+            Sdf::Translate { offset, node } => {
+                let b = node.bounds();
+                Aabb::new(b.min + *offset, b.max + *offset)
+            }
+            Sdf::Round { radius, node } => {
+                let b = node.bounds();
+                Aabb::new(b.min - Vec3::fill(*radius), b.max + Vec3::fill(*radius))
+            }
+            Sdf::Union(a, b) => Aabb::union(&a.bounds(), &b.bounds()),
+            Sdf::SmoothUnion { a, b, k } => {
+                let b = Aabb::union(&a.bounds(), &b.bounds());
+                Aabb::new(b.min - Vec3::fill(*k), b.max + Vec3::fill(*k))
+            }
+            Sdf::Subtract(a, _) | Sdf::SmoothSubtract { a, .. } => a.bounds(),
+            Sdf::Repeat {
+                period,
+                count,
+                node,
+            } => {
+                let b = node.bounds();
+                let spread = *period * *count;
+                Aabb::new(b.min - spread, b.max + spread)
+            }
+            Sdf::Twist { node, .. } => {
+                // Any rotation around y stays within the circle around the box.
+                let b = node.bounds();
+                let r = Vec3(
+                    b.min.0.abs().max(b.max.0.abs()),
+                    0.0,
+                    b.min.2.abs().max(b.max.2.abs()),
+                )
+                .length();
+                Aabb::new(Vec3(-r, b.min.1, -r), Vec3(r, b.max.1, r))
+            }
+        }
+    }
+
+    /*
+        How much faster than 1 the distance can change, the step of the sphere tracing is divided
+        by this. Twisting stretches space, so the distance under estimates less than it should.
+    */
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            Sdf::Translate { node, .. } | Sdf::Round { node, .. } | Sdf::Repeat { node, .. } => {
+                node.lipschitz()
+            }
+            Sdf::Union(a, b)
+            | Sdf::Subtract(a, b)
+            | Sdf::SmoothUnion { a, b, .. }
+            | Sdf::SmoothSubtract { a, b, .. } => a.lipschitz().max(b.lipschitz()),
+            Sdf::Twist { amount, node } => {
+                let b = self.bounds();
+                let r = b.max.0.max(b.max.2);
+                node.lipschitz() * (1.0 + (amount * r) * (amount * r)).sqrt()
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/*
+    Distance function ready to be traced, with the bounds and step size worked out once.
+    The field is around the origin, like the analytic primitives.
+*/
+pub struct Field {
+    root: Sdf,
+    bounds: Aabb,
+    lipschitz: f64,
+}
+
+impl Field {
+    pub fn new(root: Sdf) -> Field {
+        // Padded so the surface is never exactly on the boundary of the box.
+        let b = root.bounds();
+        let padding = Vec3::fill(HIT_EPSILON * 10.0);
+        Field {
+            bounds: Aabb::new(b.min - padding, b.max + padding),
+            lipschitz: root.lipschitz(),
+            root,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /*
+        Sphere tracing along the normalized direction from s to s_end, returns the distance
+        along the direction of the first crossing. The side of the surface the ray starts on
+        is kept, so rays leaving the inside (refraction) find the way out.
+    */
+    fn march(&self, o: Vec3, dir: Vec3, mut s: f64, s_end: f64) -> Option<f64> {
+        let side = self.root.distance(o + dir * s).signum();
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.root.distance(o + dir * s);
+            if distance < HIT_EPSILON {
+                return Some(s);
+            }
+            s += distance / self.lipschitz;
+            if s > s_end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn hit(&self, p: Vec3, t: f64) -> Hit {
+        let normal = self.root.normal(p);
+        let dir = (p - self.bounds.centroid()).normalize();
+        Hit {
+            t,
+            normal,
+            uv: Vec2(
+                ((-dir.2).atan2(dir.0) + PI) / (2.0 * PI),
+                (-dir.1).acos() / PI,
+            ),
+        }
+    }
+
+    // First crossing of the surface between t_min and t_max, d does not have to be normalized.
+    pub fn trace(&self, o: Vec3, d: Vec3, t_min: f64, t_max: f64) -> Option<Hit> {
+        let length = d.length();
+        let inv = Vec3(1.0 / d.0, 1.0 / d.1, 1.0 / d.2);
+        let (t0, t1) = self.bounds.clip(o, inv, t_min, t_max)?;
+        let dir = d / length;
+        let s = self.march(o, dir, t0 * length, t1 * length)?;
+        Some(self.hit(o + dir * s, s / length))
+    }
+
+    // All crossings along the line of the ray sorted on t, the same crossing can be found twice.
+    pub fn crossings(&self, o: Vec3, d: Vec3) -> Vec<Hit> {
+        let length = d.length();
+        let inv = Vec3(1.0 / d.0, 1.0 / d.1, 1.0 / d.2);
+        let (t0, t1) = match self.bounds.clip(o, inv, f64::NEG_INFINITY, f64::INFINITY) {
+            Some(range) => range,
+            None => return vec![],
+        };
+        let dir = d / length;
+
+        let mut hits = vec![];
+        let mut s = t0 * length;
+        while let Some(crossing) = self.march(o, dir, s, t1 * length) {
+            hits.push(self.hit(o + dir * crossing, crossing / length));
+            s = crossing + CROSSING_STEP;
+        }
+        hits
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_sdf_distance() {
+        let sphere = Sdf::Sphere { radius: 1.0 };
+        assert_approx_eq!(sphere.distance(Vec3(3.0, 0.0, 0.0)), 2.0, ASSERT_MARGIN);
+        assert_approx_eq!(sphere.distance(Vec3::zero()), -1.0, ASSERT_MARGIN);
+
+        let cube = Sdf::Box {
+            half_size: Vec3::fill(1.0),
+        };
+        assert_approx_eq!(cube.distance(Vec3(0.0, 3.0, 0.0)), 2.0, ASSERT_MARGIN);
+
+        let carved = Sdf::Subtract(
+            Box::new(cube),
+            Box::new(Sdf::Translate {
+                offset: Vec3(0.0, 1.0, 0.0),
+                node: Box::new(sphere),
+            }),
+        );
+        // The top of the box is carved out.
+        assert!(carved.distance(Vec3(0.0, 0.9, 0.0)) > 0.0);
+        assert!(carved.distance(Vec3(0.0, -0.9, 0.0)) < 0.0);
+        assert!(carved.distance(Vec3(0.0, 3.0, 0.0)) > 0.0);
+
+        let pair = Sdf::Union(
+            Box::new(Sdf::Sphere { radius: 1.0 }),
+            Box::new(Sdf::Translate {
+                offset: Vec3(3.0, 0.0, 0.0),
+                node: Box::new(Sdf::Sphere { radius: 1.0 }),
+            }),
+        );
+        // Inside either sphere, outside between them.
+        assert_approx_eq!(pair.distance(Vec3::zero()), -1.0, ASSERT_MARGIN);
+        assert_approx_eq!(pair.distance(Vec3(3.0, 0.0, 0.0)), -1.0, ASSERT_MARGIN);
+        assert_approx_eq!(pair.distance(Vec3(1.5, 0.0, 0.0)), 0.5, ASSERT_MARGIN);
+    }
+
+    #[test]
+    fn test_sdf_trace() {
+        let field = Field::new(Sdf::Sphere { radius: 1.0 });
+        let hit = field
+            .trace(
+                Vec3(0.0, 0.0, -5.0),
+                Vec3(0.0, 0.0, 2.0),
+                0.0,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_approx_eq!(hit.t, 2.0, 0.0001);
+        assert_approx_eq!(hit.normal.2, -1.0, 0.0001);
+
+        let crossings = field.crossings(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0));
+        assert_eq!(crossings.len(), 2);
+        assert_approx_eq!(crossings[1].t, 6.0, 0.0001);
+    }
+
+    #[test]
+    fn test_sdf_normal() {
+        let sphere = Sdf::Sphere { radius: 2.0 };
+        let n = sphere.normal(Vec3(0.0, 2.0, 0.0));
+        assert_approx_eq!(n.1, 1.0, 0.0001);
+    }
+}
@@ -1,8 +1,18 @@
+use super::bvh::{Aabb, Bvh};
+use super::csg::{self, Interval, Operation, SurfaceHit};
+use super::curve::Curves;
 use super::material::Material;
+use super::math::matrix::{Mat4, Transform, Trs};
+use super::math::vector::{Vec2, Vector};
+use super::mesh::Mesh;
+use super::particles::Particles;
+use super::primitive;
 use super::ray::Ray;
+use super::sdf::Field;
 use super::Vec3;
 
 use std::f64;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 //
@@ -10,24 +20,391 @@ use std::sync::Arc;
 pub struct Object {
     position: Vec3,
     object_type: ObjectType,
-    material: Arc<Material>, // TODO: refactor
+    // Instances use the materials of the instanced objects, unless they override it.
+    material: Option<Arc<Material>>, // TODO: refactor
+    motion: Option<Box<Motion>>,
+    // Name in the ID mattes, objects of the scene without a name are named by the scene.
+    name: Option<Arc<str>>,
 }
+
+/*
+    Movement of an object while the shutter is open. Motion is defined between time 0 and 1,
+    times outside of that range are clamped.
+*/
+#[derive(Clone)]
+pub enum Motion {
+    // The object is moved by velocity * time.
+    Linear {
+        velocity: Vec3,
+    },
+    // The object is transformed, interpolated between start (time 0) and end (time 1).
+    Transform {
+        start: Trs,
+        end: Trs,
+    },
+    // The object is moved by its own motion first, then transformed like Transform.
+    Parented {
+        start: Trs,
+        end: Trs,
+        motion: Box<Motion>,
+    },
+}
+
+impl Motion {
+    pub fn at(&self, time: f64) -> Transform {
+        let time = f64::clamp(time, 0.0, 1.0);
+        match self {
+            Motion::Linear { velocity } => Transform {
+                matrix: Mat4::translation(*velocity * time),
+                inverse: Mat4::translation(-*velocity * time),
+            },
+            Motion::Transform { start, end } => Trs::lerp(start, end, time).to_transform(),
+            Motion::Parented { start, end, motion } => motion
+                .at(time)
+                .then(&Trs::lerp(start, end, time).to_transform()),
+        }
+    }
+
+    // Upper bound of the speed (distance per unit of time) of points within radius of the origin.
+    fn speed(&self, radius: f64) -> f64 {
+        match self {
+            Motion::Linear { velocity } => velocity.length(),
+            Motion::Transform { start, end } => transform_speed(start, end, radius),
+            // The parent moves the moved points, and scales the movement of the child.
+            Motion::Parented { start, end, motion } => {
+                transform_speed(start, end, motion.reach(radius))
+                    + max_scale(start, end) * motion.speed(radius)
+            }
+        }
+    }
+
+    // Furthest distance from the origin that points within radius get during the motion.
+    fn reach(&self, radius: f64) -> f64 {
+        match self {
+            Motion::Linear { velocity } => radius + velocity.length(),
+            Motion::Transform { start, end } => transform_reach(start, end, radius),
+            Motion::Parented { start, end, motion } => {
+                transform_reach(start, end, motion.reach(radius))
+            }
+        }
+    }
+}
+
+fn max_scale(start: &Trs, end: &Trs) -> f64 {
+    let max = |s: Vec3| s.0.abs().max(s.1.abs()).max(s.2.abs());
+    max(start.scale).max(max(end.scale))
+}
+
+/*
+    The point moves by the translation, the rotation (the angular speed is at most the sum of
+    the speeds around the axes) and the scale, which all change linearly between start and end.
+*/
+fn transform_speed(start: &Trs, end: &Trs, radius: f64) -> f64 {
+    let rotation = end.rotation - start.rotation;
+    let angular = (rotation.0.abs() + rotation.1.abs() + rotation.2.abs()).to_radians();
+    let scale = end.scale - start.scale;
+    let scaling = scale.0.abs().max(scale.1.abs()).max(scale.2.abs());
+    (end.translation - start.translation).length()
+        + (angular * max_scale(start, end) + scaling) * radius
+}
+
+fn transform_reach(start: &Trs, end: &Trs, radius: f64) -> f64 {
+    let translation = start.translation.length().max(end.translation.length());
+    translation + max_scale(start, end) * radius
+}
+
+// Number of moments used to find the bounds that cover the whole motion.
+const MOTION_BOUNDS_STEPS: usize = 16;
 #[derive(Clone)]
 pub enum ObjectType {
-    Sphere { radius: f64 },
-    Plane { distance: f64, normal: Vec3 },
+    Sphere {
+        radius: f64,
+    },
+    Plane {
+        distance: f64,
+        normal: Vec3,
+    },
+    // Axis aligned box around the position, use transformed() for an oriented box.
+    Cuboid {
+        half_size: Vec3,
+    },
+    // Parallelogram with a corner at the position.
+    Quad {
+        u: Vec3,
+        v: Vec3,
+    },
+    Disc {
+        normal: Vec3,
+        radius: f64,
+    },
+    // Capped cylinder and cone standing on the position, along the y axis.
+    Cylinder {
+        radius: f64,
+        height: f64,
+    },
+    Cone {
+        radius: f64,
+        height: f64,
+    },
+    // Torus around the y axis.
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    // Hair or grass strands placed at the position.
+    Curves {
+        curves: Arc<Curves>,
+    },
+    // Spheres sharing the material, placed at the position.
+    Particles {
+        particles: Arc<Particles>,
+    },
+    // Triangle mesh placed at the position.
+    Mesh {
+        mesh: Arc<Mesh>,
+    },
+    // Signed distance field around the position, rendered with sphere tracing.
+    Sdf {
+        field: Arc<Field>,
+    },
+    // Shared geometry placed with its own transform.
+    Instance {
+        geometry: Arc<Group>,
+        transform: Box<Transform>,
+    },
+    // Constructive solid geometry of two closed objects.
+    Csg {
+        operation: Operation,
+        a: Box<Object>,
+        b: Box<Object>,
+    },
 }
 
 pub fn new(position: Vec3, object_type: ObjectType, material: &Arc<Material>) -> Object {
     Object {
         position,
         object_type,
-        material: Arc::clone(&material),
+        material: Some(Arc::clone(material)),
+        motion: None,
+        name: None,
+    }
+}
+
+pub fn with_name(obj: Object, name: &str) -> Object {
+    Object {
+        name: Some(name.into()),
+        ..obj
+    }
+}
+
+pub fn name(obj: &Object) -> Option<&Arc<str>> {
+    obj.name.as_ref()
+}
+
+// Calls f for the materials of the object and the objects inside of it, in a fixed order.
+pub fn for_each_material(obj: &Object, f: &mut dyn FnMut(&Arc<Material>)) {
+    if let Some(material) = &obj.material {
+        f(material);
+    }
+    match &obj.object_type {
+        ObjectType::Instance { geometry, .. } => {
+            for child in geometry.objects.iter() {
+                for_each_material(child, f);
+            }
+        }
+        ObjectType::Csg { a, b, .. } => {
+            for_each_material(a, f);
+            for_each_material(b, f);
+        }
+        _ => {}
+    }
+}
+
+// Replaces the motion of the object.
+pub fn with_motion(obj: Object, motion: Motion) -> Object {
+    Object {
+        motion: Some(Box::new(motion)),
+        ..obj
+    }
+}
+
+// Transforms the object together with its motion, like a parent of the object.
+pub fn with_parent_motion(obj: Object, start: Trs, end: Trs) -> Object {
+    let motion = match obj.motion {
+        None => Motion::Transform { start, end },
+        Some(motion) => Motion::Parented { start, end, motion },
+    };
+    Object {
+        motion: Some(Box::new(motion)),
+        ..obj
+    }
+}
+
+pub fn instance(geometry: &Arc<Group>, transform: Transform) -> Object {
+    Object {
+        position: transform.point(Vec3::zero()),
+        object_type: ObjectType::Instance {
+            geometry: Arc::clone(geometry),
+            transform: Box::new(transform),
+        },
+        material: None,
+        motion: None,
+        name: None,
+    }
+}
+
+// Places a single object with a transform, for example to rotate a box.
+pub fn transformed(obj: Object, transform: Transform) -> Object {
+    instance(&Arc::new(Group::new(vec![obj])), transform)
+}
+
+/*
+    Combination of two closed objects (sphere, plane as half space, box, cylinder, cone, torus,
+    instances of those or other csg objects). Surfaces keep the material of their object.
+*/
+pub fn csg(operation: Operation, a: Object, b: Object) -> Object {
+    Object {
+        position: Vec3::zero(),
+        object_type: ObjectType::Csg {
+            operation,
+            a: Box::new(a),
+            b: Box::new(b),
+        },
+        material: None,
+        motion: None,
+        name: None,
+    }
+}
+
+// Instance which renders all instanced objects with the given material.
+pub fn instance_with_material(
+    geometry: &Arc<Group>,
+    transform: Transform,
+    material: &Arc<Material>,
+) -> Object {
+    Object {
+        material: Some(Arc::clone(material)),
+        ..instance(geometry, transform)
+    }
+}
+
+/*
+    List of objects with its own BVH. Used for the objects of the scene and as the shared
+    geometry of instances, groups can be nested through instances to build a hierarchy.
+*/
+#[derive(Clone, Default)]
+pub struct Group {
+    pub objects: Vec<Object>,
+    bvh: Bvh,
+}
+
+impl Group {
+    pub fn new(objects: Vec<Object>) -> Group {
+        let bounds: Vec<Option<Aabb>> = objects.iter().map(bounds).collect();
+        Group {
+            bvh: Bvh::build(&bounds),
+            objects,
+        }
+    }
+
+    pub fn intersect(&self, ray: &mut Ray, tolerance: f64) {
+        let objects = &self.objects;
+        self.bvh.traverse(ray, tolerance, |i, ray| {
+            intersect(&objects[i], ray, tolerance)
+        });
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+}
+
+/*
+    Bounds in world space covering the whole motion, None for objects without bounds (planes).
+    Moving objects are bounded at moments through the motion, each padded by the distance the
+    object can move in half the time between two moments, as rotations move it off the line
+    between them.
+*/
+pub fn bounds(obj: &Object) -> Option<Aabb> {
+    let b = static_bounds(obj)?;
+    match &obj.motion {
+        None => Some(b),
+        Some(motion) => {
+            let radius = b
+                .corners()
+                .iter()
+                .fold(0.0, |radius: f64, corner| radius.max(corner.length()));
+            let padding = Vec3::fill(motion.speed(radius) * 0.5 / MOTION_BOUNDS_STEPS as f64);
+            Some(
+                (0..=MOTION_BOUNDS_STEPS).fold(Aabb::empty(), |total, step| {
+                    let time = step as f64 / MOTION_BOUNDS_STEPS as f64;
+                    let moment = b.transform(&motion.at(time).matrix);
+                    let padded = Aabb::new(moment.min - padding, moment.max + padding);
+                    Aabb::union(&total, &padded)
+                }),
+            )
+        }
+    }
+}
+
+fn static_bounds(obj: &Object) -> Option<Aabb> {
+    // Bounds of the primitives are around the origin.
+    let placed = |b: Aabb| {
+        Some(Aabb {
+            min: b.min + obj.position,
+            max: b.max + obj.position,
+        })
+    };
+
+    match &obj.object_type {
+        &ObjectType::Sphere { radius } => Some(Aabb::new(
+            obj.position - Vec3::fill(radius),
+            obj.position + Vec3::fill(radius),
+        )),
+        ObjectType::Plane { .. } => None,
+        ObjectType::Cuboid { half_size } => placed(primitive::cuboid_bounds(*half_size)),
+        ObjectType::Quad { u, v } => placed(primitive::quad_bounds(*u, *v)),
+        ObjectType::Disc { normal, radius } => placed(primitive::disc_bounds(*normal, *radius)),
+        ObjectType::Cylinder { radius, height } | ObjectType::Cone { radius, height } => {
+            placed(primitive::cylinder_bounds(*radius, *height))
+        }
+        ObjectType::Torus {
+            major_radius,
+            minor_radius,
+        } => placed(primitive::torus_bounds(*major_radius, *minor_radius)),
+        ObjectType::Curves { curves } => placed(curves.bounds()),
+        ObjectType::Particles { particles } => placed(particles.bounds()),
+        ObjectType::Mesh { mesh } => placed(mesh.bounds()),
+        ObjectType::Sdf { field } => placed(field.bounds()),
+        ObjectType::Instance {
+            geometry,
+            transform,
+        } => geometry.bounds().map(|b| b.transform(&transform.matrix)),
+        // The result of an intersection or difference is always within a.
+        ObjectType::Csg { operation, a, b } => match (operation, bounds(a), bounds(b)) {
+            (Operation::Union, Some(a), Some(b)) => Some(Aabb::union(&a, &b)),
+            (Operation::Union, _, _) => None,
+            (Operation::Intersection, None, b) => b,
+            (_, a, _) => a,
+        },
     }
 }
 
 //
 pub fn intersect(obj: &Object, ray: &mut Ray, tolerance: f64) {
+    match &obj.motion {
+        None => intersect_static(obj, ray, tolerance),
+        Some(motion) => {
+            // Intersect the object where it is at the time of the ray.
+            let transform = motion.at(ray.time);
+            intersect_transformed(ray, &transform, None, |local| {
+                intersect_static(obj, local, tolerance)
+            });
+        }
+    }
+}
+
+fn intersect_static(obj: &Object, ray: &mut Ray, tolerance: f64) {
     match &obj.object_type {
         // Intersect for sphere
         &ObjectType::Sphere { radius } => {
@@ -54,7 +431,7 @@ pub fn intersect(obj: &Object, ray: &mut Ray, tolerance: f64) {
             if _t < ray.travel_distance {
                 let point_intersect = ray.at(_t); //ray.origin + ray.direction * _t;
                 let _normal = (point_intersect - obj.position) / radius;
-                ray.set_intersection(_t, Arc::clone(&obj.material), _normal);
+                ray.set_intersection(_t, material(obj), _normal, sphere_uv(_normal));
             }
         }
         // Intersect for plane
@@ -62,8 +439,441 @@ pub fn intersect(obj: &Object, ray: &mut Ray, tolerance: f64) {
             let t = -(Vec3::dot(ray.origin, normal) + distance) / Vec3::dot(ray.direction, normal);
 
             if t < ray.travel_distance && t >= tolerance {
-                ray.set_intersection(t, Arc::clone(&obj.material), normal);
+                // Planes are infinite, uv is in world units.
+                let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+                let point = ray.at(t);
+                let uv = Vec2(Vec3::dot(point, tangent), Vec3::dot(point, bitangent));
+                ray.set_intersection(t, material(obj), normal, uv);
+            }
+        }
+        ObjectType::Cuboid { half_size } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::cuboid(o, d, *half_size, tolerance, t_max)
+        }),
+        ObjectType::Quad { u, v } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::quad(o, d, *u, *v, tolerance, t_max)
+        }),
+        ObjectType::Disc { normal, radius } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::disc(o, d, *normal, *radius, tolerance, t_max)
+        }),
+        ObjectType::Cylinder { radius, height } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::cylinder(o, d, *radius, *height, tolerance, t_max)
+        }),
+        ObjectType::Cone { radius, height } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::cone(o, d, *radius, *height, tolerance, t_max)
+        }),
+        ObjectType::Torus {
+            major_radius,
+            minor_radius,
+        } => intersect_primitive(obj, ray, |o, d, t_max| {
+            primitive::torus(o, d, *major_radius, *minor_radius, tolerance, t_max)
+        }),
+        // Curves also give the direction of the fiber, for the hair material.
+        ObjectType::Curves { curves } => {
+            let o = ray.origin - obj.position;
+            if let Some((hit, tangent)) =
+                curves.intersect(o, ray.direction, tolerance, ray.travel_distance)
+            {
+                ray.set_intersection(hit.t, material(obj), hit.normal, hit.uv);
+                ray.set_tangent(tangent);
+            }
+        }
+        ObjectType::Particles { particles } => {
+            let o = ray.origin - obj.position;
+            if let Some((hit, color)) =
+                particles.intersect(o, ray.direction, tolerance, ray.travel_distance)
+            {
+                ray.set_intersection(hit.t, material(obj), hit.normal, hit.uv);
+                if let Some(color) = color {
+                    ray.set_color(color);
+                }
+            }
+        }
+        ObjectType::Mesh { mesh } => {
+            let o = ray.origin - obj.position;
+            if let Some((hit, color)) =
+                mesh.intersect(o, ray.direction, tolerance, ray.travel_distance)
+            {
+                ray.set_intersection(hit.t, material(obj), hit.normal, hit.uv);
+                if let Some(color) = color {
+                    ray.set_color(color);
+                }
+            }
+        }
+        ObjectType::Sdf { field } => {
+            intersect_primitive(obj, ray, |o, d, t_max| field.trace(o, d, tolerance, t_max))
+        }
+        // Intersect for instance, the ray is moved into the space of the instanced objects.
+        ObjectType::Instance {
+            geometry,
+            transform,
+        } => {
+            intersect_transformed(ray, transform, obj.material.as_ref(), |local| {
+                geometry.intersect(local, tolerance)
+            });
+        }
+        // Intersect for csg, the first boundary of the combined intervals is the hit.
+        ObjectType::Csg { .. } => {
+            let hit = static_intervals(obj, ray)
+                .into_iter()
+                .flat_map(|interval| vec![interval.enter, interval.exit])
+                .find(|hit| hit.t >= tolerance && hit.t < ray.travel_distance);
+
+            if let Some(SurfaceHit {
+                t,
+                normal,
+                uv,
+                material: Some(material),
+            }) = hit
+            {
+                ray.set_intersection(t, material, normal, uv);
+            }
+        }
+    }
+}
+
+/*
+    All parts of the (infinite) line of the ray that are inside of the object, sorted on t.
+    Objects that are not closed (quad, disc, curves) have no inside and return nothing.
+*/
+pub fn intervals(obj: &Object, ray: &Ray) -> Vec<Interval> {
+    match &obj.motion {
+        None => static_intervals(obj, ray),
+        Some(motion) => {
+            let transform = motion.at(ray.time);
+            transformed_intervals(ray, &transform, None, |local| static_intervals(obj, local))
+        }
+    }
+}
+
+fn static_intervals(obj: &Object, ray: &Ray) -> Vec<Interval> {
+    let o = ray.origin - obj.position;
+    let d = ray.direction;
+    let surface = |hit: primitive::Hit| SurfaceHit {
+        t: hit.t,
+        normal: hit.normal,
+        uv: hit.uv,
+        material: obj.material.clone(),
+    };
+
+    let facing = |hit: primitive::Hit| csg::crossing(hit, d);
+    let crossing_intervals = |crossings: Vec<(primitive::Hit, bool)>| -> Vec<Interval> {
+        csg::pair_crossings(crossings)
+            .into_iter()
+            .map(|(enter, exit)| Interval {
+                enter: surface(enter),
+                exit: surface(exit),
+            })
+            .collect()
+    };
+
+    // Convex objects: the first crossing enters, the next one exits.
+    let convex = |hit: &dyn Fn(f64) -> Option<primitive::Hit>| match hit(f64::NEG_INFINITY) {
+        Some(enter) => {
+            let exit = hit(enter.t + 1e-7 * enter.t.abs().max(1.0)).unwrap_or(enter);
+            vec![Interval {
+                enter: surface(enter),
+                exit: surface(exit),
+            }]
+        }
+        None => vec![],
+    };
+
+    match &obj.object_type {
+        &ObjectType::Sphere { radius } => {
+            let a = Vec3::dot(d, d);
+            let b = 2.0 * Vec3::dot(d, o);
+            let c = Vec3::dot(o, o) - radius * radius;
+            let disc = b * b - 4.0 * a * c;
+            if disc < 0.0 {
+                return vec![];
+            }
+
+            let hit = |t: f64| {
+                let normal = (o + d * t) / radius;
+                surface(primitive::Hit {
+                    t,
+                    normal,
+                    uv: sphere_uv(normal),
+                })
+            };
+            vec![Interval {
+                enter: hit((-b - disc.sqrt()) / (2.0 * a)),
+                exit: hit((-b + disc.sqrt()) / (2.0 * a)),
+            }]
+        }
+        // Everything below the plane is inside.
+        &ObjectType::Plane { distance, normal } => {
+            let side = Vec3::dot(ray.origin, normal) + distance;
+            let denom = Vec3::dot(ray.direction, normal);
+            if denom.abs() < 1e-12 {
+                if side < 0.0 {
+                    return vec![Interval {
+                        enter: SurfaceHit::infinite(f64::NEG_INFINITY),
+                        exit: SurfaceHit::infinite(f64::INFINITY),
+                    }];
+                }
+                return vec![];
+            }
+
+            let t = -side / denom;
+            let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+            let point = ray.at(t);
+            let hit = surface(primitive::Hit {
+                t,
+                normal,
+                uv: Vec2(Vec3::dot(point, tangent), Vec3::dot(point, bitangent)),
+            });
+            if denom < 0.0 {
+                vec![Interval {
+                    enter: hit,
+                    exit: SurfaceHit::infinite(f64::INFINITY),
+                }]
+            } else {
+                vec![Interval {
+                    enter: SurfaceHit::infinite(f64::NEG_INFINITY),
+                    exit: hit,
+                }]
+            }
+        }
+        ObjectType::Cuboid { half_size } => {
+            convex(&|t_min| primitive::cuboid(o, d, *half_size, t_min, f64::INFINITY))
+        }
+        ObjectType::Cylinder { radius, height } => {
+            convex(&|t_min| primitive::cylinder(o, d, *radius, *height, t_min, f64::INFINITY))
+        }
+        ObjectType::Cone { radius, height } => {
+            convex(&|t_min| primitive::cone(o, d, *radius, *height, t_min, f64::INFINITY))
+        }
+        ObjectType::Torus {
+            major_radius,
+            minor_radius,
+        } => {
+            let hits = primitive::torus_hits(o, d, *major_radius, *minor_radius);
+            crossing_intervals(hits.into_iter().filter_map(facing).collect())
+        }
+        // Meshes are expected to be closed, with the triangles facing outwards.
+        ObjectType::Mesh { mesh } => crossing_intervals(mesh.crossings(o, d)),
+        ObjectType::Sdf { field } => crossing_intervals(
+            field
+                .crossings(o, d)
+                .into_iter()
+                .filter_map(facing)
+                .collect(),
+        ),
+        // Particles are closed, but too many to combine and not supported.
+        ObjectType::Quad { .. }
+        | ObjectType::Disc { .. }
+        | ObjectType::Curves { .. }
+        | ObjectType::Particles { .. } => vec![],
+        ObjectType::Instance {
+            geometry,
+            transform,
+        } => transformed_intervals(ray, transform, obj.material.as_ref(), |local| {
+            geometry.objects.iter().fold(vec![], |total, object| {
+                csg::combine(Operation::Union, total, intervals(object, local))
+            })
+        }),
+        ObjectType::Csg { operation, a, b } => {
+            let mut combined = csg::combine(*operation, intervals(a, ray), intervals(b, ray));
+            if let Some(material) = &obj.material {
+                for interval in combined.iter_mut() {
+                    for hit in [&mut interval.enter, &mut interval.exit].iter_mut() {
+                        if hit.material.is_some() {
+                            hit.material = Some(Arc::clone(material));
+                        }
+                    }
+                }
+            }
+            combined
+        }
+    }
+}
+
+fn sphere_uv(normal: Vec3) -> Vec2 {
+    Vec2(
+        ((-normal.2).atan2(normal.0) + PI) / (2.0 * PI),
+        (-normal.1).acos() / PI,
+    )
+}
+
+// Ray in the space of the transform. Direction is not normalized so t is the same in both.
+fn local_ray(ray: &Ray, transform: &Transform) -> Ray {
+    let mut local = ray.scattered(
+        transform.inverse.transform_point(ray.origin),
+        transform.inverse.transform_vector(ray.direction),
+    );
+    local.travel_distance = ray.travel_distance;
+    local
+}
+
+fn transformed_intervals<F>(
+    ray: &Ray,
+    transform: &Transform,
+    material: Option<&Arc<Material>>,
+    intervals_local: F,
+) -> Vec<Interval>
+where
+    F: FnOnce(&Ray) -> Vec<Interval>,
+{
+    let mut intervals = intervals_local(&local_ray(ray, transform));
+    for interval in intervals.iter_mut() {
+        for hit in [&mut interval.enter, &mut interval.exit].iter_mut() {
+            if hit.material.is_none() {
+                // End of an infinite interval.
+                continue;
+            }
+            hit.normal = Vec3::normalize(transform.normal(hit.normal));
+            if let Some(material) = material {
+                hit.material = Some(Arc::clone(material));
+            }
+        }
+    }
+    intervals
+}
+
+/*
+    Moves the ray into the space of the transform, intersects it there and brings the hit back.
+    The material replaces the material of the hit when given.
+*/
+fn intersect_transformed<F>(
+    ray: &mut Ray,
+    transform: &Transform,
+    material: Option<&Arc<Material>>,
+    intersect_local: F,
+) where
+    F: FnOnce(&mut Ray),
+{
+    let mut local = local_ray(ray, transform);
+    intersect_local(&mut local);
+
+    if let Some(hit) = local.is_intersected {
+        let outward = if hit.front_face {
+            hit.normal
+        } else {
+            -hit.normal
+        };
+        let material = match material {
+            Some(material) => Arc::clone(material),
+            None => hit.material,
+        };
+        let normal = Vec3::normalize(transform.normal(outward));
+        ray.set_intersection(local.travel_distance, material, normal, hit.uv);
+        if let Some(tangent) = hit.tangent {
+            ray.set_tangent(Vec3::normalize(transform.matrix.transform_vector(tangent)));
+        }
+        if let Some(color) = hit.color {
+            ray.set_color(color);
+        }
+    }
+}
+
+// Runs the intersection of an analytic primitive, which expects the object at the origin.
+fn intersect_primitive<F>(obj: &Object, ray: &mut Ray, intersect_local: F)
+where
+    F: FnOnce(Vec3, Vec3, f64) -> Option<primitive::Hit>,
+{
+    if let Some(hit) = intersect_local(
+        ray.origin - obj.position,
+        ray.direction,
+        ray.travel_distance,
+    ) {
+        ray.set_intersection(hit.t, material(obj), hit.normal, hit.uv);
+    }
+}
+
+fn material(obj: &Object) -> Arc<Material> {
+    Arc::clone(obj.material.as_ref().expect("Object without material"))
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::material::{self, MaterialType};
+    use crate::ray::Ray;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_motion_bounds() {
+        let material = material::new(Vec3::fill(0.5), MaterialType::Lambertian);
+        // A small sphere off the origin, so rotations swing it far between the moments.
+        let ball = new(
+            Vec3(2.0, 0.5, 0.0),
+            ObjectType::Sphere { radius: 0.3 },
+            &material,
+        );
+        let spin = Motion::Transform {
+            start: Trs::identity(),
+            end: Trs {
+                translation: Vec3(0.5, 1.0, 0.0),
+                rotation: Vec3(40.0, 170.0, 90.0),
+                scale: Vec3(1.0, 2.0, 1.5),
+            },
+        };
+        let parented = Motion::Parented {
+            start: Trs {
+                translation: Vec3(1.0, 0.0, 0.0),
+                ..Trs::identity()
+            },
+            end: Trs {
+                rotation: Vec3(0.0, 0.0, 200.0),
+                ..Trs::identity()
+            },
+            motion: Box::new(spin.clone()),
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for motion in [spin, parented].iter() {
+            let moving = with_motion(ball.clone(), motion.clone());
+            let total = bounds(&moving).unwrap();
+            let corners = static_bounds(&ball).unwrap().corners();
+            for _ in 0..1000 {
+                let transform = motion.at(rng.gen());
+                for &corner in corners.iter() {
+                    let p = transform.point(corner);
+                    assert!(
+                        p.0 >= total.min.0
+                            && p.1 >= total.min.1
+                            && p.2 >= total.min.2
+                            && p.0 <= total.max.0
+                            && p.1 <= total.max.1
+                            && p.2 <= total.max.2,
+                        "{:?} is not within {:?}",
+                        p,
+                        total
+                    );
+                }
             }
         }
     }
+
+    #[test]
+    fn test_instance_material() {
+        let red = material::new(Vec3(0.7, 0.1, 0.1), MaterialType::Lambertian);
+        let blue = material::new(Vec3(0.1, 0.1, 0.7), MaterialType::Lambertian);
+        let group = Arc::new(Group::new(vec![new(
+            Vec3::zero(),
+            ObjectType::Sphere { radius: 1.0 },
+            &red,
+        )]));
+        let moved = Transform::trs(Vec3(0.0, 0.0, -5.0), Vec3::zero(), Vec3::fill(1.0));
+
+        // The instanced sphere keeps its material, unless the instance replaces it.
+        for (instance, material) in [
+            (instance(&group, moved), &red),
+            (instance_with_material(&group, moved, &blue), &blue),
+        ]
+        .iter()
+        {
+            let mut ray = Ray::new(Vec3::zero(), Vec3(0.0, 0.0, -1.0));
+            intersect(instance, &mut ray, 0.001);
+            let hit = ray.is_intersected.unwrap();
+            assert!(Arc::ptr_eq(&hit.material, material));
+            assert!((ray.travel_distance - 4.0).abs() < 1e-9);
+        }
+    }
 }
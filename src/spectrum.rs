@@ -0,0 +1,228 @@
+use crate::math::vector::Vec3;
+
+use std::sync::OnceLock;
+
+// Visible range that is sampled in spectral mode, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// Reference wavelength used when a wavelength dependent quantity is evaluated in RGB mode.
+pub const LAMBDA_REFERENCE: f64 = 550.0;
+
+/*
+    Hero wavelength sampling: one wavelength is sampled and two more are placed at equal
+    distances over the visible range. Packed in a Vec3 so the existing RGB throughput math
+    (component wise multiply) carries three wavelengths at once.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Wavelengths {
+    pub lambda: Vec3,
+    // Set once a dispersive event made the path only valid for the hero wavelength.
+    pub secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let rotate = |offset: f64| {
+            let l = LAMBDA_MIN + (u + offset) * range;
+            if l > LAMBDA_MAX {
+                l - range
+            } else {
+                l
+            }
+        };
+
+        Wavelengths {
+            lambda: Vec3(rotate(0.0), rotate(1.0 / 3.0), rotate(2.0 / 3.0)),
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda.0
+    }
+
+    // Returns the weight that has to be applied to the throughput when terminating.
+    pub fn terminate_secondary(&mut self) -> Vec3 {
+        if self.secondary_terminated {
+            return Vec3(1.0, 0.0, 0.0);
+        }
+
+        self.secondary_terminated = true;
+        Vec3(3.0, 0.0, 0.0)
+    }
+}
+
+// Asymmetric gaussian used by the analytic CIE fit.
+fn lobe(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 colour matching functions, multi lobe fit by Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+
+    Vec3(x, y, z)
+}
+
+// XYZ to linear sRGB (D65).
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Vec3 {
+    Vec3(
+        3.240_454_2 * xyz.0 - 1.537_138_5 * xyz.1 - 0.498_531_4 * xyz.2,
+        -0.969_266_0 * xyz.0 + 1.876_010_8 * xyz.1 + 0.041_556_0 * xyz.2,
+        0.055_643_4 * xyz.0 - 0.204_025_9 * xyz.1 + 1.057_225_2 * xyz.2,
+    )
+}
+
+/*
+    Precomputed data for the conversions between RGB and spectra.
+
+    The RGB -> spectrum upsampling uses three box functions (blue, green and red part of the
+    visible range). The matrix maps the box weights to RGB, so inverting it gives the weights
+    for a given albedo. Because the boxes sum to a flat spectrum, white stays white.
+*/
+struct Tables {
+    y_integral: f64,
+    white: Vec3,
+    rgb_to_box: [Vec3; 3],
+}
+
+const BOX_EDGES: [f64; 2] = [490.0, 590.0];
+
+fn box_index(lambda: f64) -> usize {
+    if lambda < BOX_EDGES[0] {
+        0
+    } else if lambda < BOX_EDGES[1] {
+        1
+    } else {
+        2
+    }
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut boxes = [Vec3::zero(); 3];
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            boxes[box_index(lambda)] += cie_xyz(lambda);
+            lambda += 1.0;
+        }
+
+        let y_integral = boxes[0].1 + boxes[1].1 + boxes[2].1;
+        let white = xyz_to_linear_srgb((boxes[0] + boxes[1] + boxes[2]) / y_integral);
+
+        // Columns are the (white balanced) rgb value of each box.
+        let column = |xyz: Vec3| xyz_to_linear_srgb(xyz / y_integral) / white;
+        let (c0, c1, c2) = (column(boxes[0]), column(boxes[1]), column(boxes[2]));
+
+        Tables {
+            y_integral,
+            white,
+            rgb_to_box: invert_columns(c0, c1, c2),
+        }
+    })
+}
+
+// Inverse of the 3x3 matrix with the given columns, returned as rows.
+fn invert_columns(c0: Vec3, c1: Vec3, c2: Vec3) -> [Vec3; 3] {
+    // Rows of the matrix are the columns transposed.
+    let r0 = Vec3(c0.0, c1.0, c2.0);
+    let r1 = Vec3(c0.1, c1.1, c2.1);
+    let r2 = Vec3(c0.2, c1.2, c2.2);
+
+    let det = Vec3::dot(r0, Vec3::cross(r1, r2));
+    let a = Vec3::cross(r1, r2) / det;
+    let b = Vec3::cross(r2, r0) / det;
+    let c = Vec3::cross(r0, r1) / det;
+
+    // a, b and c are the columns of the inverse.
+    [
+        Vec3(a.0, b.0, c.0),
+        Vec3(a.1, b.1, c.1),
+        Vec3(a.2, b.2, c.2),
+    ]
+}
+
+// Evaluate the spectrum of an RGB reflectance (or emission) at the given wavelength.
+pub fn upsample(rgb: Vec3, lambda: f64) -> f64 {
+    let row = tables().rgb_to_box[box_index(lambda)];
+    Vec3::dot(row, rgb).max(0.0)
+}
+
+pub fn upsample_wavelengths(rgb: Vec3, wavelengths: &Wavelengths) -> Vec3 {
+    Vec3(
+        upsample(rgb, wavelengths.lambda.0),
+        upsample(rgb, wavelengths.lambda.1),
+        upsample(rgb, wavelengths.lambda.2),
+    )
+}
+
+// Convert the radiance carried by the three wavelengths into a linear RGB sample.
+pub fn to_rgb(radiance: Vec3, wavelengths: &Wavelengths) -> Vec3 {
+    let t = tables();
+    // Uniform sampling, pdf = 1 / range. Averaged over the three wavelengths.
+    let scale = (LAMBDA_MAX - LAMBDA_MIN) / (3.0 * t.y_integral);
+
+    let xyz = (cie_xyz(wavelengths.lambda.0) * radiance.0
+        + cie_xyz(wavelengths.lambda.1) * radiance.1
+        + cie_xyz(wavelengths.lambda.2) * radiance.2)
+        * scale;
+
+    xyz_to_linear_srgb(xyz) / t.white
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    extern crate assert_approx_eq;
+    use assert_approx_eq::assert_approx_eq;
+
+    const ASSERT_MARGIN: f64 = 0.000001f64;
+
+    #[test]
+    fn test_wavelengths_in_range() {
+        for i in 0..100 {
+            let w = Wavelengths::sample(i as f64 / 100.0);
+            for l in [w.lambda.0, w.lambda.1, w.lambda.2].iter() {
+                assert!(*l >= LAMBDA_MIN && *l <= LAMBDA_MAX);
+            }
+        }
+    }
+
+    #[test]
+    fn test_upsample_round_trip() {
+        let rgb = Vec3(0.4, 0.2, 0.1);
+
+        // Integrate the upsampled spectrum back to rgb.
+        let mut result = Vec3::zero();
+        let steps = 3400;
+        for i in 0..steps {
+            let w = Wavelengths::sample((i as f64 + 0.5) / steps as f64);
+            result += to_rgb(upsample_wavelengths(rgb, &w), &w);
+        }
+        let result = result / steps as f64;
+
+        assert_approx_eq!(result.0, rgb.0, 0.01);
+        assert_approx_eq!(result.1, rgb.1, 0.01);
+        assert_approx_eq!(result.2, rgb.2, 0.01);
+    }
+
+    #[test]
+    fn test_upsample_white_is_flat() {
+        for l in [400.0, 500.0, 600.0, 700.0].iter() {
+            assert_approx_eq!(upsample(Vec3::fill(1.0), *l), 1.0, ASSERT_MARGIN);
+        }
+    }
+}
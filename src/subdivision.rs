@@ -0,0 +1,247 @@
+use super::math::vector::{Vec2, Vec3};
+use super::mesh::Mesh;
+
+use std::collections::HashMap;
+
+/*
+    Polygon control cage for Catmull-Clark subdivision. Faces list their vertices counter
+    clockwise seen from the outside, faces can have any number of vertices. Uvs are optional
+    (one per vertex) and are interpolated linearly instead of smoothed, so texture seams stay
+    where they are.
+*/
+#[derive(Clone)]
+pub struct Cage {
+    pub positions: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn average<T>(
+    items: impl Iterator<Item = T>,
+    zero: T,
+    add: impl Fn(T, T) -> T,
+    div: impl Fn(T, f64) -> T,
+) -> T {
+    let mut count = 0;
+    let sum = items.fold(zero, |sum, item| {
+        count += 1;
+        add(sum, item)
+    });
+    div(sum, count.max(1) as f64)
+}
+
+fn average_vec3(items: impl Iterator<Item = Vec3>) -> Vec3 {
+    average(items, Vec3::zero(), |a, b| a + b, |a, n| a / n)
+}
+
+fn average_vec2(items: impl Iterator<Item = Vec2>) -> Vec2 {
+    average(
+        items,
+        Vec2(0.0, 0.0),
+        |a, b| Vec2(a.0 + b.0, a.1 + b.1),
+        |a, n| Vec2(a.0 / n, a.1 / n),
+    )
+}
+
+impl Cage {
+    /*
+        One step of Catmull-Clark, the result only has quads. New vertices are stored as the
+        moved old vertices, then a point per edge, then a point per face. Edges with only one
+        face are boundaries and are kept sharp with the cubic B-spline curve rule.
+    */
+    pub fn subdivide(&self) -> Cage {
+        let vertex_count = self.positions.len();
+        let has_uvs = self.uvs.len() == vertex_count;
+
+        let face_points: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|face| average_vec3(face.iter().map(|&i| self.positions[i])))
+            .collect();
+
+        // Edges in order of appearance, with the faces on either side.
+        let mut edge_index: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut edges: Vec<((usize, usize), Vec<usize>)> = vec![];
+        for (f, face) in self.faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let key = edge_key(face[i], face[(i + 1) % face.len()]);
+                let index = *edge_index.entry(key).or_insert_with(|| {
+                    edges.push((key, vec![]));
+                    edges.len() - 1
+                });
+                edges[index].1.push(f);
+            }
+        }
+
+        let edge_points: Vec<Vec3> = edges
+            .iter()
+            .map(|&((a, b), ref faces)| {
+                let mid = (self.positions[a] + self.positions[b]) / 2.0;
+                if faces.len() == 2 {
+                    (mid * 2.0 + face_points[faces[0]] + face_points[faces[1]]) / 4.0
+                } else {
+                    mid
+                }
+            })
+            .collect();
+
+        // Faces and edges around every vertex.
+        let mut vertex_faces = vec![vec![]; vertex_count];
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face.iter() {
+                vertex_faces[v].push(f);
+            }
+        }
+        let mut vertex_edges = vec![vec![]; vertex_count];
+        for (e, &((a, b), _)) in edges.iter().enumerate() {
+            vertex_edges[a].push(e);
+            vertex_edges[b].push(e);
+        }
+
+        let mut positions: Vec<Vec3> = (0..vertex_count)
+            .map(|v| {
+                let p = self.positions[v];
+                let boundary: Vec<usize> = vertex_edges[v]
+                    .iter()
+                    .filter(|&&e| edges[e].1.len() != 2)
+                    .map(|&e| {
+                        let (a, b) = edges[e].0;
+                        if a == v {
+                            b
+                        } else {
+                            a
+                        }
+                    })
+                    .collect();
+
+                match boundary.len() {
+                    0 if !vertex_faces[v].is_empty() => {
+                        let n = vertex_faces[v].len() as f64;
+                        let q = average_vec3(vertex_faces[v].iter().map(|&f| face_points[f]));
+                        let r = average_vec3(vertex_edges[v].iter().map(|&e| {
+                            let (a, b) = edges[e].0;
+                            (self.positions[a] + self.positions[b]) / 2.0
+                        }));
+                        (q + r * 2.0 + p * (n - 3.0)) / n
+                    }
+                    2 => {
+                        (p * 6.0 + self.positions[boundary[0]] + self.positions[boundary[1]]) / 8.0
+                    }
+                    // Corners and non manifold vertices stay in place.
+                    _ => p,
+                }
+            })
+            .collect();
+        positions.extend(edge_points);
+        positions.extend(face_points);
+
+        let uvs = if has_uvs {
+            let mut uvs = self.uvs.clone();
+            uvs.extend(
+                edges
+                    .iter()
+                    .map(|&((a, b), _)| average_vec2([self.uvs[a], self.uvs[b]].iter().copied())),
+            );
+            uvs.extend(
+                self.faces
+                    .iter()
+                    .map(|face| average_vec2(face.iter().map(|&i| self.uvs[i]))),
+            );
+            uvs
+        } else {
+            vec![]
+        };
+
+        let edge_vertex = |a: usize, b: usize| vertex_count + edge_index[&edge_key(a, b)];
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+        for (f, face) in self.faces.iter().enumerate() {
+            let face_vertex = vertex_count + edges.len() + f;
+            let n = face.len();
+            for i in 0..n {
+                let prev = face[(i + n - 1) % n];
+                let current = face[i];
+                let next = face[(i + 1) % n];
+                faces.push(vec![
+                    current,
+                    edge_vertex(current, next),
+                    face_vertex,
+                    edge_vertex(prev, current),
+                ]);
+            }
+        }
+
+        Cage {
+            positions,
+            uvs,
+            faces,
+        }
+    }
+
+    // Subdivides the cage the number of times and turns it into a smooth triangle mesh.
+    pub fn to_mesh(&self, levels: usize) -> Mesh {
+        let cage = (0..levels).fold(self.clone(), |cage, _| cage.subdivide());
+        let triangles = cage
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+        Mesh::smooth(cage.positions, cage.uvs, triangles)
+    }
+}
+
+// Closed box around the origin, a starting point for subdivision modelling.
+pub fn cube(half_size: Vec3) -> Cage {
+    let Vec3(x, y, z) = half_size;
+    Cage {
+        positions: vec![
+            Vec3(-x, -y, -z),
+            Vec3(x, -y, -z),
+            Vec3(x, y, -z),
+            Vec3(-x, y, -z),
+            Vec3(-x, -y, z),
+            Vec3(x, -y, z),
+            Vec3(x, y, z),
+            Vec3(-x, y, z),
+        ],
+        uvs: vec![],
+        faces: vec![
+            vec![0, 3, 2, 1],
+            vec![4, 5, 6, 7],
+            vec![0, 1, 5, 4],
+            vec![3, 7, 6, 2],
+            vec![0, 4, 7, 3],
+            vec![1, 2, 6, 5],
+        ],
+    }
+}
+
+/***
+ *  Tests
+***/
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::math::vector::Vector;
+
+    #[test]
+    fn test_subdivide_cube() {
+        let cage = cube(Vec3::fill(1.0)).subdivide();
+        // 8 vertices, 12 edges and 6 faces become the vertices of 24 quads.
+        assert_eq!(cage.positions.len(), 26);
+        assert_eq!(cage.faces.len(), 24);
+
+        // Corners move inwards, faces stay in place.
+        assert!(cage.positions[0].length() < 3f64.sqrt());
+        assert_eq!(cage.positions[25], Vec3(1.0, 0.0, 0.0));
+
+        // The surface converges within the cage and stays closed.
+        let mesh = cube(Vec3::fill(1.0)).to_mesh(3);
+        assert_eq!(mesh.triangles.len(), 6 * 64 * 2);
+        assert!(mesh.positions.iter().all(|p| p.length() < 3f64.sqrt()));
+    }
+}
@@ -0,0 +1,87 @@
+use super::math::vector::{Vec2, Vec3};
+
+use std::io::{Error, Read};
+
+extern crate png;
+
+// sRGB encoded value (0 to 1) to linear.
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/*
+    Color image sampled with the uvs of the surface, stored as linear RGB. Uvs wrap around and
+    v goes up in the image, like the height maps for displacement.
+*/
+pub struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> Texture {
+        assert_eq!(pixels.len(), width * height, "Texture size does not match");
+        Texture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Decodes a png image (any bit depth or color type) with sRGB colors, alpha is ignored.
+    pub fn decode_png<R: Read>(data: R) -> Result<Texture, Error> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buffer = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buffer)?;
+
+        let (width, height) = (info.width as usize, info.height as usize);
+        let channels = info.line_size / width;
+        let pixels = buffer
+            .chunks(info.line_size)
+            .flat_map(|line| line.chunks(channels).take(width))
+            .map(|pixel| {
+                let value = |i: usize| srgb_to_linear(pixel[i.min(channels - 1)] as f64 / 255.0);
+                // Gray images have one channel (two with alpha).
+                if channels < 3 {
+                    Vec3::fill(value(0))
+                } else {
+                    Vec3(value(0), value(1), value(2))
+                }
+            })
+            .collect();
+        Ok(Texture::new(width, height, pixels))
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // Pixel in linear RGB, y goes down from the top of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    // Bilinear sample.
+    pub fn sample(&self, uv: Vec2) -> Vec3 {
+        let x = uv.0.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - uv.1.rem_euclid(1.0)) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let pixel = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.pixel(x, y)
+        };
+        let top = pixel(x0, y0) * (1.0 - tx) + pixel(x0 + 1.0, y0) * tx;
+        let bottom = pixel(x0, y0 + 1.0) * (1.0 - tx) + pixel(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
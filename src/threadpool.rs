@@ -1,10 +1,10 @@
+use super::film::Film;
 use super::scene::Scene;
-use std::collections::HashMap;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
-type Coordinate = (usize, usize, usize);
-type Job = Box<dyn Fn(&Scene, (usize, usize, usize)) -> (u8, u8, u8) + Send + 'static>;
+type Coordinate = (usize, usize);
+type Job = Box<dyn Fn(&Scene, Coordinate) -> Film + Send + 'static>;
 
 pub enum Message {
     NewJob(Coordinate, Job),
@@ -14,6 +14,8 @@ pub enum Message {
 pub struct ThreadPool {
     pub workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
+    // Film tiles of finished jobs with their coordinate, in the order they finish.
+    pub results: mpsc::Receiver<(Coordinate, Film)>,
 }
 
 /* Thread pool to distribute the calculations over threads */
@@ -24,19 +26,29 @@ impl ThreadPool {
 
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
+        let (result_sender, results) = mpsc::channel();
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver), scene.clone()));
+            workers.push(Worker::new(
+                id,
+                Arc::clone(&receiver),
+                result_sender.clone(),
+                scene.clone(),
+            ));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers,
+            sender,
+            results,
+        }
     }
 
     pub fn schedule<F>(&self, coordinate: Coordinate, f: F)
     where
-        F: Fn(&Scene, Coordinate) -> (u8, u8, u8) + Send + 'static,
+        F: Fn(&Scene, Coordinate) -> Film + Send + 'static,
     {
         let job = Box::new(f);
 
@@ -62,27 +74,25 @@ impl ThreadPool {
 pub struct Worker {
     pub id: usize,
     pub handle: Option<thread::JoinHandle<()>>,
-    pub results: Arc<Mutex<HashMap<usize, (u8, u8, u8)>>>, // index -> (r, g, b)
 }
 
 impl Worker {
     pub fn new(
         id: usize,
         receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+        results: mpsc::Sender<(Coordinate, Film)>,
         arc_scene: Scene,
     ) -> Worker {
         // Create references
-        let results_map = Arc::new(Mutex::new(HashMap::new()));
         let scene = arc_scene.clone();
-        let map = Arc::clone(&results_map);
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv().unwrap();
             match message {
                 Message::NewJob(coordinate, job) => {
-                    let (r, g, b) = job(&scene, (coordinate.0, coordinate.1, coordinate.2));
+                    let tile = job(&scene, coordinate);
 
-                    // Add result to map.
-                    map.lock().unwrap().insert(coordinate.2, (r, g, b));
+                    // Send the result to the pool, it is merged while other jobs run.
+                    results.send((coordinate, tile)).unwrap();
                 }
                 Message::Terminate => {
                     break;
@@ -93,7 +103,6 @@ impl Worker {
         Worker {
             id,
             handle: Some(thread),
-            results: Arc::clone(&results_map),
         }
     }
 }